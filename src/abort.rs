@@ -0,0 +1,68 @@
+/* Print a message and end the simulation with a failure
+ *
+ * htif_abort!() prints its message and where it was called from to the host
+ * console, then asks the host to exit with ABORT_EXIT_CODE, without
+ * unwinding or going through the panic handler. htif_assert!() does the
+ * same if its condition is false, like assert!():
+ *
+ * htif_assert!(harts > 0, "no harts found in {:?}", dtb);
+ * htif_abort!("unexpected trap {}", cause);
+ *
+ * As any hart may be holding the global console when things go wrong, these
+ * don't wait for it, but use the linker-defined registers directly, as with
+ * HTIF::steal(). Requests to the host aren't interleaved, though lines of
+ * output may be. HTIF::abort() does the same through a given driver.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::fmt::{self, Write};
+use super::HTIF;
+use super::transport::HtifTransport;
+
+/* exit code reported to the host on abort */
+pub const ABORT_EXIT_CODE: u32 = 1;
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* print the given message, such as abort(format_args!(...)), on a line of its own,
+       and end the simulation with ABORT_EXIT_CODE. output is lost if the host can't
+       take it, but the exit is still requested */
+    pub fn abort(&mut self, args: fmt::Arguments) -> !
+    {
+        let _ = writeln!(self, "{}", args);
+        self.exit(ABORT_EXIT_CODE)
+    }
+}
+
+/* used by htif_abort!() and htif_assert!() */
+#[doc(hidden)]
+pub fn _abort(file: &str, line: u32, args: fmt::Arguments) -> !
+{
+    let mut htif = unsafe { HTIF::steal() };
+    htif.abort(format_args!("aborted at {}:{}: {}", file, line, args))
+}
+
+/* print a message and end the simulation with a failure */
+#[macro_export]
+macro_rules! htif_abort
+{
+    () => ($crate::abort::_abort(file!(), line!(), format_args!("explicit abort")));
+    ($($arg:tt)+) => ($crate::abort::_abort(file!(), line!(), format_args!($($arg)+)));
+}
+
+/* abort with a message if the condition is false */
+#[macro_export]
+macro_rules! htif_assert
+{
+    ($cond:expr $(,)?) => (if !$cond
+    {
+        $crate::htif_abort!("assertion failed: {}", stringify!($cond))
+    });
+    ($cond:expr, $($arg:tt)+) => (if !$cond
+    {
+        $crate::htif_abort!("assertion failed: {}: {}", stringify!($cond), format_args!($($arg)+))
+    });
+}
@@ -0,0 +1,84 @@
+/* ANSI escape sequences for styling host console output
+ *
+ * These only have an effect if the host's terminal understands them,
+ * which nearly all do. They're free functions, as ansi::reset() would
+ * otherwise be confused with HTIF::reset(), which resets the host.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+
+const ESCAPE: u8 = 0x1b;
+
+/* the eight standard terminal colors, numbered as ANSI numbers them */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color
+{
+    Black = 0,
+    Red = 1,
+    Green = 2,
+    Yellow = 3,
+    Blue = 4,
+    Magenta = 5,
+    Cyan = 6,
+    White = 7
+}
+
+/* build the select graphic rendition sequence ESC [ <code> m from a one or two digit code */
+fn sgr(code: u8) -> ([u8; 5], usize)
+{
+    match code
+    {
+        0..=9 => ([ESCAPE, b'[', b'0' + code, b'm', 0], 4),
+        _ => ([ESCAPE, b'[', b'0' + code / 10, b'0' + code % 10, b'm'], 5)
+    }
+}
+
+/* print subsequent text in the given foreground color */
+pub fn set_color(htif: &HTIF<impl HtifTransport>, color: Color) -> Result<(), Fault>
+{
+    send_sgr(htif, 30 + color as u8)
+}
+
+/* print subsequent text in bold */
+pub fn bold(htif: &HTIF<impl HtifTransport>) -> Result<(), Fault>
+{
+    send_sgr(htif, 1)
+}
+
+/* return to plain text, undoing set_color() and bold() */
+pub fn reset(htif: &HTIF<impl HtifTransport>) -> Result<(), Fault>
+{
+    send_sgr(htif, 0)
+}
+
+/* erase the current line and return the cursor to its start */
+pub fn clear_line(htif: &HTIF<impl HtifTransport>) -> Result<(), Fault>
+{
+    htif.send_bytes(&[b'\r', ESCAPE, b'[', b'2', b'K'])
+}
+
+fn send_sgr(htif: &HTIF<impl HtifTransport>, code: u8) -> Result<(), Fault>
+{
+    let (sequence, len) = sgr(code);
+    htif.send_bytes(&sequence[..len])
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn sequences()
+    {
+        let (sequence, len) = sgr(0);
+        assert_eq!(&sequence[..len], b"\x1b[0m");
+        let (sequence, len) = sgr(30 + Color::Cyan as u8);
+        assert_eq!(&sequence[..len], b"\x1b[36m");
+    }
+}
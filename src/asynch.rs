@@ -0,0 +1,98 @@
+/* async access to the host console
+ *
+ * The poll_ functions make a single non-blocking attempt, and can be driven by
+ * any executor, or wrapped in a future by hand. The async functions do exactly
+ * that. Neither ever blocks the hart: while the host isn't ready, the task asks
+ * to be polled again and lets the executor run something else meanwhile.
+ * In interrupt-driven mode, a task waiting on a reply instead sleeps until
+ * handle_irq() wakes it. There's no interrupt for the host taking a request
+ * from tohost, so writers always ask to be polled again.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::future::poll_fn;
+use core::task::{Context, Poll};
+use super::{HTIF, Fault, COMMAND_WRITE_CHAR};
+use super::protocol::encode_command;
+use super::transport::HtifTransport;
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* return a byte from the host console if one has arrived, or Pending if not */
+    pub fn poll_read_byte(&mut self, cx: &mut Context<'_>) -> Poll<Result<u8, Fault>>
+    {
+        /* register before looking, so that a reply gathered in between isn't missed */
+        if self.irq_enabled
+        {
+            self.register_waker(cx.waker());
+        }
+
+        match self.try_read_byte()
+        {
+            Ok(Some(byte)) => Poll::Ready(Ok(byte)),
+            Ok(None) => self.pending_reply(cx),
+            Err(e) => Poll::Ready(Err(e))
+        }
+    }
+
+    /* start writing a byte to the host console, returning Ready once the host has
+       room for it and Pending if not. the byte may still be waiting in tohost after
+       this, so use poll_flush() to await the host taking it. no newline translation
+       is done */
+    pub fn poll_send_byte(&mut self, cx: &mut Context<'_>, byte: u8) -> Poll<Result<(), Fault>>
+    {
+        if self.read_to_host() != 0
+        {
+            return self.pending(cx);
+        }
+
+        self.write_to_host(encode_command(self.console, COMMAND_WRITE_CHAR as u8, byte as u64))?;
+        self.count(|stats| stats.bytes_sent = stats.bytes_sent.wrapping_add(1));
+        Poll::Ready(Ok(()))
+    }
+
+    /* return Ready once the host has taken the last request, or Pending if not */
+    pub fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Fault>>
+    {
+        match self.read_to_host()
+        {
+            0 => Poll::Ready(Ok(())),
+            _ => self.pending(cx)
+        }
+    }
+
+    /* wait for a byte from the host console, and return it */
+    pub async fn read_byte_async(&mut self) -> Result<u8, Fault>
+    {
+        poll_fn(|cx| self.poll_read_byte(cx)).await
+    }
+
+    /* write a byte to the host console, and wait for the host to take it */
+    pub async fn send_byte_async(&mut self, byte: u8) -> Result<(), Fault>
+    {
+        poll_fn(|cx| self.poll_send_byte(cx, byte)).await?;
+        poll_fn(|cx| self.poll_flush(cx)).await
+    }
+
+    /* nothing will wake the task when the host takes a request, so ask to be
+       polled again straight away */
+    fn pending<R>(&self, cx: &mut Context<'_>) -> Poll<R>
+    {
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+
+    /* wait for a reply. if interrupt-driven, the caller must already have
+       registered the task's waker, and the task sleeps until handle_irq() wakes it */
+    fn pending_reply<R>(&self, cx: &mut Context<'_>) -> Poll<R>
+    {
+        match self.irq_enabled
+        {
+            true => Poll::Pending,
+            false => self.pending(cx)
+        }
+    }
+}
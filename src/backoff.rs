@@ -0,0 +1,92 @@
+/* Pausing between polls of the host
+ *
+ * While waiting on the host, the driver re-reads tohost or fromhost, pausing
+ * between reads as its Backoff says. The right trade-off differs between hosts:
+ * Spike services HTIF between instructions, so polling hard costs little, whereas
+ * an FPGA's fesvr bridge is slow to reply, and each read may cross a bus it shares.
+ * A timeout counts polls whatever the backoff, so slower backoffs take longer
+ * to time out.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+/* longest pause, in spins, an exponential backoff can be asked for */
+const MAX_SPINS: u32 = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff
+{
+    None, /* poll again straight away */
+    Fixed(u32), /* spin the given number of times between polls */
+    Exponential(u32), /* spin once after the first poll, and twice as long after each one after it, up to the given number of spins */
+    Wfi /* wait for an interrupt between polls, such as a timer tick. an interrupt must be enabled, or the hart may never wake */
+}
+
+/* spinning once between polls is what the driver has always done */
+impl Default for Backoff
+{
+    fn default() -> Self
+    {
+        Backoff::DEFAULT
+    }
+}
+
+impl Backoff
+{
+    pub const DEFAULT: Backoff = Backoff::Fixed(1);
+
+    /* pause after the given number of polls, starting from zero, have failed to find the host ready */
+    pub(crate) fn pause(self, polls: u64)
+    {
+        match self
+        {
+            Backoff::None => (),
+            Backoff::Fixed(spins) => spin(spins),
+            Backoff::Exponential(limit) => spin(exponential_spins(polls, limit)),
+            Backoff::Wfi => wait_for_interrupt()
+        }
+    }
+}
+
+/* return how long to spin after the given number of polls, doubling from one */
+fn exponential_spins(polls: u64, limit: u32) -> u32
+{
+    (1u32 << polls.min(31)).min(limit).min(MAX_SPINS)
+}
+
+fn spin(spins: u32)
+{
+    for _ in 0..spins
+    {
+        core::hint::spin_loop();
+    }
+}
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+fn wait_for_interrupt()
+{
+    unsafe { core::arch::asm!("wfi", options(nomem, nostack)) }
+}
+
+/* nothing to wait for off RISC-V, such as in host-side tests */
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+fn wait_for_interrupt()
+{
+    core::hint::spin_loop();
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn exponential()
+    {
+        let spins: Vec<u32> = (0..6).map(|polls| exponential_spins(polls, 8)).collect();
+        assert_eq!(spins, [1, 2, 4, 8, 8, 8]);
+        assert_eq!(exponential_spins(1000, u32::MAX), MAX_SPINS);
+    }
+}
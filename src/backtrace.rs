@@ -0,0 +1,146 @@
+/* Print a stack backtrace by walking frame pointers
+ *
+ * Enabled by the backtrace feature. Given where to start, print_backtrace()
+ * lists the return address of each frame on the stack, such as from a panic
+ * or trap handler, so that the trace can be symbolized on the host with
+ * addr2line -e kernel.elf:
+ *
+ * backtrace:
+ *   0: 0x0000000080002a4c
+ *   1: 0x00000000800011f0
+ *
+ * This relies on the code being built with frame pointers, as with
+ * -C force-frame-pointers=yes, so that each frame keeps the caller's return
+ * address one word below where its frame pointer points, and the caller's
+ * frame pointer a word below that, as the RISC-V calling convention lays out.
+ * The walk stops at a null or misaligned frame pointer, or one that doesn't
+ * move up the stack, or after MAX_FRAMES frames, but pointers that lead
+ * outside the stack can't be detected, as the driver doesn't know where it is.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::mem::size_of;
+use core::ptr::read_volatile;
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+
+/* the most frames printed, in case the frame pointers loop */
+pub const MAX_FRAMES: usize = 64;
+
+const WORD: usize = size_of::<usize>();
+
+/* the return addresses of the frames on a stack, innermost first */
+pub struct Frames
+{
+    ra: Option<usize>, /* the next return address to give out, if not read from a frame */
+    fp: usize, /* the frame to read the next return address from */
+    depth: usize
+}
+
+impl Frames
+{
+    /* walk the stack from the frame pointed to by fp, starting with the return
+       address ra, if given, such as the ra or mepc register a trap saved. this is
+       unsafe as the frame pointers must point into valid memory */
+    pub unsafe fn new(fp: usize, ra: Option<usize>) -> Self
+    {
+        Frames { ra, fp, depth: 0 }
+    }
+
+    /* walk the stack from the caller's frame. only available on RISC-V */
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    #[inline(always)]
+    pub unsafe fn here() -> Self
+    {
+        let fp: usize;
+        core::arch::asm!("mv {}, s0", out(reg) fp, options(nomem, nostack));
+        Frames::new(fp, None)
+    }
+}
+
+impl Iterator for Frames
+{
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize>
+    {
+        if self.depth == MAX_FRAMES
+        {
+            return None;
+        }
+        self.depth += 1;
+
+        if let Some(ra) = self.ra.take()
+        {
+            return Some(ra);
+        }
+
+        if self.fp < 2 * WORD || self.fp & (WORD - 1) != 0
+        {
+            return None;
+        }
+
+        /* safe as long as new()'s frame pointers are valid */
+        let (ra, caller) = unsafe
+        {
+            let frame = self.fp as *const usize;
+            (read_volatile(frame.sub(1)), read_volatile(frame.sub(2)))
+        };
+
+        /* the stack grows down, so each caller's frame is above its callee's */
+        self.fp = match caller > self.fp
+        {
+            true => caller,
+            false => 0
+        };
+
+        match ra
+        {
+            0 => None,
+            ra => Some(ra)
+        }
+    }
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* print the return addresses of the given frames to the host console, one per line */
+    pub fn print_backtrace(&self, frames: Frames) -> Result<(), Fault>
+    {
+        self.send_bytes(b"backtrace:\n")?;
+        for (index, ra) in frames.enumerate()
+        {
+            self.send_bytes(b"  ")?;
+            self.write_u64_dec(index as u64)?;
+            self.send_bytes(b": ")?;
+            self.write_ptr(ra as *const u8)?;
+            self.send_bytes(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn walk()
+    {
+        /* two frames, the outer one the last, each with its return address and caller's frame pointer below it */
+        let mut stack = [0usize; 8];
+        let base = stack.as_ptr() as usize;
+        stack[2] = base + 8 * WORD;
+        stack[3] = 0x8000_1000;
+        stack[6] = 0;
+        stack[7] = 0x8000_2000;
+
+        let inner = stack.as_ptr() as usize + 4 * WORD;
+        let frames: Vec<usize> = unsafe { Frames::new(inner, Some(0x8000_0004)) }.collect();
+        assert_eq!(frames, [0x8000_0004, 0x8000_1000, 0x8000_2000]);
+    }
+}
@@ -0,0 +1,258 @@
+/* Base64 streams over the host console
+ *
+ * The console is a text stream, and a host terminal or script may mangle
+ * control characters, so binary data such as core dumps and profiles are
+ * better sent as base64. Base64Writer encodes bytes as they're written, in
+ * lines of LINE_LEN characters, and Base64Reader decodes them as they're read:
+ *
+ * let mut out = Base64Writer::new(&htif);
+ * out.write(&profile)?;
+ * out.finish()?;
+ *
+ * and off the host, base64 -d recovers the data. The reader skips whitespace,
+ * and ends its stream at the padding, or at the first byte that's neither base64
+ * nor whitespace, such as a '.' the sender puts on a line of its own.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::transport::{HtifTransport, Mmio};
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const PAD: u8 = b'=';
+
+/* characters per line of encoded output, as MIME uses */
+pub const LINE_LEN: usize = 76;
+
+/* encode up to three bytes as four characters, padding if there are fewer than three */
+fn encode(bytes: &[u8]) -> [u8; 4]
+{
+    let byte = |index: usize| *bytes.get(index).unwrap_or(&0) as u32;
+    let group = (byte(0) << 16) | (byte(1) << 8) | byte(2);
+
+    let mut quad = [PAD; 4];
+    for (index, c) in quad.iter_mut().enumerate().take(bytes.len() + 1)
+    {
+        *c = ALPHABET[((group >> (18 - 6 * index)) & 0x3f) as usize];
+    }
+    quad
+}
+
+/* return the six bits the given character stands for, or None if it isn't base64 */
+fn decode_char(c: u8) -> Option<u32>
+{
+    let value = match c
+    {
+        b'A'..=b'Z' => c - b'A',
+        b'a'..=b'z' => c - b'a' + 26,
+        b'0'..=b'9' => c - b'0' + 52,
+        b'+' => 62,
+        b'/' => 63,
+        _ => return None
+    };
+    Some(value as u32)
+}
+
+/* encodes bytes to the console as base64 */
+pub struct Base64Writer<'a, T = Mmio>
+{
+    htif: &'a HTIF<T>,
+    pending: [u8; 3], /* bytes waiting for a group of three to be made up */
+    len: usize, /* number of bytes pending */
+    column: usize /* characters on the current line */
+}
+
+impl<'a, T: HtifTransport> Base64Writer<'a, T>
+{
+    /* write base64 to the given console */
+    pub fn new(htif: &'a HTIF<T>) -> Self
+    {
+        Base64Writer { htif, pending: [0; 3], len: 0, column: 0 }
+    }
+
+    /* encode the given bytes. the last one or two may be held until more are written */
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), Fault>
+    {
+        for byte in bytes
+        {
+            self.pending[self.len] = *byte;
+            self.len += 1;
+            if self.len == self.pending.len()
+            {
+                self.len = 0;
+                self.emit(encode(&self.pending))?;
+            }
+        }
+        Ok(())
+    }
+
+    /* encode whatever's held, with padding, and end the last line */
+    pub fn finish(mut self) -> Result<(), Fault>
+    {
+        if self.len > 0
+        {
+            let quad = encode(&self.pending[..self.len]);
+            self.emit(quad)?;
+        }
+
+        match self.column
+        {
+            0 => Ok(()),
+            _ => self.htif.send_bytes(b"\n")
+        }
+    }
+
+    fn emit(&mut self, quad: [u8; 4]) -> Result<(), Fault>
+    {
+        self.htif.send_bytes(&quad)?;
+        self.column += quad.len();
+        if self.column == LINE_LEN
+        {
+            self.column = 0;
+            self.htif.send_bytes(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+/* decodes base64 from the console */
+pub struct Base64Reader<'a, T = Mmio>
+{
+    htif: &'a mut HTIF<T>,
+    decoded: [u8; 3], /* bytes decoded but not yet read */
+    start: usize, /* index of the next of them to be read */
+    end: usize, /* index after the last of them */
+    ended: bool /* true once the end of the stream has been seen */
+}
+
+impl<'a, T: HtifTransport> Base64Reader<'a, T>
+{
+    /* read base64 from the given console. characters read aren't echoed */
+    pub fn new(htif: &'a mut HTIF<T>) -> Self
+    {
+        Base64Reader { htif, decoded: [0; 3], start: 0, end: 0, ended: false }
+    }
+
+    /* decode bytes into buf, blocking until it's full or the stream ends. returns the
+       number of bytes decoded, which is less than buf.len() only at the end of the
+       stream. fails with InvalidResponse if the stream is malformed */
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Fault>
+    {
+        let mut read = 0;
+        while read < buf.len()
+        {
+            if self.start == self.end
+            {
+                if self.ended
+                {
+                    break;
+                }
+                self.decode_group()?;
+                continue;
+            }
+
+            buf[read] = self.decoded[self.start];
+            self.start += 1;
+            read += 1;
+        }
+        Ok(read)
+    }
+
+    /* read and decode the next group of four characters */
+    fn decode_group(&mut self) -> Result<(), Fault>
+    {
+        let mut group = 0;
+        let mut chars = 0;
+        while chars < 4
+        {
+            let c = self.htif.take_byte()?;
+            match decode_char(c)
+            {
+                Some(bits) =>
+                {
+                    group |= bits << (18 - 6 * chars);
+                    chars += 1;
+                },
+                None if c.is_ascii_whitespace() => (),
+                None =>
+                {
+                    /* a group of two characters is padded with two PADs. take the second */
+                    if c == PAD && chars == 2
+                    {
+                        self.htif.take_byte()?;
+                    }
+                    self.ended = true;
+                    break;
+                }
+            }
+        }
+
+        /* four characters make three bytes, three make two, and two make one */
+        let len = match chars
+        {
+            0 => 0,
+            1 => return Err(Fault::InvalidResponse),
+            chars => chars - 1
+        };
+
+        for (index, byte) in self.decoded.iter_mut().enumerate()
+        {
+            *byte = (group >> (16 - 8 * index)) as u8;
+        }
+        self.start = 0;
+        self.end = len;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::fesvr::Fesvr;
+
+    #[test]
+    fn round_trip()
+    {
+        let mut htif = HTIF::over(Fesvr::<512>::new());
+        let data: Vec<u8> = (0..=255).collect();
+
+        let mut out = Base64Writer::new(&htif);
+        out.write(&data[..100]).unwrap();
+        out.write(&data[100..]).unwrap();
+        out.finish().unwrap();
+
+        let encoded = htif.transport().output().to_vec();
+        assert!(encoded.starts_with(b"AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4\n"));
+        assert!(encoded.ends_with(b"+/w==\n"));
+
+        htif.transport().type_input(&encoded);
+        let mut decoded = vec![0; 300];
+        let mut len = 0;
+        let mut reader = Base64Reader::new(&mut htif);
+        loop
+        {
+            match reader.read(&mut decoded[len..]).unwrap()
+            {
+                0 => break,
+                read => len += read
+            }
+        }
+        assert_eq!(&decoded[..len], &data[..]);
+    }
+
+    #[test]
+    fn unpadded()
+    {
+        let mut htif = HTIF::over(Fesvr::<64>::new());
+        htif.transport().type_input(b"aGk\n.\n");
+        let mut buf = [0; 4];
+        let mut reader = Base64Reader::new(&mut htif);
+        assert_eq!(reader.read(&mut buf), Ok(2));
+        assert_eq!(&buf[..2], b"hi");
+        assert_eq!(reader.read(&mut buf), Ok(0));
+    }
+}
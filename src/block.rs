@@ -0,0 +1,119 @@
+/* Access a disk image attached to Spike with +disk=<file> via its HTIF block device
+ *
+ * The host identifies the device as "disk size=<bytes>". Reads and writes are
+ * described by a request in target memory whose address is passed to the host,
+ * which copies data directly to or from the given buffer and replies with the
+ * request's tag. As with all host-accessed memory, the request and data buffers
+ * must be at physical addresses.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::protocol::encode_command;
+use super::transport::HtifTransport;
+
+/* disks are accessed in whole sectors of this many bytes */
+pub const SECTOR_SIZE: usize = 512;
+
+const COMMAND_READ:  u64 = 0; /* copy from the disk into target memory */
+const COMMAND_WRITE: u64 = 1; /* copy from target memory to the disk */
+
+/* the host's identity for disk devices, followed by the disk's size in bytes */
+const IDENTITY_PREFIX: &str = "disk size=";
+
+/* layout of a read or write request, as expected by the host */
+#[repr(C, align(8))]
+struct Request
+{
+    addr: u64,   /* physical address of the data buffer */
+    offset: u64, /* byte offset into the disk */
+    size: u64,   /* number of bytes to transfer */
+    tag: u64     /* value the host replies with when done */
+}
+
+/* a disk provided by the host */
+#[derive(Debug)]
+pub struct BlockDevice
+{
+    device: u8,
+    capacity: u64
+}
+
+impl BlockDevice
+{
+    /* use the disk at the given HTIF device number. fails with NotPresent if
+       the host doesn't provide a disk there. Spike attaches disks from device 2 */
+    pub fn new(htif: &mut HTIF<impl HtifTransport>, device: u8) -> Result<Self, Fault>
+    {
+        let identity = htif.identify(device)?;
+        let capacity = match identity.as_str().strip_prefix(IDENTITY_PREFIX)
+        {
+            Some(size) => size.parse::<u64>().map_err(|_| Fault::NotPresent)?,
+            None => return Err(Fault::NotPresent)
+        };
+
+        Ok(BlockDevice { device, capacity })
+    }
+
+    /* use the first disk provided by the host, if any */
+    pub fn find(htif: &mut HTIF<impl HtifTransport>) -> Result<Self, Fault>
+    {
+        match htif.find_device(IDENTITY_PREFIX)?
+        {
+            Some(dev) => BlockDevice::new(htif, dev.number),
+            None => Err(Fault::NotPresent)
+        }
+    }
+
+    /* return the disk's size in bytes */
+    pub fn capacity(&self) -> u64
+    {
+        self.capacity
+    }
+
+    /* return the number of whole sectors on the disk */
+    pub fn sectors(&self) -> u64
+    {
+        self.capacity / SECTOR_SIZE as u64
+    }
+
+    /* copy the given sector from the disk into buf */
+    pub fn read_sector(&self, htif: &mut HTIF<impl HtifTransport>, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), Fault>
+    {
+        self.transfer(htif, COMMAND_READ, sector, buf.as_mut_ptr() as u64)
+    }
+
+    /* copy buf to the given sector on the disk */
+    pub fn write_sector(&self, htif: &mut HTIF<impl HtifTransport>, sector: u64, buf: &[u8; SECTOR_SIZE]) -> Result<(), Fault>
+    {
+        self.transfer(htif, COMMAND_WRITE, sector, buf.as_ptr() as u64)
+    }
+
+    /* have the host carry out a one-sector read or write command using the buffer at addr */
+    fn transfer(&self, htif: &mut HTIF<impl HtifTransport>, command: u64, sector: u64, addr: u64) -> Result<(), Fault>
+    {
+        /* the host gives up on the whole simulation if asked to go beyond
+           the end of the disk, so catch that here */
+        if sector >= self.sectors()
+        {
+            return Err(Fault::OutOfRange);
+        }
+
+        let request = Request
+        {
+            addr,
+            offset: sector * SECTOR_SIZE as u64,
+            size: SECTOR_SIZE as u64,
+            tag: sector
+        };
+
+        match htif.transact(encode_command(self.device, command as u8, &request as *const Request as u64))?.payload == request.tag
+        {
+            true => Ok(()),
+            false => Err(Fault::InvalidResponse)
+        }
+    }
+}
@@ -0,0 +1,127 @@
+/* buffered output to the host console
+ *
+ * Collecting a line before sending it means other code, such as another hart
+ * waiting on a lock around the console, isn't held up while a line is formatted.
+ * Buffered output is sent at each newline, when the buffer fills, when flush()
+ * is called, and when the writer is dropped, through write_all(). So with bulk
+ * output on, see bulk.rs, each of those costs one handshake with the host rather
+ * than one per byte.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::transport::{HtifTransport, Mmio};
+
+pub struct BufferedWriter<'a, const N: usize, T: HtifTransport = Mmio>
+{
+    htif: &'a mut HTIF<T>,
+    buffer: [u8; N],
+    len: usize /* number of bytes in buffer waiting to be sent */
+}
+
+impl<'a, const N: usize, T: HtifTransport> BufferedWriter<'a, N, T>
+{
+    /* buffer up to N bytes of output before sending it to the given console */
+    pub fn new(htif: &'a mut HTIF<T>) -> Self
+    {
+        BufferedWriter { htif, buffer: [0; N], len: 0 }
+    }
+
+    /* add bytes to the buffer, sending the buffer on to the host at each newline
+       and whenever it fills. on failure, bytes from the failed one onwards aren't taken */
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), Fault>
+    {
+        /* a zero-sized buffer can't hold anything, so send straight through */
+        if N == 0
+        {
+            return self.htif.write_all(bytes).map_err(|e| e.fault);
+        }
+
+        for byte in bytes
+        {
+            if self.len == N
+            {
+                self.flush()?;
+            }
+
+            self.buffer[self.len] = *byte;
+            self.len += 1;
+
+            if *byte == b'\n'
+            {
+                self.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /* send everything buffered to the host. on failure, what the host
+       didn't take remains buffered, to be tried again at the next flush */
+    pub fn flush(&mut self) -> Result<(), Fault>
+    {
+        match self.htif.write_all(&self.buffer[..self.len])
+        {
+            Ok(()) =>
+            {
+                self.len = 0;
+                Ok(())
+            },
+            Err(e) =>
+            {
+                self.buffer.copy_within(e.written..self.len, 0);
+                self.len -= e.written;
+                Err(e.fault)
+            }
+        }
+    }
+
+    /* return the number of bytes waiting to be sent */
+    pub fn buffered(&self) -> usize
+    {
+        self.len
+    }
+}
+
+impl<const N: usize, T: HtifTransport> core::fmt::Write for BufferedWriter<'_, N, T>
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result
+    {
+        self.write(s.as_bytes()).map_err(|_| core::fmt::Error)
+    }
+}
+
+/* don't lose output that didn't end in a newline. there's no one to report a failure to */
+impl<const N: usize, T: HtifTransport> Drop for BufferedWriter<'_, N, T>
+{
+    fn drop(&mut self)
+    {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::fesvr::Fesvr;
+
+    #[test]
+    fn one_handshake_per_flush()
+    {
+        let mut htif = HTIF::over(Fesvr::<64>::new()).with_bulk_output(1);
+        htif.reset_stats();
+        {
+            let mut writer = BufferedWriter::<16, _>::new(&mut htif);
+            assert_eq!(writer.write(b"hello, "), Ok(()));
+            assert_eq!(writer.write(b"world\n"), Ok(()));
+            assert_eq!(writer.buffered(), 0);
+        }
+
+        assert_eq!(htif.stats().requests, 1);
+        assert_eq!(&*htif.transport().output(), b"hello, world\n");
+    }
+}
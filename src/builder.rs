@@ -0,0 +1,205 @@
+/* gather a driver's configuration before creating it
+ *
+ * HTIF::new() and its with_ methods remain for simple cases. A builder keeps
+ * every setting in one place, and can be passed around before the driver exists:
+ *
+ * let htif = HTIF::builder().timeout(1_000_000).echo(true).build()?;
+ *
+ * Everything but build() and probe() can be done in a const context, and
+ * build_static() creates a driver there too, for kernels that keep it in a static.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault, DEVICE_CHARIO};
+use super::newline::Newlines;
+use super::transport::Mmio;
+use super::exit::ExitDevice;
+use super::backoff::Backoff;
+use super::trace::Transaction;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HtifBuilder
+{
+    registers: Mmio,
+    console: u8,
+    timeout: Option<u64>,
+    backoff: Backoff,
+    bulk_output: Option<usize>,
+    trace: Option<fn(Transaction)>,
+    echo: bool,
+    newlines: Newlines,
+    exit_device: ExitDevice
+}
+
+/* the register addresses are only dereferenced by the driver built from this,
+   so a builder can be shared, such as from a static holding a board's settings */
+unsafe impl Sync for HtifBuilder {}
+
+impl Default for HtifBuilder
+{
+    fn default() -> Self
+    {
+        HtifBuilder::new()
+    }
+}
+
+impl HtifBuilder
+{
+    /* start from the settings HTIF::new() uses */
+    pub const fn new() -> Self
+    {
+        HtifBuilder
+        {
+            registers: Mmio::linker(),
+            console: DEVICE_CHARIO as u8,
+            timeout: None,
+            backoff: Backoff::DEFAULT,
+            bulk_output: None,
+            trace: None,
+            echo: false,
+            newlines: Newlines::NONE,
+            exit_device: ExitDevice::Htif
+        }
+    }
+
+    /* use the tohost and fromhost registers at the given addresses rather than
+       the linker-defined ones. see HTIF::at() for why this is unsafe */
+    pub const unsafe fn registers(mut self, to_host: *mut u64, from_host: *mut u64) -> Self
+    {
+        self.registers = Mmio::at(to_host, from_host);
+        self
+    }
+
+    /* see HTIF::with_console() */
+    pub const fn console(mut self, device: u8) -> Self
+    {
+        self.console = device;
+        self
+    }
+
+    /* see HTIF::with_timeout() */
+    pub const fn timeout(mut self, polls: u64) -> Self
+    {
+        self.timeout = Some(polls);
+        self
+    }
+
+    /* see HTIF::with_backoff() */
+    pub const fn backoff(mut self, backoff: Backoff) -> Self
+    {
+        self.backoff = backoff;
+        self
+    }
+
+    /* see HTIF::with_bulk_output() */
+    pub const fn bulk_output(mut self, min_len: usize) -> Self
+    {
+        self.bulk_output = Some(min_len);
+        self
+    }
+
+    /* see HTIF::with_trace() */
+    pub const fn trace(mut self, hook: fn(Transaction)) -> Self
+    {
+        self.trace = Some(hook);
+        self
+    }
+
+    /* see HTIF::with_echo() */
+    pub const fn echo(mut self, echo: bool) -> Self
+    {
+        self.echo = echo;
+        self
+    }
+
+    /* see HTIF::with_newlines() */
+    pub const fn newlines(mut self, newlines: Newlines) -> Self
+    {
+        self.newlines = newlines;
+        self
+    }
+
+    /* see HTIF::with_exit_device() for why this is unsafe */
+    pub const unsafe fn exit_device(mut self, device: ExitDevice) -> Self
+    {
+        self.exit_device = device;
+        self
+    }
+
+    /* create the driver */
+    pub fn build(self) -> Result<HTIF, Fault>
+    {
+        Ok(self.configure(HTIF::over(self.registers)))
+    }
+
+    /* create the driver only if a host is servicing the registers. see HTIF::probe() */
+    pub fn probe(self) -> Result<HTIF, Fault>
+    {
+        Ok(self.configure(HTIF::over(self.registers).probed()?))
+    }
+
+    /* create the driver in a const context, so that it can be placed in a static,
+       such as inside a lock. no request is made of the host, so this can't fail.
+       unlike build(), this can't discard a reply an earlier boot stage left in
+       fromhost. call recover() before first use if one might be there */
+    pub const fn build_static(self) -> HTIF
+    {
+        self.configure(HTIF::assemble(self.registers))
+    }
+
+    const fn configure(self, htif: HTIF) -> HTIF
+    {
+        let htif = htif.with_console(self.console).with_backoff(self.backoff).with_echo(self.echo).with_newlines(self.newlines);
+        let htif = unsafe { htif.with_exit_device(self.exit_device) };
+        let htif = match self.bulk_output
+        {
+            Some(min_len) => htif.with_bulk_output(min_len),
+            None => htif
+        };
+        let htif = match self.trace
+        {
+            Some(hook) => htif.with_trace(hook),
+            None => htif
+        };
+        match self.timeout
+        {
+            Some(polls) => htif.with_timeout(polls),
+            None => htif
+        }
+    }
+}
+
+impl HTIF
+{
+    /* configure a driver before creating it */
+    pub const fn builder() -> HtifBuilder
+    {
+        HtifBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use core::ptr::addr_of_mut;
+
+    static mut TO_HOST: u64 = 0;
+    static mut FROM_HOST: u64 = 0;
+
+    /* the driver and its settings must be able to live in statics */
+    static SETTINGS: HtifBuilder = HTIF::builder().console(2).timeout(10);
+    static mut DRIVER: HTIF = unsafe { SETTINGS.registers(addr_of_mut!(TO_HOST), addr_of_mut!(FROM_HOST)) }.build_static();
+
+    #[test]
+    fn const_construction()
+    {
+        let driver = unsafe { &*core::ptr::addr_of!(DRIVER) };
+        assert_eq!(driver.console, 2);
+        assert_eq!(driver.timeout, Some(10));
+        assert_eq!(driver.transport.registers.map(|(to_host, _)| to_host.addr()), Some(addr_of_mut!(TO_HOST)));
+    }
+}
@@ -0,0 +1,137 @@
+/* Bulk console output via the host's write syscall
+ *
+ * The console device takes one byte per handshake, so a long boot log costs
+ * thousands of them. fesvr's console writes to its own stdout, which the guest
+ * can also reach by proxying write(1, buf, len), handing over a whole buffer in
+ * one handshake. Drivers built with_bulk_output() send console writes of at
+ * least the given length that way. This is done by write_bytes() itself, so it
+ * applies to every writer in the crate built on it, from send_bytes() and
+ * fmt::Write to BufferedWriter, ufmt, embedded-hal and the line editors.
+ * Output that goes out a byte at a time by design, such as TxRing's and the
+ * raw bytes of XMODEM and the GDB stub, is never sent in bulk.
+ *
+ * As with other proxied syscalls, the buffer's address is passed to the host as
+ * is, so it must be a physical address: the stack or heap of a kernel running
+ * with paging on may not be. Only the default console, device 1, goes to the
+ * host's stdout, so writes to other consoles are always sent a byte at a time.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault, PartialWrite, DEVICE_CHARIO};
+use super::syscall::SYS_WRITE;
+use super::transport::HtifTransport;
+
+/* the host's standard output */
+const STDOUT: u64 = 1;
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* send writes to the console of at least min_len bytes to the host's stdout in
+       one proxied write syscall, rather than a byte at a time. only use this with
+       hosts that proxy syscalls, such as fesvr. call this before using the driver */
+    pub const fn with_bulk_output(mut self, min_len: usize) -> Self
+    {
+        self.bulk_output = Some(min_len);
+        self
+    }
+
+    /* write a buffer of bytes to the host console. this is write_bytes(), which
+       already sends long enough writes in bulk, for callers holding the driver
+       exclusively, such as BufferedWriter */
+    pub fn write_all(&mut self, to_send: &[u8]) -> Result<(), PartialWrite>
+    {
+        self.write_bytes(to_send)
+    }
+
+    /* return true if a console write of len bytes should go via the write syscall */
+    pub(crate) fn is_bulk(&self, len: usize) -> bool
+    {
+        match self.bulk_output
+        {
+            Some(min_len) => len >= min_len && self.console as u64 == DEVICE_CHARIO,
+            None => false
+        }
+    }
+
+    /* write bytes to the host's stdout, expanding LFs if need be. on failure, the
+       error records how many bytes the host is known to have taken */
+    pub(crate) fn write_bulk(&self, to_send: &[u8]) -> Result<(), PartialWrite>
+    {
+        let mut written = 0;
+        let result = match self.newlines.crlf_output
+        {
+            false => self.write_stdout(to_send, &mut written),
+            true =>
+            {
+                /* each line goes in one syscall, and its CR LF in another */
+                to_send.split_inclusive(|&b| b == b'\n').try_for_each(|line| match line.split_last()
+                {
+                    Some((b'\n', text)) =>
+                    {
+                        self.write_stdout(text, &mut written)?;
+                        self.write_stdout(b"\r\n", &mut 0)?;
+                        written += 1;
+                        Ok(())
+                    },
+                    _ => self.write_stdout(line, &mut written)
+                })
+            }
+        };
+
+        self.count(|stats| stats.bytes_sent = stats.bytes_sent.wrapping_add(written as u64));
+        result.map_err(|fault| PartialWrite { written, fault })
+    }
+
+    /* write every byte to the host's stdout, however many calls that takes,
+       adding the number of bytes the host takes to written */
+    fn write_stdout(&self, mut bytes: &[u8], written: &mut usize) -> Result<(), Fault>
+    {
+        while !bytes.is_empty()
+        {
+            /* a host that takes nothing would otherwise be asked forever */
+            let taken = match self.proxy_syscall(SYS_WRITE, &[STDOUT, bytes.as_ptr() as u64, bytes.len() as u64])? as usize
+            {
+                0 => return Err(Fault::InvalidResponse),
+                taken => taken.min(bytes.len())
+            };
+
+            *written += taken;
+            bytes = &bytes[taken..];
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::fesvr::Fesvr;
+    use crate::newline::Newlines;
+
+    #[test]
+    fn every_writer_goes_bulk()
+    {
+        let htif = HTIF::over(Fesvr::<64>::new()).with_bulk_output(4);
+        htif.reset_stats();
+        assert_eq!(htif.send_bytes(b"booting\n"), Ok(()));
+        assert_eq!(htif.stats().requests, 1);
+
+        /* anything shorter still goes a byte at a time */
+        assert_eq!(htif.write_str("ok\n"), Ok(()));
+        assert_eq!(htif.stats().requests, 4);
+        assert_eq!(htif.stats().bytes_sent, 11);
+        assert_eq!(&*htif.transport().output(), b"booting\nok\n");
+    }
+
+    #[test]
+    fn bulk_translates_newlines()
+    {
+        let htif = HTIF::over(Fesvr::<64>::new()).with_bulk_output(1).with_newlines(Newlines::TERMINAL);
+        assert_eq!(htif.send_bytes(b"a\nb"), Ok(()));
+        assert_eq!(&*htif.transport().output(), b"a\r\nb");
+    }
+}
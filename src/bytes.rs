@@ -0,0 +1,68 @@
+/* iterators over bytes arriving from the host console
+ *
+ * for byte in htif.bytes() blocks for each byte in turn, while
+ * htif.try_bytes() only returns what the host has ready right now.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::transport::{HtifTransport, Mmio};
+
+/* blocks for each byte. this never ends: errors, such as timeouts, are returned
+   and the next call to next() tries again, so the caller decides when to stop */
+pub struct Bytes<'a, T = Mmio>
+{
+    htif: &'a mut HTIF<T>
+}
+
+impl<T: HtifTransport> Iterator for Bytes<'_, T>
+{
+    type Item = Result<u8, Fault>;
+
+    fn next(&mut self) -> Option<Result<u8, Fault>>
+    {
+        Some(self.htif.read_byte())
+    }
+}
+
+/* returns bytes without blocking, and ends once none are ready or after an error */
+pub struct TryBytes<'a, T = Mmio>
+{
+    htif: &'a mut HTIF<T>,
+    failed: bool
+}
+
+impl<T: HtifTransport> Iterator for TryBytes<'_, T>
+{
+    type Item = Result<u8, Fault>;
+
+    fn next(&mut self) -> Option<Result<u8, Fault>>
+    {
+        if self.failed
+        {
+            return None;
+        }
+
+        let result = self.htif.try_read_byte().transpose();
+        self.failed = matches!(result, Some(Err(_)));
+        result
+    }
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* iterate over bytes from the host console, blocking for each one */
+    pub fn bytes(&mut self) -> Bytes<'_, T>
+    {
+        Bytes { htif: self }
+    }
+
+    /* iterate over the bytes the host console has ready, without blocking */
+    pub fn try_bytes(&mut self) -> TryBytes<'_, T>
+    {
+        TryBytes { htif: self, failed: false }
+    }
+}
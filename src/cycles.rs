@@ -0,0 +1,43 @@
+/* Read the hart's cycle counter
+ *
+ * The counter must be readable at the current privilege level: outside
+ * M-mode, rdcycle traps unless enabled in mcounteren (and scounteren in U-mode).
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+/* return the hart's cycle counter, or None if there isn't one */
+#[cfg(target_arch = "riscv64")]
+pub(crate) fn read_cycles() -> Option<u64>
+{
+    let cycles: u64;
+    unsafe { core::arch::asm!("rdcycle {}", out(reg) cycles, options(nomem, nostack)) };
+    Some(cycles)
+}
+
+/* the counter is read in halves, so re-read the high half in case the low half wrapped */
+#[cfg(target_arch = "riscv32")]
+pub(crate) fn read_cycles() -> Option<u64>
+{
+    loop
+    {
+        let (hi, lo, again): (u32, u32, u32);
+        unsafe
+        {
+            core::arch::asm!("rdcycleh {}", "rdcycle {}", "rdcycleh {}",
+                out(reg) hi, out(reg) lo, out(reg) again, options(nomem, nostack));
+        }
+        if hi == again
+        {
+            return Some(((hi as u64) << 32) | lo as u64);
+        }
+    }
+}
+
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+pub(crate) fn read_cycles() -> Option<u64>
+{
+    None
+}
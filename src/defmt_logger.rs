@@ -0,0 +1,68 @@
+/* defmt global logger that writes to the global host console
+ *
+ * Enabled by the defmt feature. Encoded frames contain no NUL bytes and plain
+ * text shouldn't either, so each frame is preceded by a NUL, and the encoder
+ * ends each frame with one. A host tool can then split frames from any text
+ * printed around them by toggling between the two at every NUL.
+ *
+ * The console is locked from the start of a frame to its end. A nested log
+ * from the same hart, such as from a trap handler, will deadlock.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::ptr::addr_of_mut;
+use super::HTIF;
+use super::global;
+
+/* marks the start of a frame */
+const FRAME_START: u8 = 0;
+
+#[defmt::global_logger]
+struct Logger;
+
+/* only touched while the console is locked */
+static mut ENCODER: defmt::Encoder = defmt::Encoder::new();
+static mut CONSOLE: Option<&'static mut HTIF> = None;
+
+/* output is best effort: there's nowhere to report errors */
+fn send(bytes: &[u8])
+{
+    unsafe
+    {
+        if let Some(console) = (*addr_of_mut!(CONSOLE)).as_mut()
+        {
+            let _ = console.send_bytes(bytes);
+        }
+    }
+}
+
+unsafe impl defmt::Logger for Logger
+{
+    fn acquire()
+    {
+        unsafe
+        {
+            *addr_of_mut!(CONSOLE) = global::acquire();
+            send(&[FRAME_START]);
+            (*addr_of_mut!(ENCODER)).start_frame(send);
+        }
+    }
+
+    /* output isn't buffered */
+    unsafe fn flush() {}
+
+    unsafe fn release()
+    {
+        (*addr_of_mut!(ENCODER)).end_frame(send);
+        *addr_of_mut!(CONSOLE) = None;
+        global::release();
+    }
+
+    unsafe fn write(bytes: &[u8])
+    {
+        (*addr_of_mut!(ENCODER)).write(bytes, send);
+    }
+}
@@ -0,0 +1,119 @@
+/* Route replies from the host to the devices they're meant for
+ *
+ * The host posts replies from all devices through the one fromhost register, so
+ * a reply for one device may turn up while waiting on another. Rather than drop
+ * it, hold on to it in a small queue of pending replies until its device asks.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::FromHostReply;
+
+/* maximum number of replies that can be held for devices yet to ask for them */
+const MAX_PENDING: usize = 8;
+
+/* replies in order of arrival, oldest first */
+#[derive(Debug)]
+pub struct Demux
+{
+    pending: [Option<FromHostReply>; MAX_PENDING],
+    count: usize
+}
+
+impl Demux
+{
+    pub const fn new() -> Self
+    {
+        Demux { pending: [None; MAX_PENDING], count: 0 }
+    }
+
+    /* hold on to a reply until its device asks for it. if the queue is full, the
+       oldest reply is dropped to make room, and returned */
+    pub fn push(&mut self, reply: FromHostReply) -> Option<FromHostReply>
+    {
+        let mut dropped = None;
+        if self.count == MAX_PENDING
+        {
+            dropped = self.remove(0);
+        }
+
+        self.pending[self.count] = Some(reply);
+        self.count += 1;
+        dropped
+    }
+
+    /* take the oldest reply held for the given device, if any */
+    pub fn take(&mut self, device: u8) -> Option<FromHostReply>
+    {
+        let index = self.pending[..self.count].iter().position(|reply| match reply
+        {
+            Some(reply) => reply.device == device,
+            None => false
+        })?;
+
+        self.remove(index)
+    }
+
+    /* return the number of replies held */
+    pub fn len(&self) -> usize
+    {
+        self.count
+    }
+
+    /* throw away all held replies */
+    pub fn clear(&mut self)
+    {
+        *self = Demux::new();
+    }
+
+    /* remove the reply at index, closing the gap it leaves */
+    fn remove(&mut self, index: usize) -> Option<FromHostReply>
+    {
+        let reply = self.pending[index].take();
+        self.pending[index..self.count].rotate_left(1);
+        self.count -= 1;
+        reply
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn reply(device: u8, payload: u64) -> FromHostReply
+    {
+        FromHostReply { device, command: 0, payload }
+    }
+
+    #[test]
+    fn routes_by_device_in_order()
+    {
+        let mut demux = Demux::new();
+        demux.push(reply(1, 10));
+        demux.push(reply(2, 20));
+        demux.push(reply(1, 11));
+
+        assert_eq!(demux.take(0), None);
+        assert_eq!(demux.take(1), Some(reply(1, 10)));
+        assert_eq!(demux.take(2), Some(reply(2, 20)));
+        assert_eq!(demux.take(1), Some(reply(1, 11)));
+        assert_eq!(demux.take(1), None);
+    }
+
+    #[test]
+    fn drops_oldest_when_full()
+    {
+        let mut demux = Demux::new();
+        for payload in 0..MAX_PENDING as u64
+        {
+            assert_eq!(demux.push(reply(1, payload)), None);
+        }
+
+        assert_eq!(demux.push(reply(2, 99)), Some(reply(1, 0)));
+        assert_eq!(demux.take(2), Some(reply(2, 99)));
+        assert_eq!(demux.take(1), Some(reply(1, 1)));
+    }
+}
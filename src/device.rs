@@ -0,0 +1,174 @@
+/* Discover which devices the host provides via HTIF's identify command
+ *
+ * Every HTIF device's last command, 255, writes a NUL-terminated identity string
+ * into a 64-byte, 64-byte aligned buffer in target memory. The command's payload
+ * holds the buffer's address multiplied by 256, plus, in the low byte, what to
+ * identify: 255 for the device itself, or a command number for that command's name.
+ * Device numbers the host doesn't use identify as empty strings.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::ptr::{read_volatile, addr_of, addr_of_mut};
+use super::{HTIF, Fault};
+use super::protocol::encode_command;
+use super::transport::{HtifTransport, Mmio};
+
+const COMMAND_IDENTIFY: u64 = 0xff; /* every device's last command reports identities */
+const IDENTIFY_DEVICE:  u8 = 0xff;  /* ask the identify command for the device's name */
+const IDENTITY_SIZE:    usize = 64; /* size of buffer the host writes an identity into */
+
+/* an identity string as reported by the host */
+#[repr(C, align(64))]
+#[derive(Clone, Copy)]
+pub struct Identity([u8; IDENTITY_SIZE]);
+
+impl Identity
+{
+    /* return the identity's bytes, minus the NUL terminator */
+    pub fn as_bytes(&self) -> &[u8]
+    {
+        let len = self.0.iter().position(|&b| b == 0).unwrap_or(IDENTITY_SIZE);
+        &self.0[..len]
+    }
+
+    /* return the identity as a string, or an empty string if the host sent garbage */
+    pub fn as_str(&self) -> &str
+    {
+        core::str::from_utf8(self.as_bytes()).unwrap_or("")
+    }
+
+    /* an empty identity means there's nothing there */
+    pub fn is_empty(&self) -> bool
+    {
+        self.0[0] == 0
+    }
+}
+
+impl core::fmt::Debug for Identity
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+    {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+/* describes a device provided by the host */
+#[derive(Debug, Clone, Copy)]
+pub struct Device
+{
+    pub number: u8,
+    pub identity: Identity
+}
+
+/* iterates over the devices provided by the host, in device number order.
+   stops after the first failure to query the host */
+pub struct Devices<'a, T = Mmio>
+{
+    htif: &'a mut HTIF<T>,
+    next: u16
+}
+
+impl<T: HtifTransport> Iterator for Devices<'_, T>
+{
+    type Item = Result<Device, Fault>;
+
+    fn next(&mut self) -> Option<Result<Device, Fault>>
+    {
+        while self.next <= u8::MAX as u16
+        {
+            let number = self.next as u8;
+            self.next += 1;
+
+            match self.htif.identify(number)
+            {
+                Ok(identity) if identity.is_empty() => continue,
+                Ok(identity) => return Some(Ok(Device { number, identity })),
+                Err(e) =>
+                {
+                    self.next = u8::MAX as u16 + 1;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* return the identity of the given device number, which is empty if there's no such device */
+    pub fn identify(&mut self, device: u8) -> Result<Identity, Fault>
+    {
+        self.query_identity(device, IDENTIFY_DEVICE)
+    }
+
+    /* return the name of the given command on the given device number,
+       which is empty if the device doesn't support the command */
+    pub fn identify_command(&mut self, device: u8, command: u8) -> Result<Identity, Fault>
+    {
+        self.query_identity(device, command)
+    }
+
+    /* iterate over the devices provided by the host. this queries every
+       possible device number, so cache the results if they're needed often */
+    pub fn devices(&mut self) -> Devices<'_, T>
+    {
+        Devices { htif: self, next: 0 }
+    }
+
+    /* return the first device whose identity starts with the given prefix, if any */
+    pub fn find_device(&mut self, prefix: &str) -> Result<Option<Device>, Fault>
+    {
+        for dev in self.devices()
+        {
+            let dev = dev?;
+            if dev.identity.as_str().starts_with(prefix)
+            {
+                return Ok(Some(dev));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /* have the given device's identify command write the identity of what into a buffer.
+       the host writes to the buffer directly, so it must be at a physical address */
+    fn query_identity(&mut self, device: u8, what: u8) -> Result<Identity, Fault>
+    {
+        let mut identity = Identity([0; IDENTITY_SIZE]);
+
+        /* the buffer is 64-byte aligned, so multiplying its address by 256 leaves
+           the low byte clear for what to identify */
+        let addr = addr_of_mut!(identity) as u64;
+        self.transact(encode_command(device, COMMAND_IDENTIFY as u8, (addr << 8) | what as u64))?;
+
+        /* the host wrote into identity behind the compiler's back */
+        Ok(unsafe { read_volatile(addr_of!(identity)) })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn identity_strings()
+    {
+        let mut bytes = [0; IDENTITY_SIZE];
+        assert!(Identity(bytes).is_empty());
+        assert_eq!(Identity(bytes).as_str(), "");
+
+        bytes[..4].copy_from_slice(b"bcd\0");
+        assert!(!Identity(bytes).is_empty());
+        assert_eq!(Identity(bytes).as_bytes(), b"bcd");
+
+        /* the host should always NUL-terminate, but don't rely on it */
+        let bytes = [b'x'; IDENTITY_SIZE];
+        assert_eq!(Identity(bytes).as_bytes().len(), IDENTITY_SIZE);
+    }
+}
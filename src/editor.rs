@@ -0,0 +1,407 @@
+/* line editing with history for interactive programs on the host console
+ *
+ * Enabled by the line-editor feature. Where HTIF::read_line() only handles
+ * backspace, a LineEditor lets the user move about the line and recall
+ * earlier lines, as a shell would, for kernel monitors and the like:
+ *
+ * let mut editor = LineEditor::<80, 8>::new();
+ * loop { let command = editor.read_line(&mut htif)?; ... }
+ *
+ * Lines are up to LEN bytes, and the last HISTORY of them are remembered.
+ * It understands the arrow keys, home, end, and delete, as sent by VT100-style
+ * terminals, and their emacs-style control keys:
+ *
+ *  left, right: ctrl-b, ctrl-f     home, end: ctrl-a, ctrl-e
+ *  up, down to recall: ctrl-p, ctrl-n
+ *  backspace erases before the cursor, delete and ctrl-d at it
+ *  ctrl-u erases the whole line, ctrl-k from the cursor to its end
+ *
+ * Only printable ASCII is inserted, so that each byte is one column. Edits are
+ * drawn on the console whatever the driver's echo setting, using only
+ * backspaces and reprinting, so any terminal will do, though it must pass
+ * keystrokes through as they're typed, as Spike's does.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+
+const CTRL_A: u8 = 0x01;
+const CTRL_B: u8 = 0x02;
+const CTRL_D: u8 = 0x04;
+const CTRL_E: u8 = 0x05;
+const CTRL_F: u8 = 0x06;
+const BACKSPACE: u8 = 0x08;
+const CTRL_K: u8 = 0x0b;
+const CTRL_N: u8 = 0x0e;
+const CTRL_P: u8 = 0x10;
+const CTRL_U: u8 = 0x15;
+const ESCAPE: u8 = 0x1b;
+const DELETE: u8 = 0x7f;
+
+/* a keystroke, decoded from one or more bytes */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key
+{
+    Char(u8), /* insert this at the cursor */
+    Left,
+    Right,
+    Home,
+    End,
+    Up, /* recall the previous line */
+    Down, /* recall the next line */
+    Backspace, /* erase before the cursor */
+    Delete, /* erase at the cursor */
+    KillLine, /* erase the whole line */
+    KillToEnd, /* erase from the cursor to the end of the line */
+    Enter
+}
+
+/* where the decoder is in an escape sequence */
+#[derive(Debug, Clone, Copy)]
+enum State
+{
+    Normal,
+    Escape, /* ESC seen */
+    Csi(u8), /* ESC [ seen, followed by this number so far */
+    Ss3 /* ESC O seen */
+}
+
+/* turn a stream of bytes into keystrokes */
+#[derive(Debug, Clone, Copy)]
+struct Decoder
+{
+    state: State
+}
+
+impl Decoder
+{
+    const fn new() -> Self
+    {
+        Decoder { state: State::Normal }
+    }
+
+    /* take the next byte, and return the keystroke it completes, if any.
+       unrecognized keys and sequences are dropped */
+    fn feed(&mut self, byte: u8) -> Option<Key>
+    {
+        let (state, key) = match (self.state, byte)
+        {
+            (State::Normal, ESCAPE) => (State::Escape, None),
+            (State::Normal, byte) => (State::Normal, match byte
+            {
+                b'\r' | b'\n' => Some(Key::Enter),
+                BACKSPACE | DELETE => Some(Key::Backspace),
+                CTRL_A => Some(Key::Home),
+                CTRL_B => Some(Key::Left),
+                CTRL_D => Some(Key::Delete),
+                CTRL_E => Some(Key::End),
+                CTRL_F => Some(Key::Right),
+                CTRL_K => Some(Key::KillToEnd),
+                CTRL_N => Some(Key::Down),
+                CTRL_P => Some(Key::Up),
+                CTRL_U => Some(Key::KillLine),
+                b' '..=b'~' => Some(Key::Char(byte)),
+                _ => None
+            }),
+
+            (State::Escape, b'[') => (State::Csi(0), None),
+            (State::Escape, b'O') => (State::Ss3, None),
+            (State::Escape, _) => (State::Normal, None),
+
+            /* parameters, such as the 3 in ESC [ 3 ~. modifiers after a ; are ignored */
+            (State::Csi(n), b'0'..=b'9') => (State::Csi(n.saturating_mul(10).saturating_add(byte - b'0')), None),
+            (State::Csi(n), b';') => (State::Csi(n), None),
+            (State::Csi(n), b'~') => (State::Normal, match n
+            {
+                1 | 7 => Some(Key::Home),
+                3 => Some(Key::Delete),
+                4 | 8 => Some(Key::End),
+                _ => None
+            }),
+
+            (State::Csi(_), byte) | (State::Ss3, byte) => (State::Normal, match byte
+            {
+                b'A' => Some(Key::Up),
+                b'B' => Some(Key::Down),
+                b'C' => Some(Key::Right),
+                b'D' => Some(Key::Left),
+                b'H' => Some(Key::Home),
+                b'F' => Some(Key::End),
+                _ => None
+            })
+        };
+
+        self.state = state;
+        key
+    }
+}
+
+/* a line of up to LEN bytes */
+#[derive(Debug, Clone, Copy)]
+struct Line<const LEN: usize>
+{
+    bytes: [u8; LEN],
+    len: usize
+}
+
+impl<const LEN: usize> Line<LEN>
+{
+    const EMPTY: Self = Line { bytes: [0; LEN], len: 0 };
+
+    fn as_slice(&self) -> &[u8]
+    {
+        &self.bytes[..self.len]
+    }
+}
+
+pub struct LineEditor<const LEN: usize, const HISTORY: usize>
+{
+    line: Line<LEN>, /* the line being edited */
+    cursor: usize, /* index in the line of the byte under the cursor */
+    shown: Line<LEN>, /* the line as it appears on the console */
+    shown_cursor: usize, /* where the cursor is on the console */
+    draft: Line<LEN>, /* the line being edited before history was recalled */
+    history: [Line<LEN>; HISTORY], /* earlier lines, as a ring */
+    newest: usize, /* index in history of the most recent line */
+    remembered: usize, /* number of lines in history */
+    recalled: usize, /* how far back in history the line being edited is, or 0 for the draft */
+    keys: Decoder
+}
+
+impl<const LEN: usize, const HISTORY: usize> Default for LineEditor<LEN, HISTORY>
+{
+    fn default() -> Self
+    {
+        LineEditor::new()
+    }
+}
+
+impl<const LEN: usize, const HISTORY: usize> LineEditor<LEN, HISTORY>
+{
+    /* create an editor with no history. this is const so it can be placed in a static */
+    pub const fn new() -> Self
+    {
+        LineEditor
+        {
+            line: Line::EMPTY,
+            cursor: 0,
+            shown: Line::EMPTY,
+            shown_cursor: 0,
+            draft: Line::EMPTY,
+            history: [Line::EMPTY; HISTORY],
+            newest: 0,
+            remembered: 0,
+            recalled: 0,
+            keys: Decoder::new()
+        }
+    }
+
+    /* let the user edit a line on the given console until Enter is pressed, and return
+       it, excluding the CR or LF that ended it. the cursor starts where the console's
+       is, so print any prompt first. non-empty lines are added to the history */
+    pub fn read_line(&mut self, htif: &mut HTIF<impl HtifTransport>) -> Result<&[u8], Fault>
+    {
+        self.line = Line::EMPTY;
+        self.cursor = 0;
+        self.shown = Line::EMPTY;
+        self.shown_cursor = 0;
+        self.recalled = 0;
+        self.keys = Decoder::new();
+
+        loop
+        {
+            let key = match self.keys.feed(htif.take_byte()?)
+            {
+                Some(key) => key,
+                None => continue
+            };
+
+            if key == Key::Enter
+            {
+                /* the console's newline translation adds a CR if the terminal needs one */
+                htif.send_bytes(b"\n")?;
+                self.remember();
+                return Ok(self.line.as_slice());
+            }
+
+            self.edit(key);
+            self.redraw(htif)?;
+        }
+    }
+
+    /* apply a keystroke, other than Enter, to the line */
+    fn edit(&mut self, key: Key)
+    {
+        let line = &mut self.line;
+        match key
+        {
+            Key::Char(byte) if line.len < LEN =>
+            {
+                line.bytes.copy_within(self.cursor..line.len, self.cursor + 1);
+                line.bytes[self.cursor] = byte;
+                line.len += 1;
+                self.cursor += 1;
+            },
+            Key::Backspace if self.cursor > 0 =>
+            {
+                line.bytes.copy_within(self.cursor..line.len, self.cursor - 1);
+                line.len -= 1;
+                self.cursor -= 1;
+            },
+            Key::Delete if self.cursor < line.len =>
+            {
+                line.bytes.copy_within(self.cursor + 1..line.len, self.cursor);
+                line.len -= 1;
+            },
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(line.len),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = line.len,
+            Key::KillLine =>
+            {
+                line.len = 0;
+                self.cursor = 0;
+            },
+            Key::KillToEnd => line.len = self.cursor,
+            Key::Up if self.recalled < self.remembered => self.recall(self.recalled + 1),
+            Key::Down if self.recalled > 0 => self.recall(self.recalled - 1),
+            _ => ()
+        }
+    }
+
+    /* replace the line with the one the given distance back in history,
+       or the draft for 0, and put the cursor at its end */
+    fn recall(&mut self, distance: usize)
+    {
+        if self.recalled == 0
+        {
+            self.draft = self.line;
+        }
+
+        self.line = match distance
+        {
+            0 => self.draft,
+            _ => self.history[(self.newest + HISTORY + 1 - distance) % HISTORY]
+        };
+        self.cursor = self.line.len;
+        self.recalled = distance;
+    }
+
+    /* add the line just entered to the history, unless it's empty or a repeat */
+    fn remember(&mut self)
+    {
+        if HISTORY == 0 || self.line.len == 0
+        {
+            return;
+        }
+
+        if self.remembered > 0 && self.history[self.newest].as_slice() == self.line.as_slice()
+        {
+            return;
+        }
+
+        self.newest = match self.remembered
+        {
+            0 => 0,
+            _ => (self.newest + 1) % HISTORY
+        };
+        self.history[self.newest] = self.line;
+        self.remembered = (self.remembered + 1).min(HISTORY);
+    }
+
+    /* bring the console up to date with the line, reprinting from the first change */
+    fn redraw(&mut self, htif: &HTIF<impl HtifTransport>) -> Result<(), Fault>
+    {
+        let line = self.line.as_slice();
+        let shown = self.shown.as_slice();
+        let same = line.iter().zip(shown).take_while(|(a, b)| a == b).count();
+
+        if same == line.len() && same == shown.len()
+        {
+            /* only the cursor has moved */
+            move_cursor(htif, line, self.shown_cursor, self.cursor)?;
+        }
+        else
+        {
+            /* print the rest of the line over what was there, blanking what's left of the old */
+            move_cursor(htif, line, self.shown_cursor, same)?;
+            htif.send_bytes(&line[same..])?;
+            let blanks = shown.len().saturating_sub(line.len());
+            repeat(htif, b' ', blanks)?;
+            move_cursor(htif, line, line.len() + blanks, self.cursor)?;
+        }
+
+        self.shown = self.line;
+        self.shown_cursor = self.cursor;
+        Ok(())
+    }
+}
+
+/* move the console's cursor from one column to another within the line,
+   backing up with backspaces and moving forward by reprinting */
+fn move_cursor(htif: &HTIF<impl HtifTransport>, line: &[u8], from: usize, to: usize) -> Result<(), Fault>
+{
+    match to < from
+    {
+        true => repeat(htif, BACKSPACE, from - to),
+        false => htif.send_bytes(&line[from..to])
+    }
+}
+
+/* send the given byte count times */
+fn repeat(htif: &HTIF<impl HtifTransport>, byte: u8, count: usize) -> Result<(), Fault>
+{
+    let chunk = [byte; 16];
+    let mut left = count;
+    while left > 0
+    {
+        let len = left.min(chunk.len());
+        htif.send_bytes(&chunk[..len])?;
+        left -= len;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::fesvr::Fesvr;
+    use crate::newline::Newlines;
+
+    #[test]
+    fn decoding()
+    {
+        let mut keys = Decoder::new();
+        let decoded: Vec<Key> = b"a\x1b[D\x1b[3~\x1bOH\x1b[1;5C\x15\r".iter().filter_map(|byte| keys.feed(*byte)).collect();
+        assert_eq!(decoded, [Key::Char(b'a'), Key::Left, Key::Delete, Key::Home, Key::Right, Key::KillLine, Key::Enter]);
+    }
+
+    #[test]
+    fn editing()
+    {
+        let mut htif = HTIF::over(Fesvr::<256>::new()).with_newlines(Newlines::TERMINAL);
+        let mut editor = LineEditor::<8, 2>::new();
+
+        /* insert in the middle, and erase at the end */
+        htif.transport().type_input(b"acd\x1b[D\x1b[Db\x05\x08\r");
+        assert_eq!(editor.read_line(&mut htif), Ok(&b"abc"[..]));
+        assert_eq!(&*htif.transport().output(), b"acd\x08\x08bcd\x08\x08cd\x08 \x08\r\n");
+
+        /* recall earlier lines, while an unfinished one waits */
+        htif.transport().type_input(b"xyz\r");
+        assert_eq!(editor.read_line(&mut htif), Ok(&b"xyz"[..]));
+        htif.transport().type_input(b"new\x10\x10\x0e\x0e\r");
+        assert_eq!(editor.read_line(&mut htif), Ok(&b"new"[..]));
+        htif.transport().type_input(b"\x1b[A\x1b[A\x1b[A\r");
+        assert_eq!(editor.read_line(&mut htif), Ok(&b"xyz"[..]));
+
+        /* kill to the end, then the rest */
+        htif.transport().type_input(b"hello\x01\x06\x0b\x15\r");
+        assert_eq!(editor.read_line(&mut htif), Ok(&b""[..]));
+    }
+}
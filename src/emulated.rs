@@ -0,0 +1,218 @@
+/* Play the part of the HTIF host for the console, over some other interface
+ *
+ * Transports such as sbi::SbiConsole have no real tohost and fromhost behind
+ * them. They instead hand each request to an EmulatedHost, which carries out
+ * console writes and exits through a Backend, and answers console reads with
+ * whatever the Backend has to offer. It doesn't know which device the driver
+ * uses as its console, and there are no other devices to mistake for it, so
+ * character reads and writes on any device go to the Backend. Every device
+ * identifies as absent, and every syscall, including those made for bulk
+ * output and host files, fails with ENOSYS, so that neither waits forever.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::cell::{Cell, RefCell};
+use core::ptr::write_volatile;
+use super::{DEVICE_SYSCALL, COMMAND_READ_CHAR, COMMAND_WRITE_CHAR, CHAR_VALID};
+use super::protocol::{FromHostReply, DEVICE_SHIFT, COMMAND_SHIFT, PAYLOAD_MASK};
+use super::mock::Queue;
+
+const COMMAND_IDENTIFY: u64 = 0xff;
+const SYSCALL_DONE: u64 = 1;
+const ENOSYS: u64 = 38;
+
+/* the most replies that can wait their turn behind the one in fromhost */
+const MAX_QUEUED: usize = 4;
+
+/* the operations an emulated host relies on */
+pub(crate) trait Backend
+{
+    /* print a character */
+    fn put_byte(&self, byte: u8);
+
+    /* return a character if one is available */
+    fn get_byte(&self) -> Option<u8>;
+
+    /* end the run with the given exit code, zero meaning success */
+    fn exit(&self, code: u64);
+}
+
+#[derive(Debug)]
+pub(crate) struct EmulatedHost
+{
+    read_pending: Cell<Option<u8>>, /* device the driver is waiting on a character from, if any */
+    reply: Cell<u64>, /* reply waiting in our pretend fromhost, or zero */
+    queued: RefCell<Queue<MAX_QUEUED>> /* replies waiting for fromhost to be free */
+}
+
+impl EmulatedHost
+{
+    pub(crate) const fn new() -> Self
+    {
+        EmulatedHost { read_pending: Cell::new(None), reply: Cell::new(0), queued: RefCell::new(Queue::new()) }
+    }
+
+    /* every request is carried out the moment it's made, so tohost is always empty */
+    pub(crate) fn read_to_host(&self) -> u64
+    {
+        0
+    }
+
+    pub(crate) fn write_to_host(&self, backend: &impl Backend, val: u64)
+    {
+        /* zero is no request at all, but the driver withdrawing one, or recovering */
+        if val == 0
+        {
+            return;
+        }
+
+        let device = val >> DEVICE_SHIFT;
+        let command = (val >> COMMAND_SHIFT) & 0xff;
+        let payload = val & PAYLOAD_MASK;
+
+        match (device, command)
+        {
+            (DEVICE_SYSCALL, 0) if payload & 1 == 1 => backend.exit(payload >> 1),
+            (DEVICE_SYSCALL, 0) =>
+            {
+                /* the payload is the address of the block the driver filled in,
+                   whose first word takes the result */
+                unsafe { write_volatile(payload as *mut u64, ENOSYS.wrapping_neg()) };
+                self.reply(DEVICE_SYSCALL, 0, SYSCALL_DONE);
+            },
+
+            /* leave the identity blank, which marks the device absent */
+            (_, COMMAND_IDENTIFY) => self.reply(device, command, 0),
+            (_, COMMAND_WRITE_CHAR) => backend.put_byte(payload as u8),
+            (_, COMMAND_READ_CHAR) => self.read_pending.set(Some(device as u8)),
+            _ => ()
+        }
+    }
+
+    /* post a reply, which waits its turn if the driver has yet to take the last one */
+    fn reply(&self, device: u64, command: u64, payload: u64)
+    {
+        let reply = FromHostReply { device: device as u8, command: command as u8, payload }.encode();
+        match self.reply.get()
+        {
+            0 => self.reply.set(reply),
+            _ => { self.queued.borrow_mut().push(reply); }
+        }
+    }
+
+    /* post the next queued reply, or answer a pending console read once the backend
+       has a character for it */
+    pub(crate) fn read_from_host(&self, backend: &impl Backend) -> u64
+    {
+        if self.reply.get() == 0
+        {
+            let queued = self.queued.borrow_mut().pop();
+            match (queued, self.read_pending.get())
+            {
+                (Some(reply), _) => self.reply.set(reply),
+                (None, Some(device)) =>
+                {
+                    if let Some(byte) = backend.get_byte()
+                    {
+                        let reply = FromHostReply { device, command: COMMAND_READ_CHAR as u8, payload: CHAR_VALID | byte as u64 };
+                        self.reply.set(reply.encode());
+                        self.read_pending.set(None);
+                    }
+                },
+                (None, None) => ()
+            }
+        }
+
+        self.reply.get()
+    }
+
+    pub(crate) fn write_from_host(&self, val: u64)
+    {
+        self.reply.set(val);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{HTIF, Fault, DEVICE_CHARIO};
+    use crate::syscall::SYS_CLOSE;
+    use crate::transport::HtifTransport;
+
+    struct Script
+    {
+        printed: RefCell<Vec<u8>>,
+        typed: Cell<Option<u8>>,
+        exited: Cell<Option<u64>>
+    }
+
+    impl Backend for Script
+    {
+        fn put_byte(&self, byte: u8) { self.printed.borrow_mut().push(byte) }
+        fn get_byte(&self) -> Option<u8> { self.typed.take() }
+        fn exit(&self, code: u64) { self.exited.set(Some(code)) }
+    }
+
+    /* a transport over the script, as SbiConsole is over SBI */
+    struct Emulated
+    {
+        script: Script,
+        host: EmulatedHost
+    }
+
+    impl HtifTransport for Emulated
+    {
+        fn read_to_host(&self) -> u64 { self.host.read_to_host() }
+        fn write_to_host(&self, val: u64) { self.host.write_to_host(&self.script, val) }
+        fn read_from_host(&self) -> u64 { self.host.read_from_host(&self.script) }
+        fn write_from_host(&self, val: u64) { self.host.write_from_host(val) }
+    }
+
+    #[test]
+    fn console()
+    {
+        let script = Script { printed: RefCell::new(Vec::new()), typed: Cell::new(None), exited: Cell::new(None) };
+        let host = EmulatedHost::new();
+
+        host.write_to_host(&script, (DEVICE_CHARIO << DEVICE_SHIFT) | (COMMAND_WRITE_CHAR << COMMAND_SHIFT) | b'a' as u64);
+        assert_eq!(*script.printed.borrow(), b"a");
+
+        host.write_to_host(&script, (DEVICE_CHARIO << DEVICE_SHIFT) | (COMMAND_READ_CHAR << COMMAND_SHIFT));
+        assert_eq!(host.read_from_host(&script), 0);
+        script.typed.set(Some(b'z'));
+        let reply = FromHostReply::decode(host.read_from_host(&script));
+        assert_eq!(reply.payload, CHAR_VALID | b'z' as u64);
+        host.write_from_host(0);
+        assert_eq!(host.read_from_host(&script), 0);
+
+        host.write_to_host(&script, (3 << 1) | 1);
+        assert_eq!(script.exited.get(), Some(3));
+    }
+
+    #[test]
+    fn other_console()
+    {
+        let script = Script { printed: RefCell::new(Vec::new()), typed: Cell::new(Some(b'q')), exited: Cell::new(None) };
+        let htif = HTIF::over(Emulated { script, host: EmulatedHost::new() }).with_console(3);
+        assert_eq!(htif.send_bytes(b"hi"), Ok(()));
+        assert_eq!(*htif.transport().script.printed.borrow(), b"hi");
+
+        let mut htif = htif;
+        assert_eq!(htif.read_byte(), Ok(b'q'));
+    }
+
+    #[test]
+    fn syscalls_fail_fast()
+    {
+        let script = Script { printed: RefCell::new(Vec::new()), typed: Cell::new(None), exited: Cell::new(None) };
+        let mut htif = HTIF::over(Emulated { script, host: EmulatedHost::new() }).with_bulk_output(1);
+        assert_eq!(htif.proxy_syscall(SYS_CLOSE, &[3]), Err(Fault::HostError(ENOSYS)));
+        assert_eq!(htif.send_bytes(b"bulk"), Err(Fault::HostError(ENOSYS)));
+        assert_eq!(htif.identify(2).map(|identity| identity.is_empty()), Ok(true));
+        assert!(htif.transport().script.printed.borrow().is_empty());
+    }
+}
@@ -0,0 +1,71 @@
+/* Ending the simulation on hosts other than Spike
+ *
+ * Under Spike, exit() and report_fail() go through HTIF's syscall device. QEMU's
+ * RISC-V machines have no HTIF, but provide a sifive_test register that ends
+ * or resets the emulator when written. Selecting it at construction keeps the
+ * same exit(), report_pass(), report_fail(), and reset() calls working there.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::ptr::write_volatile;
+
+/* address of the sifive_test register on QEMU's virt machine */
+pub const QEMU_VIRT_TEST_BASE: usize = 0x10_0000;
+
+/* values understood by sifive_test. a failure carries its exit code in the upper 16 bits */
+const TEST_PASS: u32 = 0x5555;
+const TEST_FAIL: u32 = 0x3333;
+const TEST_RESET: u32 = 0x7777;
+const TEST_CODE_SHIFT: u32 = 16;
+const TEST_CODE_MAX: u32 = 0xffff;
+
+/* where requests to end or restart the simulation go */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitDevice
+{
+    Htif, /* HTIF's syscall device, as under Spike */
+    SifiveTest(*mut u32) /* a sifive_test register, as under QEMU */
+}
+
+/* the register address is only written, once, to end the simulation */
+unsafe impl Send for ExitDevice {}
+
+/* return what to write to sifive_test to exit with the given code. codes too
+   large for the register are clamped, so that a failure can't read as a pass */
+fn sifive_test_exit(code: u32) -> u32
+{
+    match code
+    {
+        0 => TEST_PASS,
+        _ => (code.min(TEST_CODE_MAX) << TEST_CODE_SHIFT) | TEST_FAIL
+    }
+}
+
+/* end the simulation via a sifive_test register. unsafe as reg must point to one */
+pub(crate) unsafe fn sifive_test_write_exit(reg: *mut u32, code: u32)
+{
+    write_volatile(reg, sifive_test_exit(code));
+}
+
+/* restart the simulation via a sifive_test register. unsafe as reg must point to one */
+pub(crate) unsafe fn sifive_test_write_reset(reg: *mut u32)
+{
+    write_volatile(reg, TEST_RESET);
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn exit_codes()
+    {
+        assert_eq!(sifive_test_exit(0), 0x5555);
+        assert_eq!(sifive_test_exit(3), 0x3_3333);
+        assert_eq!(sifive_test_exit(0x1_0000), 0xffff_3333);
+    }
+}
@@ -0,0 +1,270 @@
+/* Find HTIF in a flattened device tree
+ *
+ * Spike tells the payloads it boots about HTIF with a node in the device tree
+ * it passes in a1, compatible with "ucb,htif0". Spike itself finds tohost and
+ * fromhost in the payload's symbol table, so its node has no reg property, and
+ * the driver uses the linker's registers. Other hosts, such as FPGA bridges to
+ * fesvr, give the registers a fixed address instead, and list it in the node's
+ * reg property: tohost at the address and fromhost in the word after it.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::slice;
+use super::{HTIF, Fault, REG_TOTAL_SIZE};
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_VERSION: u32 = 17; /* the version this parser understands, and those compatible with it */
+const FDT_HEADER_SIZE: usize = 40;
+
+/* tokens in the structure block */
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/* the defaults for nodes without #address-cells and #size-cells */
+const DEFAULT_ADDRESS_CELLS: u32 = 2;
+const DEFAULT_SIZE_CELLS: u32 = 1;
+
+/* nodes nested deeper than this are skipped over */
+const MAX_DEPTH: usize = 16;
+
+const HTIF_NODE_NAME: &[u8] = b"htif";
+const HTIF_COMPATIBLE: &[u8] = b"ucb,htif0";
+
+/* look for HTIF in the given device tree blob. returns the address of the
+   registers if the node has a reg property, or None if the linker's should be
+   used, as under Spike. fails with NotPresent if there's no HTIF node, and with
+   InvalidResponse if the blob is malformed */
+pub fn find(dtb: &[u8]) -> Result<Option<u64>, Fault>
+{
+    if read_u32(dtb, 0)? != FDT_MAGIC || read_u32(dtb, 24)? > FDT_VERSION
+    {
+        return Err(Fault::InvalidResponse);
+    }
+
+    let structs = read_u32(dtb, 8)? as usize;
+    let strings = read_u32(dtb, 12)? as usize;
+
+    /* the #address-cells and #size-cells set by each node on the path to the
+       current one, which apply to that node's children */
+    let mut cells = [(DEFAULT_ADDRESS_CELLS, DEFAULT_SIZE_CELLS); MAX_DEPTH];
+    let mut depth = 0;
+
+    /* what's been seen of the current node so far */
+    let mut is_htif = false;
+    let mut reg = None;
+
+    let mut offset = structs;
+    loop
+    {
+        let token = read_u32(dtb, offset)?;
+        offset += 4;
+
+        /* a node's properties all come before its children, so it's fully
+           described as soon as another node begins or it ends */
+        if (token == FDT_BEGIN_NODE || token == FDT_END_NODE) && is_htif
+        {
+            return reg.transpose();
+        }
+
+        match token
+        {
+            FDT_BEGIN_NODE =>
+            {
+                let name = read_str(dtb, offset)?;
+                offset += align(name.len() + 1);
+
+                if depth < MAX_DEPTH
+                {
+                    cells[depth] = (DEFAULT_ADDRESS_CELLS, DEFAULT_SIZE_CELLS);
+                }
+                depth += 1;
+
+                is_htif = node_name(name) == HTIF_NODE_NAME;
+                reg = None;
+            },
+
+            FDT_END_NODE =>
+            {
+                depth = depth.checked_sub(1).ok_or(Fault::InvalidResponse)?;
+                is_htif = false;
+                reg = None;
+            },
+
+            FDT_PROP =>
+            {
+                let len = read_u32(dtb, offset)? as usize;
+                let name = read_str(dtb, strings + read_u32(dtb, offset + 4)? as usize)?;
+                offset += 8;
+                let value = dtb.get(offset..offset + len).ok_or(Fault::InvalidResponse)?;
+                offset += align(len);
+
+                /* our own cell counts, and those our parent set for us */
+                let ours = depth.checked_sub(1).filter(|&d| d < MAX_DEPTH);
+                let parents = depth.checked_sub(2).filter(|&d| d < MAX_DEPTH);
+
+                match (name, ours)
+                {
+                    (b"#address-cells", Some(d)) => cells[d].0 = read_u32(value, 0)?,
+                    (b"#size-cells", Some(d)) => cells[d].1 = read_u32(value, 0)?,
+                    (b"compatible", _) => is_htif |= value.split(|&c| c == 0).any(|s| s == HTIF_COMPATIBLE),
+                    (b"reg", _) =>
+                    {
+                        let (address_cells, _) = parents.map(|d| cells[d])
+                            .unwrap_or((DEFAULT_ADDRESS_CELLS, DEFAULT_SIZE_CELLS));
+                        reg = Some(read_cells(value, address_cells));
+                    },
+                    _ => ()
+                }
+            },
+
+            FDT_NOP => (),
+            FDT_END => return Err(Fault::NotPresent),
+            _ => return Err(Fault::InvalidResponse)
+        }
+    }
+}
+
+/* read an address of the given number of cells from the start of a property */
+fn read_cells(value: &[u8], address_cells: u32) -> Result<u64, Fault>
+{
+    match address_cells
+    {
+        1 => Ok(read_u32(value, 0)? as u64),
+        2 => Ok(((read_u32(value, 0)? as u64) << 32) | read_u32(value, 4)? as u64),
+        _ => Err(Fault::Unsupported)
+    }
+}
+
+/* return a node's name without its unit address */
+fn node_name(name: &[u8]) -> &[u8]
+{
+    name.split(|&c| c == b'@').next().unwrap_or(name)
+}
+
+/* round up to the 4-byte alignment of everything in the structure block */
+fn align(len: usize) -> usize
+{
+    (len + 3) & !3
+}
+
+/* read a big-endian word */
+fn read_u32(blob: &[u8], offset: usize) -> Result<u32, Fault>
+{
+    match blob.get(offset..offset + 4)
+    {
+        Some(word) => Ok(u32::from_be_bytes([word[0], word[1], word[2], word[3]])),
+        None => Err(Fault::InvalidResponse)
+    }
+}
+
+/* read a nul-terminated string, without its terminator */
+fn read_str(blob: &[u8], offset: usize) -> Result<&[u8], Fault>
+{
+    let rest = blob.get(offset..).ok_or(Fault::InvalidResponse)?;
+    let len = rest.iter().position(|&c| c == 0).ok_or(Fault::InvalidResponse)?;
+    Ok(&rest[..len])
+}
+
+impl HTIF
+{
+    /* use the registers described by the HTIF node in the device tree at the given
+       address, such as the one Spike passes in a1, or the linker's if the node has
+       no reg property. fails as find() does if the node can't be found. this is unsafe
+       as the address must point to a device tree, and any registers it lists must be
+       the host's */
+    pub unsafe fn from_fdt(dtb: *const u8) -> Result<Self, Fault>
+    {
+        let header = slice::from_raw_parts(dtb, FDT_HEADER_SIZE);
+        if read_u32(header, 0)? != FDT_MAGIC
+        {
+            return Err(Fault::InvalidResponse);
+        }
+
+        let size = read_u32(header, 4)? as usize;
+        match find(slice::from_raw_parts(dtb, size))?
+        {
+            Some(base) =>
+            {
+                let to_host = base as usize as *mut u64;
+                HTIF::at(to_host, (base as usize + REG_TOTAL_SIZE / 2) as *mut u64)
+            },
+            None => HTIF::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /* build a device tree blob with the given structure block and strings */
+    fn blob(structs: &[u32], strings: &[u8]) -> Vec<u8>
+    {
+        let structs_offset = FDT_HEADER_SIZE as u32;
+        let strings_offset = structs_offset + structs.len() as u32 * 4;
+        let total = strings_offset + strings.len() as u32;
+        let header = [FDT_MAGIC, total, structs_offset, strings_offset, 0, 17, 16, 0, strings.len() as u32, structs.len() as u32 * 4];
+
+        let mut dtb = Vec::new();
+        for word in header.iter().chain(structs.iter())
+        {
+            dtb.extend_from_slice(&word.to_be_bytes());
+        }
+        dtb.extend_from_slice(strings);
+        dtb
+    }
+
+    /* turn a string into nul-terminated, padded, big-endian words */
+    fn words(s: &str) -> Vec<u32>
+    {
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.resize(align(bytes.len() + 1), 0);
+        bytes.chunks(4).map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]])).collect()
+    }
+
+    const STRINGS: &[u8] = b"#address-cells\0compatible\0reg\0";
+    const ADDRESS_CELLS: u32 = 0;
+    const COMPATIBLE: u32 = 15;
+    const REG: u32 = 26;
+
+    fn tree(address_cells: u32, node: &str, reg: &[u32]) -> Vec<u8>
+    {
+        let mut s = vec![FDT_BEGIN_NODE, 0, FDT_PROP, 4, ADDRESS_CELLS, address_cells, FDT_BEGIN_NODE];
+        s.extend(words(node));
+        s.extend([FDT_PROP, 10, COMPATIBLE]);
+        s.extend(words("ucb,htif0"));
+        if !reg.is_empty()
+        {
+            s.extend([FDT_PROP, reg.len() as u32 * 4, REG]);
+            s.extend(reg);
+        }
+        s.extend([FDT_END_NODE, FDT_END_NODE, FDT_END]);
+        blob(&s, STRINGS)
+    }
+
+    #[test]
+    fn find_htif()
+    {
+        /* as Spike describes it */
+        assert_eq!(find(&tree(2, "htif", &[])), Ok(None));
+
+        /* at a fixed address, with one and two address cells */
+        assert_eq!(find(&tree(1, "htif@4000", &[0x4000, 0x10])), Ok(Some(0x4000)));
+        assert_eq!(find(&tree(2, "htif@100004000", &[1, 0x4000, 0x10])), Ok(Some(0x1_0000_4000)));
+
+        /* found by compatible alone */
+        assert_eq!(find(&tree(1, "host@4000", &[0x4000, 0x10])), Ok(Some(0x4000)));
+
+        let mut bad = tree(2, "htif", &[]);
+        bad[0] = 0;
+        assert_eq!(find(&bad), Err(Fault::InvalidResponse));
+        assert_eq!(find(&blob(&[FDT_BEGIN_NODE, 0, FDT_END_NODE, FDT_END], STRINGS)), Err(Fault::NotPresent));
+    }
+}
@@ -0,0 +1,292 @@
+/* A software model of Spike's frontend server, fesvr, for host-side tests
+ *
+ * Where MockHtif replays a script, Fesvr behaves like the real thing, so code
+ * built on the driver can be tested end to end with cargo test, without Spike:
+ *
+ * let mut htif = HTIF::over(Fesvr::new());
+ * htif.transport().type_input(b"y");
+ * let answer = htif.read_byte()?;
+ * writeln!(htif, "continue? {}", answer as char)?;
+ * assert_eq!(&*htif.transport().output(), b"continue? y\n");
+ *
+ * It takes each request the moment it's written to tohost, and posts replies
+ * through fromhost one at a time, as fesvr does. It models:
+ *
+ *  the console, device 1, printing to an output buffer, and answering reads
+ *  from typed-in input, holding a read until there's input for it
+ *  device 0's exit requests, recording the exit code
+ *  device 0's write syscall to stdout and stderr, which go to the output buffer
+ *  device 0's read syscall from stdin, which takes typed-in input. stdin acts
+ *  as though it's non-blocking, failing with EAGAIN if there's no input, or
+ *  returning 0 once end_input() has been called and the input is used up.
+ *  fcntl() is accepted, and changes nothing. other syscalls fail with ENOSYS
+ *  identify, which is answered, but leaves the identity blank, as in a host
+ *  test its buffer's address doesn't fit in the request. every device appears
+ *  absent, though probe() succeeds
+ *
+ * Syscall arguments are read from, and results written to, the addresses in
+ * the requests, so only use this where those are the addresses of real memory,
+ * as they are in host tests. Output beyond N bytes, and replies that back up
+ * beyond N, are lost.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::cell::{Cell, Ref, RefCell};
+use core::ptr::{read_volatile, write_volatile};
+use super::{DEVICE_SYSCALL, DEVICE_CHARIO, COMMAND_READ_CHAR, COMMAND_WRITE_CHAR, CHAR_VALID};
+use super::protocol::{FromHostReply, DEVICE_SHIFT, COMMAND_SHIFT, PAYLOAD_MASK};
+use super::mock::Queue;
+use super::syscall::{SYS_READ, SYS_WRITE, SYS_FCNTL};
+use super::transport::HtifTransport;
+
+const COMMAND_IDENTIFY: u64 = 0xff;
+const SYSCALL_DONE: u64 = 1;
+const EAGAIN: u64 = 11;
+const ENOSYS: u64 = 38;
+const STDIN: u64 = 0;
+const STDOUT: u64 = 1;
+const STDERR: u64 = 2;
+
+/* bytes printed so far */
+#[derive(Debug)]
+struct Output<const N: usize>
+{
+    bytes: [u8; N],
+    len: usize
+}
+
+#[derive(Debug)]
+pub struct Fesvr<const N: usize = 4096>
+{
+    from_host: Cell<u64>, /* the reply the driver has yet to acknowledge, or zero */
+    replies: RefCell<Queue<N>>, /* replies waiting for fromhost to be free */
+    input: RefCell<Queue<N>>, /* characters typed but not yet read */
+    read_pending: Cell<bool>, /* true if the driver is waiting on a character */
+    input_ended: Cell<bool>, /* true if there will be no more input than what's typed */
+    output: RefCell<Output<N>>,
+    exit_code: Cell<Option<u32>>
+}
+
+impl<const N: usize> Default for Fesvr<N>
+{
+    fn default() -> Self
+    {
+        Fesvr::new()
+    }
+}
+
+impl<const N: usize> Fesvr<N>
+{
+    pub fn new() -> Self
+    {
+        Fesvr
+        {
+            from_host: Cell::new(0),
+            replies: RefCell::new(Queue::new()),
+            input: RefCell::new(Queue::new()),
+            read_pending: Cell::new(false),
+            input_ended: Cell::new(false),
+            output: RefCell::new(Output { bytes: [0; N], len: 0 }),
+            exit_code: Cell::new(None)
+        }
+    }
+
+    /* add characters to the input, as if typed at the host's terminal */
+    pub fn type_input(&self, bytes: &[u8])
+    {
+        for byte in bytes
+        {
+            self.input.borrow_mut().push(*byte as u64);
+        }
+        self.answer_read();
+    }
+
+    /* mark the end of the input, as if stdin were a file, so that once what's
+       been typed is read, stdin reads return 0 */
+    pub fn end_input(&self)
+    {
+        self.input_ended.set(true);
+    }
+
+    /* return everything printed so far, via the console or the write syscall */
+    pub fn output(&self) -> Ref<'_, [u8]>
+    {
+        Ref::map(self.output.borrow(), |output| &output.bytes[..output.len])
+    }
+
+    /* forget everything printed so far */
+    pub fn clear_output(&self)
+    {
+        self.output.borrow_mut().len = 0;
+    }
+
+    /* return the exit code the simulation was asked to end with, if it has been */
+    pub fn exit_code(&self) -> Option<u32>
+    {
+        self.exit_code.get()
+    }
+
+    fn print(&self, bytes: &[u8])
+    {
+        let mut output = self.output.borrow_mut();
+        let len = output.len;
+        let room = (N - len).min(bytes.len());
+        output.bytes[len..len + room].copy_from_slice(&bytes[..room]);
+        output.len += room;
+    }
+
+    /* post a reply, which waits its turn if the driver has yet to take the last one */
+    fn reply(&self, device: u64, command: u64, payload: u64)
+    {
+        let reply = FromHostReply { device: device as u8, command: command as u8, payload }.encode();
+        match self.from_host.get()
+        {
+            0 => self.from_host.set(reply),
+            _ => { self.replies.borrow_mut().push(reply); }
+        }
+    }
+
+    /* answer a held console read if there's a character for it */
+    fn answer_read(&self)
+    {
+        if self.read_pending.get()
+        {
+            let byte = self.input.borrow_mut().pop();
+            if let Some(byte) = byte
+            {
+                self.read_pending.set(false);
+                self.reply(DEVICE_CHARIO, COMMAND_READ_CHAR, CHAR_VALID | byte);
+            }
+        }
+    }
+
+    /* carry out the syscall described by the block of words at the given address */
+    fn syscall(&self, block: *mut u64)
+    {
+        let arg = |index| unsafe { read_volatile(block.add(index)) };
+        let result = match (arg(0), arg(1))
+        {
+            (SYS_WRITE, STDOUT) | (SYS_WRITE, STDERR) =>
+            {
+                let bytes = unsafe { core::slice::from_raw_parts(arg(2) as *const u8, arg(3) as usize) };
+                self.print(bytes);
+                bytes.len() as u64
+            },
+            (SYS_READ, STDIN) =>
+            {
+                let buf = unsafe { core::slice::from_raw_parts_mut(arg(2) as *mut u8, arg(3) as usize) };
+                let mut input = self.input.borrow_mut();
+                let mut read = 0;
+                while let Some(slot) = buf.get_mut(read)
+                {
+                    match input.pop()
+                    {
+                        Some(byte) => *slot = byte as u8,
+                        None => break
+                    }
+                    read += 1;
+                }
+
+                match (read, self.input_ended.get())
+                {
+                    (0, false) if !buf.is_empty() => EAGAIN.wrapping_neg(),
+                    _ => read as u64
+                }
+            },
+            (SYS_FCNTL, STDIN) => 0,
+            _ => ENOSYS.wrapping_neg()
+        };
+
+        unsafe { write_volatile(block, result) };
+        self.reply(DEVICE_SYSCALL, 0, SYSCALL_DONE);
+    }
+}
+
+impl<const N: usize> HtifTransport for Fesvr<N>
+{
+    /* every request is taken the moment it's made */
+    fn read_to_host(&self) -> u64
+    {
+        0
+    }
+
+    fn write_to_host(&self, val: u64)
+    {
+        /* zero is no request at all, but the driver withdrawing one, or recovering */
+        if val == 0
+        {
+            return;
+        }
+
+        let device = val >> DEVICE_SHIFT;
+        let command = (val >> COMMAND_SHIFT) & 0xff;
+        let payload = val & PAYLOAD_MASK;
+
+        match (device, command)
+        {
+            (_, COMMAND_IDENTIFY) => self.reply(device, command, 0),
+            (DEVICE_SYSCALL, 0) if payload & 1 == 1 => self.exit_code.set(Some((payload >> 1) as u32)),
+            (DEVICE_SYSCALL, 0) => self.syscall(payload as *mut u64),
+            (DEVICE_CHARIO, COMMAND_WRITE_CHAR) => self.print(&[payload as u8]),
+            (DEVICE_CHARIO, COMMAND_READ_CHAR) =>
+            {
+                self.read_pending.set(true);
+                self.answer_read();
+            },
+            _ => ()
+        }
+    }
+
+    fn read_from_host(&self) -> u64
+    {
+        self.from_host.get()
+    }
+
+    /* zeroing fromhost acknowledges the reply in it, making way for the next */
+    fn write_from_host(&self, _val: u64)
+    {
+        let next = self.replies.borrow_mut().pop().unwrap_or(0);
+        self.from_host.set(next);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use core::fmt::Write;
+    use crate::HTIF;
+    use crate::file::HostFile;
+    use crate::newline::Newlines;
+
+    #[test]
+    fn full_stack()
+    {
+        let mut htif = HTIF::over(Fesvr::<256>::new()).with_newlines(Newlines::TERMINAL).probed().unwrap();
+
+        htif.transport().type_input(b"y\r");
+        let answer = htif.read_byte().unwrap();
+        assert_eq!(htif.read_byte(), Ok(b'\n'));
+        writeln!(htif, "continue? {}", answer as char).unwrap();
+
+        assert_eq!(HostFile::stderr().write(&mut htif, b"oops"), Ok(4));
+        assert_eq!(&*htif.transport().output(), b"continue? y\r\noops");
+
+        /* as exit() would, without the wait for the end */
+        assert_eq!(htif.transport().exit_code(), None);
+        assert_eq!(htif.write_to_host((3 << 1) | 1), Ok(()));
+        assert_eq!(htif.transport().exit_code(), Some(3));
+    }
+
+    #[test]
+    fn recover_is_no_request()
+    {
+        let mut htif = HTIF::over(Fesvr::<16>::new());
+        htif.recover();
+        assert_eq!(htif.send_bytes(b"ok"), Ok(()));
+        assert_eq!(&*htif.transport().output(), b"ok");
+    }
+}
@@ -0,0 +1,133 @@
+/* Access files on the host's filesystem via the syscall proxy
+ *
+ * Paths are relative to the directory Spike was started in, unless absolute.
+ * Buffers passed to read() and write() must be at physical addresses, as the
+ * host accesses them directly.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+use super::syscall::{SYS_OPENAT, SYS_CLOSE, SYS_READ, SYS_WRITE, SYS_PREAD, SYS_PWRITE, SYS_LSEEK};
+
+/* flags for open(), from Linux's generic ABI. combine with | */
+pub const O_RDONLY: u64 = 0;
+pub const O_WRONLY: u64 = 1;
+pub const O_RDWR:   u64 = 2;
+pub const O_CREAT:  u64 = 0o100;
+pub const O_EXCL:   u64 = 0o200;
+pub const O_TRUNC:  u64 = 0o1000;
+pub const O_APPEND: u64 = 0o2000;
+
+/* permissions given to files created by open() */
+const CREATE_MODE: u64 = 0o644;
+
+/* tells openat() to work relative to the host's current directory */
+const AT_FDCWD: u64 = -100i64 as u64;
+
+/* longest path, including its NUL terminator, that can be passed to the host */
+const MAX_PATH_LEN: usize = 256;
+
+/* whence values for lseek() */
+const SEEK_SET: u64 = 0;
+const SEEK_CUR: u64 = 1;
+const SEEK_END: u64 = 2;
+
+/* where to move a file's offset to with seek() */
+#[derive(Debug, Clone, Copy)]
+pub enum SeekFrom
+{
+    Start(u64),   /* this many bytes from the start of the file */
+    Current(i64), /* this many bytes from the current offset */
+    End(i64)      /* this many bytes from the end of the file */
+}
+
+/* an open file on the host, identified by its host file descriptor. nothing is
+   closed automatically: call close() when done to release the host's descriptor */
+#[derive(Debug)]
+pub struct HostFile
+{
+    fd: u64
+}
+
+impl HostFile
+{
+    /* open the file at path on the host using the given O_* flags */
+    pub fn open(htif: &mut HTIF<impl HtifTransport>, path: &str, flags: u64) -> Result<Self, Fault>
+    {
+        /* the host expects a NUL-terminated string along with its length */
+        let path = path.as_bytes();
+        if path.len() >= MAX_PATH_LEN
+        {
+            return Err(Fault::PathTooLong);
+        }
+
+        let mut name = [0u8; MAX_PATH_LEN];
+        name[..path.len()].copy_from_slice(path);
+
+        let fd = htif.proxy_syscall(SYS_OPENAT,
+            &[AT_FDCWD, name.as_ptr() as u64, path.len() as u64 + 1, flags, CREATE_MODE])?;
+
+        Ok(HostFile { fd })
+    }
+
+    /* the host's standard input, output, and error streams, which are always open */
+    pub fn stdin() -> Self { HostFile { fd: 0 } }
+    pub fn stdout() -> Self { HostFile { fd: 1 } }
+    pub fn stderr() -> Self { HostFile { fd: 2 } }
+
+    /* read up to buf.len() bytes from the file into buf.
+       returns the number of bytes read, which is zero at the end of the file */
+    pub fn read(&self, htif: &mut HTIF<impl HtifTransport>, buf: &mut [u8]) -> Result<usize, Fault>
+    {
+        let read = htif.proxy_syscall(SYS_READ, &[self.fd, buf.as_mut_ptr() as u64, buf.len() as u64])?;
+        Ok(read as usize)
+    }
+
+    /* write up to buf.len() bytes from buf to the file.
+       returns the number of bytes written */
+    pub fn write(&self, htif: &mut HTIF<impl HtifTransport>, buf: &[u8]) -> Result<usize, Fault>
+    {
+        let written = htif.proxy_syscall(SYS_WRITE, &[self.fd, buf.as_ptr() as u64, buf.len() as u64])?;
+        Ok(written as usize)
+    }
+
+    /* read up to buf.len() bytes into buf from the given offset in the file,
+       without moving the file's offset. returns the number of bytes read */
+    pub fn read_at(&self, htif: &mut HTIF<impl HtifTransport>, buf: &mut [u8], offset: u64) -> Result<usize, Fault>
+    {
+        let read = htif.proxy_syscall(SYS_PREAD, &[self.fd, buf.as_mut_ptr() as u64, buf.len() as u64, offset])?;
+        Ok(read as usize)
+    }
+
+    /* write up to buf.len() bytes from buf to the given offset in the file,
+       without moving the file's offset. returns the number of bytes written */
+    pub fn write_at(&self, htif: &mut HTIF<impl HtifTransport>, buf: &[u8], offset: u64) -> Result<usize, Fault>
+    {
+        let written = htif.proxy_syscall(SYS_PWRITE, &[self.fd, buf.as_ptr() as u64, buf.len() as u64, offset])?;
+        Ok(written as usize)
+    }
+
+    /* move the file's offset, used by read() and write(). returns the new offset from the start of the file */
+    pub fn seek(&self, htif: &mut HTIF<impl HtifTransport>, pos: SeekFrom) -> Result<u64, Fault>
+    {
+        let (offset, whence) = match pos
+        {
+            SeekFrom::Start(offset) => (offset, SEEK_SET),
+            SeekFrom::Current(offset) => (offset as u64, SEEK_CUR),
+            SeekFrom::End(offset) => (offset as u64, SEEK_END)
+        };
+
+        htif.proxy_syscall(SYS_LSEEK, &[self.fd, offset, whence])
+    }
+
+    /* close the file, releasing its host file descriptor */
+    pub fn close(self, htif: &mut HTIF<impl HtifTransport>) -> Result<(), Fault>
+    {
+        htif.proxy_syscall(SYS_CLOSE, &[self.fd])?;
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+/* Formatted output that reports why it failed, and never panics
+ *
+ * write!() on the driver maps every failure to fmt::Error, which says nothing
+ * of what went wrong. try_write_fmt() returns the Fault instead, so the
+ * caller can decide whether to recover() and try again, or give up quietly.
+ *
+ * Nothing on the console's write path panics, so this, and write!(), are safe
+ * to use from panic and trap handlers: faults are returned rather than
+ * unwrapped, slices are only taken within bounds already checked, and
+ * counters wrap rather than overflow. Formatting the arguments can
+ * still panic if one of their Display or Debug implementations does, which is
+ * beyond the driver's control.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::fmt;
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+
+/* passes output to the driver, remembering the first fault. that's the one that matters,
+   as an argument's formatting may carry on writing after an error, and fail again */
+struct Recorder<'a, T>
+{
+    htif: &'a mut HTIF<T>,
+    fault: Option<Fault>
+}
+
+impl<T: HtifTransport> fmt::Write for Recorder<'_, T>
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result
+    {
+        self.htif.write_all(s.as_bytes()).map_err(|e|
+        {
+            self.fault.get_or_insert(e.fault);
+            fmt::Error
+        })
+    }
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* write formatted output to the host console, such as try_write_fmt(format_args!(...)),
+       stopping at the first fault and returning it. fails with Format if the failure was
+       one of the arguments' own formatting, rather than the host's */
+    pub fn try_write_fmt(&mut self, args: fmt::Arguments) -> Result<(), Fault>
+    {
+        let mut recorder = Recorder { htif: self, fault: None };
+        match fmt::write(&mut recorder, args)
+        {
+            Ok(()) => Ok(()),
+            Err(_) => Err(recorder.fault.unwrap_or(Fault::Format))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::mock::MockHtif;
+
+    #[test]
+    fn fault_returned()
+    {
+        let mut htif = HTIF::over(MockHtif::<8>::new()).with_timeout(10);
+        assert_eq!(htif.try_write_fmt(format_args!("{}", 1)), Ok(()));
+
+        htif.transport().set_stalled(true);
+        /* the host never takes the first byte, so the second can't be sent */
+        assert_eq!(htif.try_write_fmt(format_args!("{}", 23)), Err(Fault::Busy));
+    }
+
+    #[test]
+    fn argument_fault()
+    {
+        struct Broken;
+        impl fmt::Display for Broken
+        {
+            fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result
+            {
+                Err(fmt::Error)
+            }
+        }
+
+        let mut htif = HTIF::over(MockHtif::<8>::new());
+        assert_eq!(htif.try_write_fmt(format_args!("{}", Broken)), Err(Fault::Format));
+    }
+}
@@ -0,0 +1,171 @@
+/* Display graphics via the HTIF framebuffer device
+ *
+ * The host identifies the device as "rfb" and displays the contents of a
+ * framebuffer in target memory, which it reads directly and redraws by itself.
+ * The frame's resolution and color depth are configured once, then the host is
+ * told where the framebuffer is. Pointing the host at a different buffer flips
+ * the display to it, which allows double buffering.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::protocol::encode_command;
+use super::transport::HtifTransport;
+
+const COMMAND_CONFIGURE:   u64 = 0; /* set the resolution and color depth */
+const COMMAND_SET_ADDRESS: u64 = 1; /* set the framebuffer's physical address */
+
+/* the host acknowledges commands with this payload */
+const COMMAND_DONE: u64 = 1;
+
+/* the configure payload packs the width into bits 15-0, height into 31-16,
+   and bits per pixel from bit 32 */
+const HEIGHT_SHIFT: u64 = 16;
+const BPP_SHIFT:    u64 = 32;
+
+/* the host's identity for framebuffer devices */
+const IDENTITY_PREFIX: &str = "rfb";
+
+/* the host only understands these color depths */
+const SUPPORTED_BPP: [u8; 2] = [16, 32];
+
+/* framebuffers must start on an 8-byte boundary */
+const BUFFER_ALIGN: usize = 8;
+
+/* describes the layout of the frame */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode
+{
+    pub width: u16,
+    pub height: u16,
+    pub bpp: u8 /* bits per pixel */
+}
+
+impl Mode
+{
+    /* return the number of bytes in one row of pixels */
+    pub fn stride(&self) -> usize
+    {
+        self.width as usize * self.bpp as usize / 8
+    }
+
+    /* return the number of bytes needed to hold a whole frame */
+    pub fn size(&self) -> usize
+    {
+        self.stride() * self.height as usize
+    }
+}
+
+/* a framebuffer device provided by the host */
+#[derive(Debug)]
+pub struct Framebuffer
+{
+    device: u8,
+    mode: Option<Mode>, /* set once configured */
+    addr: Option<u64>   /* set once the host knows where the buffer is */
+}
+
+impl Framebuffer
+{
+    /* use the framebuffer device at the given HTIF device number */
+    pub fn new(htif: &mut HTIF<impl HtifTransport>, device: u8) -> Result<Self, Fault>
+    {
+        match htif.identify(device)?.as_str().starts_with(IDENTITY_PREFIX)
+        {
+            true => Ok(Framebuffer { device, mode: None, addr: None }),
+            false => Err(Fault::NotPresent)
+        }
+    }
+
+    /* use the first framebuffer device provided by the host, if any */
+    pub fn find(htif: &mut HTIF<impl HtifTransport>) -> Result<Self, Fault>
+    {
+        match htif.find_device(IDENTITY_PREFIX)?
+        {
+            Some(dev) => Framebuffer::new(htif, dev.number),
+            None => Err(Fault::NotPresent)
+        }
+    }
+
+    /* set the frame's resolution and color depth. the host only allows this once,
+       and only supports 16 and 32 bits per pixel */
+    pub fn configure(&mut self, htif: &mut HTIF<impl HtifTransport>, mode: Mode) -> Result<(), Fault>
+    {
+        if self.mode.is_some() || !SUPPORTED_BPP.contains(&mode.bpp)
+        {
+            return Err(Fault::Unsupported);
+        }
+
+        let payload = mode.width as u64 | (mode.height as u64) << HEIGHT_SHIFT | (mode.bpp as u64) << BPP_SHIFT;
+        self.command(htif, COMMAND_CONFIGURE, payload)?;
+        self.mode = Some(mode);
+        Ok(())
+    }
+
+    /* return the configured mode, if any */
+    pub fn mode(&self) -> Option<Mode>
+    {
+        self.mode
+    }
+
+    /* have the host display the contents of buffer, which must be at a physical
+       address, 8-byte aligned, and large enough to hold a whole frame in the
+       configured mode. the buffer must stay put while the host is using it */
+    pub fn set_buffer(&mut self, htif: &mut HTIF<impl HtifTransport>, buffer: &mut [u8]) -> Result<(), Fault>
+    {
+        let mode = self.mode.ok_or(Fault::Unsupported)?;
+        if buffer.len() < mode.size() || buffer.as_ptr() as usize & (BUFFER_ALIGN - 1) != 0
+        {
+            return Err(Fault::OutOfRange);
+        }
+
+        let addr = buffer.as_mut_ptr() as u64;
+        self.command(htif, COMMAND_SET_ADDRESS, addr)?;
+        self.addr = Some(addr);
+        Ok(())
+    }
+
+    /* return the physical address of the buffer being displayed, if any */
+    pub fn buffer_addr(&self) -> Option<u64>
+    {
+        self.addr
+    }
+
+    /* re-send the buffer's address to the host, prompting it to redraw the frame */
+    pub fn refresh(&self, htif: &mut HTIF<impl HtifTransport>) -> Result<(), Fault>
+    {
+        let addr = self.addr.ok_or(Fault::Unsupported)?;
+        self.command(htif, COMMAND_SET_ADDRESS, addr)
+    }
+
+    /* issue a command and wait for the host to acknowledge it. a command may hand the
+       host a buffer, so once the host has taken it, it's never abandoned. see transact() */
+    fn command(&self, htif: &mut HTIF<impl HtifTransport>, command: u64, payload: u64) -> Result<(), Fault>
+    {
+        match htif.transact(encode_command(self.device, command as u8, payload))?.payload
+        {
+            COMMAND_DONE => Ok(()),
+            _ => Err(Fault::InvalidResponse)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn mode_sizes()
+    {
+        let mode = Mode { width: 640, height: 480, bpp: 32 };
+        assert_eq!(mode.stride(), 640 * 4);
+        assert_eq!(mode.size(), 640 * 4 * 480);
+
+        let mode = Mode { width: 320, height: 200, bpp: 16 };
+        assert_eq!(mode.size(), 320 * 2 * 200);
+    }
+}
@@ -0,0 +1,97 @@
+/* gdbstub connection over the host console
+ *
+ * Enabled by the gdbstub feature. A GdbConsole owns the driver and carries the
+ * GDB remote protocol over the console, so a kernel in Spike can run a gdbstub
+ * and be debugged from the host's stdin and stdout without any extra devices:
+ *
+ * let conn = GdbConsole::new(HTIF::new()?);
+ * let gdb = GdbStub::new(conn);
+ *
+ * The protocol is binary, so bytes go to and from the host untouched: the
+ * driver's newline translation and echo settings are ignored. peek() holds on
+ * to the byte it finds, for the next read() to return.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use gdbstub::conn::{Connection, ConnectionExt};
+use super::{HTIF, Fault, poll};
+use super::transport::{HtifTransport, Mmio};
+
+pub struct GdbConsole<T: HtifTransport = Mmio>
+{
+    htif: HTIF<T>,
+    peeked: Option<u8> /* byte found by peek() but not yet read */
+}
+
+impl<T: HtifTransport> GdbConsole<T>
+{
+    /* carry GDB's traffic over the given driver's console */
+    pub const fn new(htif: HTIF<T>) -> Self
+    {
+        GdbConsole { htif, peeked: None }
+    }
+
+    /* return the driver, such as to exit once the debugger detaches */
+    pub fn htif(&mut self) -> &mut HTIF<T>
+    {
+        &mut self.htif
+    }
+
+    /* return the driver. a byte peeked at but not read is lost */
+    pub fn into_inner(self) -> HTIF<T>
+    {
+        self.htif
+    }
+
+    /* fetch a byte from the console, untranslated, if one is available.
+       blocking is true if the caller is waiting for the byte */
+    fn try_receive(&mut self, blocking: bool) -> Result<Option<u8>, Fault>
+    {
+        match self.peeked.take()
+        {
+            Some(byte) => Ok(Some(byte)),
+            None => self.htif.try_receive_byte(blocking)
+        }
+    }
+}
+
+impl<T: HtifTransport> Connection for GdbConsole<T>
+{
+    type Error = Fault;
+
+    /* hand a byte to the host. flush() waits for it to be taken */
+    fn write(&mut self, byte: u8) -> Result<(), Fault>
+    {
+        self.htif.send_raw_byte(byte)
+    }
+
+    fn flush(&mut self) -> Result<(), Fault>
+    {
+        self.htif.wait_for_consumption()
+    }
+}
+
+impl<T: HtifTransport> ConnectionExt for GdbConsole<T>
+{
+    /* block until a byte arrives, subject to the driver's timeout */
+    fn read(&mut self) -> Result<u8, Fault>
+    {
+        let (timeout, backoff) = (self.htif.timeout, self.htif.backoff);
+        let mut polls = 0;
+        let result = poll(timeout, backoff, &mut polls, || self.try_receive(true).transpose());
+        self.htif.count_polls(polls, &result);
+        result?
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, Fault>
+    {
+        if self.peeked.is_none()
+        {
+            self.peeked = self.htif.try_receive_byte(false)?;
+        }
+        Ok(self.peeked)
+    }
+}
@@ -0,0 +1,262 @@
+/* Single ownership of the linker-defined registers, and a global console
+ *
+ * HTIF::take() hands out at most one live Handle to the tohost and fromhost
+ * registers defined by the linker, so that two drivers can't end up fighting
+ * over them. HTIF::steal() is the escape hatch for when the owner can't be
+ * reached, such as in a panic handler.
+ *
+ * The global console used by htif_print!() and htif_println!() takes the handle
+ * on first use, and is locked for the duration of each use so that harts don't
+ * trample over each other's requests. console().lock() holds that lock for as
+ * long as its guard lives, for output that mustn't be split up. With the
+ * critical-section feature, the lock is taken inside a critical section, so a
+ * trap handler can't interrupt a hart holding it and then deadlock trying to
+ * take it.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::fmt;
+#[cfg(feature = "critical-section")]
+use core::ptr::addr_of_mut;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+use super::HTIF;
+use super::lock::SpinLock;
+
+/* set while a handle taken with HTIF::take() is alive */
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+static CONSOLE: SpinLock<Option<Handle>> = SpinLock::new(None);
+
+/* the critical section entered by acquire(), to be left by release(). only touched with CONSOLE locked */
+#[cfg(feature = "critical-section")]
+static mut RESTORE: Option<critical_section::RestoreState> = None;
+
+/* the driver for the linker-defined registers, owned by whoever took it */
+#[derive(Debug)]
+pub struct Handle
+{
+    htif: HTIF,
+    stolen: bool /* stolen handles don't own the registers, so mustn't release them */
+}
+
+impl Deref for Handle
+{
+    type Target = HTIF;
+
+    fn deref(&self) -> &HTIF
+    {
+        &self.htif
+    }
+}
+
+impl DerefMut for Handle
+{
+    fn deref_mut(&mut self) -> &mut HTIF
+    {
+        &mut self.htif
+    }
+}
+
+/* let someone else take the registers once the owner is done */
+impl Drop for Handle
+{
+    fn drop(&mut self)
+    {
+        if !self.stolen
+        {
+            TAKEN.store(false, Ordering::Release);
+        }
+    }
+}
+
+impl HTIF
+{
+    /* return a handle to the linker-defined registers, or None if a handle
+       taken earlier is still alive, including the global console's */
+    pub fn take() -> Option<Handle>
+    {
+        if TAKEN.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err()
+        {
+            return None;
+        }
+
+        Some(Handle { htif: HTIF::from_symbols(), stolen: false })
+    }
+
+    /* return a handle to the linker-defined registers whether or not one is already
+       alive. this is unsafe as the caller must ensure the other handle's owner
+       isn't using it */
+    pub unsafe fn steal() -> Handle
+    {
+        Handle { htif: HTIF::from_symbols(), stolen: true }
+    }
+}
+
+/* run f with exclusive access to the global console, creating it if needed.
+   returns None, without running f, if the console couldn't be created, such as
+   when someone else has taken the registers */
+pub fn with<R>(f: impl FnOnce(&mut HTIF) -> R) -> Option<R>
+{
+    exclusive(||
+    {
+        let mut console = CONSOLE.lock();
+        if console.is_none()
+        {
+            *console = HTIF::take();
+        }
+
+        console.as_mut().map(|handle| f(handle))
+    })
+}
+
+/* run f in a critical section, if the critical-section feature is enabled */
+#[cfg(feature = "critical-section")]
+fn exclusive<R>(f: impl FnOnce() -> R) -> R
+{
+    critical_section::with(|_| f())
+}
+
+#[cfg(not(feature = "critical-section"))]
+fn exclusive<R>(f: impl FnOnce() -> R) -> R
+{
+    f()
+}
+
+/* lock the global console, creating it if needed, and return it if that succeeded.
+   for loggers that lock and unlock in separate calls. unsafe as the caller must
+   call release() when done, and not use the console after */
+pub(crate) unsafe fn acquire() -> Option<&'static mut HTIF>
+{
+    #[cfg(feature = "critical-section")]
+    let restore = critical_section::acquire();
+
+    let console = &mut *CONSOLE.raw_lock();
+
+    #[cfg(feature = "critical-section")]
+    {
+        *addr_of_mut!(RESTORE) = Some(restore);
+    }
+
+    if console.is_none()
+    {
+        *console = HTIF::take();
+    }
+
+    console.as_mut().map(|handle| &mut **handle)
+}
+
+/* unlock the global console after acquire() */
+pub(crate) unsafe fn release()
+{
+    #[cfg(feature = "critical-section")]
+    let restore = (*addr_of_mut!(RESTORE)).take();
+
+    CONSOLE.raw_unlock();
+
+    #[cfg(feature = "critical-section")]
+    if let Some(restore) = restore
+    {
+        critical_section::release(restore);
+    }
+}
+
+/* the global console, which can be locked to print several things uninterrupted */
+#[derive(Debug, Clone, Copy)]
+pub struct Console;
+
+/* return the global console */
+pub fn console() -> Console
+{
+    Console
+}
+
+impl Console
+{
+    /* lock the global console, creating it if needed, so that output from other
+       harts isn't mixed in with ours until the guard is dropped. returns None if
+       the console couldn't be created. locking it again from the same hart,
+       including via htif_print!(), before the guard is dropped will deadlock */
+    pub fn lock(&self) -> Option<ConsoleGuard>
+    {
+        unsafe
+        {
+            match acquire()
+            {
+                Some(htif) => Some(ConsoleGuard { htif, unsend: PhantomData }),
+                None =>
+                {
+                    release();
+                    None
+                }
+            }
+        }
+    }
+}
+
+/* exclusive access to the global console, which is unlocked when this is dropped */
+pub struct ConsoleGuard
+{
+    htif: &'static mut HTIF,
+    unsend: PhantomData<*const ()> /* must be dropped by the hart that locked it, to leave any critical section */
+}
+
+impl Deref for ConsoleGuard
+{
+    type Target = HTIF;
+
+    fn deref(&self) -> &HTIF
+    {
+        self.htif
+    }
+}
+
+impl DerefMut for ConsoleGuard
+{
+    fn deref_mut(&mut self) -> &mut HTIF
+    {
+        self.htif
+    }
+}
+
+impl fmt::Write for ConsoleGuard
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result
+    {
+        fmt::Write::write_str(self.htif, s)
+    }
+}
+
+impl Drop for ConsoleGuard
+{
+    fn drop(&mut self)
+    {
+        unsafe { release() }
+    }
+}
+
+/* used by htif_print!(). output is dropped if the host can't be reached */
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments)
+{
+    with(|console| fmt::Write::write_fmt(console, args));
+}
+
+/* print to the host console, like print!() */
+#[macro_export]
+macro_rules! htif_print
+{
+    ($($arg:tt)*) => ($crate::global::_print(format_args!($($arg)*)));
+}
+
+/* print to the host console with a newline, like println!() */
+#[macro_export]
+macro_rules! htif_println
+{
+    () => ($crate::htif_print!("\n"));
+    ($($arg:tt)*) => ($crate::global::_print(format_args!("{}\n", format_args!($($arg)*))));
+}
@@ -0,0 +1,368 @@
+/* Text console drawn into the framebuffer, with input from the keyboard
+ *
+ * For frontends that show a display rather than, or as well as, a terminal.
+ * A GraphicalConsole draws text into a framebuffer in the font from font.rs,
+ * scrolling as lines fill up, and reads characters from the keyboard, with the
+ * same send and read calls as the serial console, and core::fmt::Write:
+ *
+ * let mut console = GraphicalConsole::find(&mut htif, mode, &mut buffer)?;
+ * writeln!(console, "hello, world")?;
+ * let c = console.read_byte()?;
+ *
+ * Newline, carriage return, backspace, and tab move the cursor as they would
+ * on a terminal. Other control characters are ignored, and bytes beyond ASCII
+ * are drawn as '?'. Pixels are written little-endian, as XRGB8888 at 32 bits
+ * per pixel or RGB565 at 16. The host is prompted to redraw after each write.
+ *
+ * Key codes below 0x80 are taken to be ASCII characters, with carriage return
+ * read as newline. Other key codes, such as for function keys, are skipped.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::font::{glyph, GLYPH_WIDTH, GLYPH_HEIGHT};
+use super::framebuffer::{Framebuffer, Mode};
+use super::keyboard::Keyboard;
+use super::transport::{HtifTransport, Mmio};
+
+/* default colors, as 0xRRGGBB */
+pub const DEFAULT_FOREGROUND: u32 = 0xc0c0c0;
+pub const DEFAULT_BACKGROUND: u32 = 0x000000;
+
+/* tab stops are this many columns apart */
+const TAB_WIDTH: usize = 8;
+
+const BACKSPACE: u8 = 0x08;
+
+/* text console over a framebuffer and keyboard */
+pub struct GraphicalConsole<'a, T = Mmio>
+{
+    htif: &'a mut HTIF<T>,
+    framebuffer: Framebuffer,
+    keyboard: Keyboard,
+    screen: Screen<'a>,
+    echo: bool /* true to draw characters as they're read */
+}
+
+impl<'a, T: HtifTransport> GraphicalConsole<'a, T>
+{
+    /* draw text into buffer, displayed by the given framebuffer, which must already be
+       configured. the buffer must meet set_buffer()'s requirements and is cleared */
+    pub fn new(htif: &'a mut HTIF<T>, mut framebuffer: Framebuffer, keyboard: Keyboard, buffer: &'a mut [u8]) -> Result<Self, Fault>
+    {
+        let mode = framebuffer.mode().ok_or(Fault::Unsupported)?;
+        framebuffer.set_buffer(htif, buffer)?;
+        let screen = Screen::new(buffer, mode)?;
+
+        let mut console = GraphicalConsole { htif, framebuffer, keyboard, screen, echo: false };
+        console.clear()?;
+        Ok(console)
+    }
+
+    /* use the host's first framebuffer and keyboard, setting the framebuffer to the
+       given mode and displaying buffer. fails with NotPresent if either is missing */
+    pub fn find(htif: &'a mut HTIF<T>, mode: Mode, buffer: &'a mut [u8]) -> Result<Self, Fault>
+    {
+        let mut framebuffer = Framebuffer::find(htif)?;
+        let keyboard = Keyboard::find(htif)?;
+        framebuffer.configure(htif, mode)?;
+        GraphicalConsole::new(htif, framebuffer, keyboard, buffer)
+    }
+
+    /* draw characters on the screen as they're read, as the keyboard can't */
+    pub fn set_echo(&mut self, echo: bool)
+    {
+        self.echo = echo;
+    }
+
+    /* draw text from now on in the given colors, as 0xRRGGBB */
+    pub fn set_colors(&mut self, foreground: u32, background: u32)
+    {
+        self.screen.set_colors(foreground, background);
+    }
+
+    /* return the screen's size in characters, as columns and rows */
+    pub fn text_size(&self) -> (usize, usize)
+    {
+        (self.screen.cols, self.screen.rows)
+    }
+
+    /* return the driver, such as to exit */
+    pub fn htif(&mut self) -> &mut HTIF<T>
+    {
+        self.htif
+    }
+
+    /* fill the screen with the background color and move the cursor to the top left */
+    pub fn clear(&mut self) -> Result<(), Fault>
+    {
+        self.screen.clear();
+        self.framebuffer.refresh(self.htif)
+    }
+
+    /* draw a byte on the screen. see send_bytes() */
+    pub fn send_byte(&mut self, to_send: u8) -> Result<(), Fault>
+    {
+        self.send_bytes(&[to_send])
+    }
+
+    /* draw bytes on the screen, then have the host redraw it. fails if the host
+       doesn't answer, though the bytes will have been drawn into the buffer */
+    pub fn send_bytes(&mut self, to_send: &[u8]) -> Result<(), Fault>
+    {
+        for byte in to_send
+        {
+            self.screen.put(*byte);
+        }
+        self.framebuffer.refresh(self.htif)
+    }
+
+    /* draw a string on the screen. see send_bytes() */
+    pub fn write_str(&mut self, to_send: &str) -> Result<(), Fault>
+    {
+        self.send_bytes(to_send.as_bytes())
+    }
+
+    /* block until a character is typed, and return it */
+    pub fn read_byte(&mut self) -> Result<u8, Fault>
+    {
+        loop
+        {
+            if let Some(byte) = key_to_byte(self.keyboard.read_key(self.htif)?)
+            {
+                self.echo_byte(byte)?;
+                return Ok(byte);
+            }
+        }
+    }
+
+    /* return a typed character if one is available, or None if not. this never blocks,
+       other than to echo the character */
+    pub fn try_read_byte(&mut self) -> Result<Option<u8>, Fault>
+    {
+        while let Some(code) = self.keyboard.try_read_key(self.htif)?
+        {
+            if let Some(byte) = key_to_byte(code)
+            {
+                self.echo_byte(byte)?;
+                return Ok(Some(byte));
+            }
+        }
+        Ok(None)
+    }
+
+    fn echo_byte(&mut self, byte: u8) -> Result<(), Fault>
+    {
+        match self.echo
+        {
+            true => self.send_byte(byte),
+            false => Ok(())
+        }
+    }
+}
+
+impl<T: HtifTransport> core::fmt::Write for GraphicalConsole<'_, T>
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result
+    {
+        GraphicalConsole::write_str(self, s).map_err(|_| core::fmt::Error)
+    }
+}
+
+/* turn a key code into the character it types, if it types one */
+fn key_to_byte(code: u32) -> Option<u8>
+{
+    match code
+    {
+        0x0d => Some(b'\n'),
+        0x01..=0x7f => Some(code as u8),
+        _ => None
+    }
+}
+
+/* return the bytes of a pixel of the given color, as 0xRRGGBB, at the given depth */
+fn pixel(color: u32, bpp: u8) -> [u8; 4]
+{
+    match bpp
+    {
+        16 =>
+        {
+            let (red, green, blue) = ((color >> 19) & 0x1f, (color >> 10) & 0x3f, (color >> 3) & 0x1f);
+            let rgb565 = ((red << 11) | (green << 5) | blue) as u16;
+            let [low, high] = rgb565.to_le_bytes();
+            [low, high, 0, 0]
+        },
+        _ => color.to_le_bytes()
+    }
+}
+
+/* a grid of characters drawn into a framebuffer */
+struct Screen<'a>
+{
+    buffer: &'a mut [u8],
+    mode: Mode,
+    cols: usize,
+    rows: usize,
+    col: usize, /* cursor position */
+    row: usize,
+    foreground: [u8; 4], /* pixels to draw in */
+    background: [u8; 4]
+}
+
+impl<'a> Screen<'a>
+{
+    /* fails with OutOfRange if the buffer can't hold the frame, or the frame can't hold a character */
+    fn new(buffer: &'a mut [u8], mode: Mode) -> Result<Self, Fault>
+    {
+        let (cols, rows) = (mode.width as usize / GLYPH_WIDTH, mode.height as usize / GLYPH_HEIGHT);
+        if buffer.len() < mode.size() || cols == 0 || rows == 0
+        {
+            return Err(Fault::OutOfRange);
+        }
+
+        let mut screen = Screen { buffer, mode, cols, rows, col: 0, row: 0, foreground: [0; 4], background: [0; 4] };
+        screen.set_colors(DEFAULT_FOREGROUND, DEFAULT_BACKGROUND);
+        Ok(screen)
+    }
+
+    fn set_colors(&mut self, foreground: u32, background: u32)
+    {
+        self.foreground = pixel(foreground, self.mode.bpp);
+        self.background = pixel(background, self.mode.bpp);
+    }
+
+    fn clear(&mut self)
+    {
+        let (size, background) = (self.mode.size(), self.background);
+        self.fill(0..size, background);
+        self.col = 0;
+        self.row = 0;
+    }
+
+    /* draw a byte at the cursor, or move the cursor as it says */
+    fn put(&mut self, byte: u8)
+    {
+        match byte
+        {
+            b'\n' => self.new_line(),
+            b'\r' => self.col = 0,
+            BACKSPACE => self.col = self.col.saturating_sub(1),
+            b'\t' =>
+            {
+                self.col = (self.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                if self.col >= self.cols
+                {
+                    self.new_line();
+                }
+            },
+            0x00..=0x1f | 0x7f => (),
+            _ =>
+            {
+                self.draw(byte);
+                self.col += 1;
+                if self.col == self.cols
+                {
+                    self.new_line();
+                }
+            }
+        }
+    }
+
+    /* draw the given character's glyph at the cursor */
+    fn draw(&mut self, c: u8)
+    {
+        let depth = self.mode.bpp as usize / 8;
+        let stride = self.mode.stride();
+        let left = self.col * GLYPH_WIDTH * depth;
+        let top = self.row * GLYPH_HEIGHT;
+
+        for (y, line) in glyph(c).iter().enumerate()
+        {
+            let start = (top + y) * stride + left;
+            let pixels = self.buffer[start..start + GLYPH_WIDTH * depth].chunks_exact_mut(depth);
+            for (x, pixel) in pixels.enumerate()
+            {
+                let color = match line & (0x80 >> x)
+                {
+                    0 => &self.background,
+                    _ => &self.foreground
+                };
+                pixel.copy_from_slice(&color[..depth]);
+            }
+        }
+    }
+
+    /* move the cursor to the start of the next line, scrolling up a line at the bottom */
+    fn new_line(&mut self)
+    {
+        self.col = 0;
+        if self.row + 1 < self.rows
+        {
+            self.row += 1;
+            return;
+        }
+
+        let line = self.mode.stride() * GLYPH_HEIGHT;
+        let end = line * self.rows;
+        let background = self.background;
+        self.buffer.copy_within(line..end, 0);
+        self.fill(end - line..end, background);
+    }
+
+    /* set every pixel in the given range of the buffer to the given color */
+    fn fill(&mut self, range: core::ops::Range<usize>, color: [u8; 4])
+    {
+        let depth = self.mode.bpp as usize / 8;
+        for pixel in self.buffer[range].chunks_exact_mut(depth)
+        {
+            pixel.copy_from_slice(&color[..depth]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /* return the 32-bit pixel at the given position in a buffer 16 pixels wide */
+    fn pixel_at(buffer: &[u8], x: usize, y: usize) -> u32
+    {
+        let start = (y * 16 + x) * 4;
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&buffer[start..start + 4]);
+        u32::from_le_bytes(bytes)
+    }
+
+    #[test]
+    fn drawing()
+    {
+        /* two rows of two characters */
+        let mode = Mode { width: 16, height: 16, bpp: 32 };
+        let mut buffer = vec![0xff; mode.size()];
+        let mut screen = Screen::new(&mut buffer, mode).unwrap();
+        screen.set_colors(0xffffff, 0x000080);
+        screen.clear();
+
+        /* the middle row of an A is solid, and of a C, isn't */
+        screen.put(b'A');
+        assert_eq!(screen.buffer[..4], [0x80, 0, 0, 0]);
+        assert_eq!(pixel_at(screen.buffer, 3, 3), 0xffffff);
+
+        /* filling the second row scrolls C up to the top left */
+        for byte in b"BCD"
+        {
+            screen.put(*byte);
+        }
+        assert_eq!((screen.col, screen.row), (0, 1));
+        assert_eq!(pixel_at(screen.buffer, 1, 0), 0x000080);
+        assert_eq!(pixel_at(screen.buffer, 1, 1), 0xffffff);
+        assert_eq!(pixel_at(screen.buffer, 3, 3), 0x000080);
+        assert!(buffer[16 * 8 * 4..].iter().zip([0x80, 0, 0, 0].iter().cycle()).all(|(a, b)| a == b));
+
+        assert_eq!(pixel(0xff8000, 16), [0x00, 0xfc, 0, 0]);
+    }
+}
@@ -0,0 +1,72 @@
+/* embedded-hal-nb serial traits for the host console
+ *
+ * Enabled by the embedded-hal-nb feature. Reads return WouldBlock until the host
+ * has a character, and writes return WouldBlock while the host is yet to take
+ * the previous one. flush() waits, in the same way, for the last write to be taken.
+ *
+ * Unlike the driver's other writers, write() sends each byte raw, without the
+ * line ending translation set by with_newlines(), as a translated LF would take
+ * two handshakes and so couldn't be written without blocking. Send CR LF where
+ * the terminal needs it. Reads are translated as read_byte()'s are.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use embedded_hal_nb::nb;
+use embedded_hal_nb::serial::{self, ErrorKind, ErrorType};
+use super::{HTIF, Fault, COMMAND_WRITE_CHAR};
+use super::protocol::encode_command;
+use super::transport::HtifTransport;
+
+impl serial::Error for Fault
+{
+    fn kind(&self) -> ErrorKind
+    {
+        ErrorKind::Other
+    }
+}
+
+impl<T: HtifTransport> ErrorType for HTIF<T>
+{
+    type Error = Fault;
+}
+
+impl<T: HtifTransport> serial::Read for HTIF<T>
+{
+    fn read(&mut self) -> nb::Result<u8, Fault>
+    {
+        match self.try_read_byte()
+        {
+            Ok(Some(byte)) => Ok(byte),
+            Ok(None) => Err(nb::Error::WouldBlock),
+            Err(e) => Err(nb::Error::Other(e))
+        }
+    }
+}
+
+impl<T: HtifTransport> serial::Write for HTIF<T>
+{
+    /* send the byte untranslated. see above */
+    fn write(&mut self, byte: u8) -> nb::Result<(), Fault>
+    {
+        if self.read_to_host() != 0
+        {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        self.write_to_host(encode_command(self.console, COMMAND_WRITE_CHAR as u8, byte as u64))?;
+        self.count(|stats| stats.bytes_sent = stats.bytes_sent.wrapping_add(1));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Fault>
+    {
+        match self.read_to_host()
+        {
+            0 => Ok(()),
+            _ => Err(nb::Error::WouldBlock)
+        }
+    }
+}
@@ -0,0 +1,132 @@
+/* per-hart handles on the global console that tag each line with the hart's ID
+ *
+ * On a multi-core simulation, every hart writing to the one console makes for
+ * a confusing log. Each hart can instead print through its own HartConsole, which
+ * starts every line it prints with [hart N]. The hart supplies its own ID, as
+ * mhartid usually can't be read outside of machine mode.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::fmt::{self, Write};
+use super::HTIF;
+use super::global;
+
+/* runs the given writer on a console while holding that console's lock */
+type Console = fn(&mut dyn FnMut(&mut dyn Write) -> fmt::Result) -> Option<fmt::Result>;
+
+#[derive(Debug)]
+pub struct HartConsole
+{
+    hart: usize,
+    line_start: bool, /* true if the next byte printed starts a new line */
+    console: Console /* the global console, other than in tests */
+}
+
+impl HartConsole
+{
+    /* create a handle for the given hart, which should only be used by that hart */
+    pub const fn new(hart: usize) -> Self
+    {
+        HartConsole { hart, line_start: true, console: global_console }
+    }
+
+    /* print formatted output, holding the global console until it's all sent so that
+       other harts can't split it up. fails if the global console can't be created */
+    pub fn print(&mut self, args: fmt::Arguments) -> fmt::Result
+    {
+        let hart = self.hart;
+        let line_start = &mut self.line_start;
+        (self.console)(&mut |out| Prefixed { out, hart, line_start: &mut *line_start }.write_fmt(args)).unwrap_or(Err(fmt::Error))
+    }
+}
+
+/* write!() on a HartConsole goes through write_fmt(), so hold the console for the whole
+   of it, rather than taking the lock afresh for each piece of the formatted output */
+impl Write for HartConsole
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result
+    {
+        self.print(format_args!("{}", s))
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments) -> fmt::Result
+    {
+        self.print(args)
+    }
+}
+
+fn global_console(f: &mut dyn FnMut(&mut dyn Write) -> fmt::Result) -> Option<fmt::Result>
+{
+    global::with(|htif: &mut HTIF| f(htif))
+}
+
+/* inserts the hart's tag at the start of each line written through it */
+struct Prefixed<'a, W: Write + ?Sized>
+{
+    out: &'a mut W,
+    hart: usize,
+    line_start: &'a mut bool
+}
+
+impl<W: Write + ?Sized> Write for Prefixed<'_, W>
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result
+    {
+        for line in s.split_inclusive('\n')
+        {
+            if *self.line_start
+            {
+                write!(self.out, "[hart {}] ", self.hart)?;
+            }
+
+            self.out.write_str(line)?;
+            *self.line_start = line.ends_with('\n');
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::cell::{Cell, RefCell};
+
+    thread_local!
+    {
+        static OUT: RefCell<String> = const { RefCell::new(String::new()) };
+        static LOCKS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /* a console that records its output and how often it was locked */
+    fn test_console(f: &mut dyn FnMut(&mut dyn Write) -> fmt::Result) -> Option<fmt::Result>
+    {
+        LOCKS.with(|locks| locks.set(locks.get() + 1));
+        Some(OUT.with(|out| f(&mut *out.borrow_mut())))
+    }
+
+    #[test]
+    fn prefixes()
+    {
+        let mut out = String::new();
+        let mut line_start = true;
+        let mut prefixed = Prefixed { out: &mut out, hart: 3, line_start: &mut line_start };
+        write!(prefixed, "one\ntwo").unwrap();
+        writeln!(prefixed, " continued").unwrap();
+        assert_eq!(out, "[hart 3] one\n[hart 3] two continued\n");
+        assert!(line_start);
+    }
+
+    #[test]
+    fn one_lock_per_write()
+    {
+        let mut console = HartConsole { hart: 1, line_start: true, console: test_console };
+        writeln!(console, "{} + {} = {}", 2, 2, 4).unwrap();
+        assert_eq!(LOCKS.with(|locks| locks.get()), 1);
+        assert_eq!(OUT.with(|out| out.borrow().clone()), "[hart 1] 2 + 2 = 4\n");
+    }
+}
@@ -0,0 +1,110 @@
+/* canonical hex and ASCII dumps of memory over the host console
+ *
+ * The format matches hexdump -C, other than listing full 64-bit addresses:
+ * 0000000080001000  48 65 6c 6c 6f 0a                                 |Hello.|
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+use super::number::HEX_DIGITS;
+
+const BYTES_PER_LINE: usize = 16;
+
+/* address, two spaces, 16 bytes of three characters plus one gap, a space, and |16 chars| plus LF */
+const LINE_LEN: usize = 16 + 2 + (BYTES_PER_LINE * 3) + 1 + 1 + (BYTES_PER_LINE + 2) + 1;
+
+/* format up to 16 bytes found at addr as one line, and return its length */
+fn format_line(addr: u64, bytes: &[u8], line: &mut [u8; LINE_LEN]) -> usize
+{
+    let mut len = 0;
+    let mut push = |byte: u8|
+    {
+        line[len] = byte;
+        len += 1;
+    };
+
+    for digit in (0..16).rev()
+    {
+        push(HEX_DIGITS[((addr >> (digit * 4)) & 0xf) as usize]);
+    }
+    push(b' ');
+
+    for index in 0..BYTES_PER_LINE
+    {
+        /* split the bytes into two groups of eight */
+        if index % 8 == 0
+        {
+            push(b' ');
+        }
+
+        match bytes.get(index)
+        {
+            Some(byte) =>
+            {
+                push(HEX_DIGITS[(byte >> 4) as usize]);
+                push(HEX_DIGITS[(byte & 0xf) as usize]);
+            },
+            None =>
+            {
+                push(b' ');
+                push(b' ');
+            }
+        }
+        push(b' ');
+    }
+
+    push(b' ');
+    push(b'|');
+    for byte in bytes
+    {
+        push(match byte
+        {
+            0x20..=0x7e => *byte,
+            _ => b'.'
+        });
+    }
+    push(b'|');
+    push(b'\n');
+
+    len
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* print bytes to the host console as a hex and ASCII dump, labelling
+       the first byte with the given address */
+    pub fn hexdump(&self, addr: u64, bytes: &[u8]) -> Result<(), Fault>
+    {
+        let mut line = [0; LINE_LEN];
+        for (index, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate()
+        {
+            let offset = (index * BYTES_PER_LINE) as u64;
+            let len = format_line(addr.wrapping_add(offset), chunk, &mut line);
+            self.send_bytes(&line[..len])?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn line_format()
+    {
+        let mut line = [0; LINE_LEN];
+        let len = format_line(0x80001000, b"Hello\n", &mut line);
+        assert_eq!(&line[..len], &b"0000000080001000  48 65 6c 6c 6f 0a                                 |Hello.|\n"[..]);
+
+        let len = format_line(0x10, b"0123456789abcdef", &mut line);
+        assert_eq!(len, LINE_LEN);
+        assert_eq!(&line[..len], &b"0000000000000010  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|\n"[..]);
+    }
+}
@@ -0,0 +1,111 @@
+/* Read the host's standard input, telling apart no input yet from the end of it
+ *
+ * fesvr's console device holds a read until a character arrives, so when the
+ * host's stdin is a pipe or file that has run dry, console reads simply never
+ * complete. A HostInput instead reads stdin via the syscall proxy, with the host
+ * descriptor made non-blocking, so that a kernel driven by a script can
+ * tell when the script's input is exhausted:
+ *
+ * let input = HostInput::open(&mut htif)?;
+ * loop { match input.read(&mut htif, &mut buf)? { Input::End => break, ... } }
+ *
+ * Don't read from the console while using a HostInput, as the two would
+ * take each other's bytes. Bytes are passed on untranslated and without echo.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::file::HostFile;
+use super::transport::HtifTransport;
+use super::syscall::SYS_FCNTL;
+
+/* the host's standard input */
+const STDIN: u64 = 0;
+
+/* fcntl() commands and flags, from Linux's generic ABI */
+const F_GETFL: u64 = 3;
+const F_SETFL: u64 = 4;
+const O_NONBLOCK: u64 = 0o4000;
+
+/* a non-blocking read found nothing to return */
+const EAGAIN: u64 = 11;
+
+/* the outcome of a read from the host's standard input */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input
+{
+    Data(usize), /* this many bytes were read */
+    NotYet, /* there's nothing to read at the moment, but may be later */
+    End /* the input is exhausted, and there will never be more */
+}
+
+/* the host's standard input, made non-blocking. call close() when done
+   to restore the host descriptor's original flags */
+#[derive(Debug)]
+pub struct HostInput
+{
+    flags: u64 /* the descriptor's flags before it was made non-blocking */
+}
+
+impl HostInput
+{
+    /* make the host's standard input non-blocking, ready for read() */
+    pub fn open(htif: &mut HTIF<impl HtifTransport>) -> Result<Self, Fault>
+    {
+        let flags = htif.proxy_syscall(SYS_FCNTL, &[STDIN, F_GETFL])?;
+        htif.proxy_syscall(SYS_FCNTL, &[STDIN, F_SETFL, flags | O_NONBLOCK])?;
+        Ok(HostInput { flags })
+    }
+
+    /* read up to buf.len() bytes of whatever input is available into buf, without waiting for more */
+    pub fn read(&self, htif: &mut HTIF<impl HtifTransport>, buf: &mut [u8]) -> Result<Input, Fault>
+    {
+        if buf.is_empty()
+        {
+            return Ok(Input::Data(0));
+        }
+
+        match HostFile::stdin().read(htif, buf)
+        {
+            Ok(0) => Ok(Input::End),
+            Ok(read) => Ok(Input::Data(read)),
+            Err(Fault::HostError(EAGAIN)) => Ok(Input::NotYet),
+            Err(e) => Err(e)
+        }
+    }
+
+    /* put the host's standard input back as it was */
+    pub fn close(self, htif: &mut HTIF<impl HtifTransport>) -> Result<(), Fault>
+    {
+        htif.proxy_syscall(SYS_FCNTL, &[STDIN, F_SETFL, self.flags])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::fesvr::Fesvr;
+
+    #[test]
+    fn end_of_input()
+    {
+        let mut htif = HTIF::over(Fesvr::<64>::new());
+        let input = HostInput::open(&mut htif).unwrap();
+        let mut buf = [0; 4];
+
+        assert_eq!(input.read(&mut htif, &mut buf), Ok(Input::NotYet));
+        htif.transport().type_input(b"hello");
+        assert_eq!(input.read(&mut htif, &mut buf), Ok(Input::Data(4)));
+        assert_eq!(&buf, b"hell");
+
+        htif.transport().end_input();
+        assert_eq!(input.read(&mut htif, &mut buf), Ok(Input::Data(1)));
+        assert_eq!(input.read(&mut htif, &mut buf), Ok(Input::End));
+        assert_eq!(input.close(&mut htif), Ok(()));
+    }
+}
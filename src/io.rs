@@ -0,0 +1,85 @@
+/* embedded-io traits for the host console
+ *
+ * Enabled by the embedded-io feature. read() blocks until at least one byte
+ * arrives, then returns whatever else the host has ready without waiting.
+ * write() blocks until the host has taken the whole buffer, or returns how
+ * much it took if it failed part way through.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use embedded_io::{ErrorKind, ErrorType, Read, Write};
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+
+impl core::error::Error for Fault {}
+
+impl embedded_io::Error for Fault
+{
+    fn kind(&self) -> ErrorKind
+    {
+        match self
+        {
+            Fault::Timeout => ErrorKind::TimedOut,
+            Fault::NotPresent => ErrorKind::NotFound,
+            Fault::InvalidResponse => ErrorKind::InvalidData,
+            Fault::TooManyArguments | Fault::PathTooLong | Fault::OutOfRange | Fault::NotAligned => ErrorKind::InvalidInput,
+            Fault::Unsupported => ErrorKind::Unsupported,
+            _ => ErrorKind::Other
+        }
+    }
+}
+
+impl<T: HtifTransport> ErrorType for HTIF<T>
+{
+    type Error = Fault;
+}
+
+impl<T: HtifTransport> Read for HTIF<T>
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Fault>
+    {
+        let (first, rest) = match buf.split_first_mut()
+        {
+            Some(split) => split,
+            None => return Ok(0)
+        };
+
+        *first = self.read_byte()?;
+
+        /* a failure after the first byte is left for the next call to find */
+        let mut received = 1;
+        for byte in rest
+        {
+            match self.try_read_byte()
+            {
+                Ok(Some(b)) => *byte = b,
+                _ => break
+            }
+            received += 1;
+        }
+
+        Ok(received)
+    }
+}
+
+impl<T: HtifTransport> Write for HTIF<T>
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Fault>
+    {
+        /* report the bytes the host did take, if any, and leave the fault for the next write */
+        match self.write_bytes(buf)
+        {
+            Ok(()) => Ok(buf.len()),
+            Err(e) if e.written > 0 => Ok(e.written),
+            Err(e) => Err(e.fault)
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), Fault>
+    {
+        self.wait_for_consumption()
+    }
+}
@@ -0,0 +1,71 @@
+/* embedded-io-async traits for the host console
+ *
+ * Enabled by the async feature, which brings in the embedded-io feature for the
+ * error types these share with it. Like the blocking versions in io.rs, read()
+ * waits for at least one byte, and then returns whatever else the host has
+ * ready. write() returns once the host has taken the whole buffer, or how much
+ * it took if it failed part way through. No newline translation is done.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::future::poll_fn;
+use embedded_io_async::{Read, Write};
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+
+impl<T: HtifTransport> Read for HTIF<T>
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Fault>
+    {
+        let (first, rest) = match buf.split_first_mut()
+        {
+            Some(split) => split,
+            None => return Ok(0)
+        };
+
+        *first = self.read_byte_async().await?;
+
+        /* a failure after the first byte is left for the next call to find */
+        let mut received = 1;
+        for byte in rest
+        {
+            match self.try_read_byte()
+            {
+                Ok(Some(b)) => *byte = b,
+                _ => break
+            }
+            received += 1;
+        }
+
+        Ok(received)
+    }
+}
+
+impl<T: HtifTransport> Write for HTIF<T>
+{
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Fault>
+    {
+        /* report the bytes the host did take, if any, and leave the fault for the next write */
+        for (sent, byte) in buf.iter().enumerate()
+        {
+            if let Err(e) = self.send_byte_async(*byte).await
+            {
+                return match sent
+                {
+                    0 => Err(e),
+                    _ => Ok(sent)
+                };
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Fault>
+    {
+        poll_fn(|cx| self.poll_flush(cx)).await
+    }
+}
@@ -0,0 +1,79 @@
+/* Interrupt-driven operation
+ *
+ * By default, the driver polls fromhost whenever it's looking for a reply. In
+ * interrupt-driven mode, the kernel instead calls handle_irq() from its trap
+ * handler whenever its platform signals the host has posted a reply. That drains
+ * fromhost into the driver's held replies, and non-blocking calls such as
+ * try_read_byte() only check those held replies, leaving the register alone.
+ * Blocking calls, such as read_byte(), LineEditor::read_line() and
+ * Keyboard::read_key(), still poll fromhost while they wait, so they work with
+ * the interrupt masked, such as in a trap handler.
+ *
+ * An async task waiting on a reply can register a Waker, which handle_irq()
+ * wakes when replies arrive, so that the task sleeps rather than busy-polls.
+ *
+ * HTIF doesn't define an interrupt of its own, so how and whether one is raised
+ * is up to the platform. This only controls how the driver behaves.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::task::Waker;
+use super::{HTIF, FromHostReply};
+use super::transport::HtifTransport;
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* switch to interrupt-driven mode */
+    pub fn enable_interrupts(&mut self)
+    {
+        self.irq_enabled = true;
+    }
+
+    /* switch back to polling fromhost */
+    pub fn disable_interrupts(&mut self)
+    {
+        self.irq_enabled = false;
+    }
+
+    /* return true if in interrupt-driven mode */
+    pub fn interrupts_enabled(&self) -> bool
+    {
+        self.irq_enabled
+    }
+
+    /* call when the platform's HTIF interrupt fires to move any replies waiting
+       in fromhost into the driver. returns the number of replies gathered */
+    pub fn handle_irq(&mut self) -> usize
+    {
+        let mut gathered = 0;
+        while let Some(val) = self.take_from_host()
+        {
+            self.hold_reply(FromHostReply::decode(val));
+            gathered += 1;
+        }
+
+        if gathered > 0
+        {
+            if let Some(waker) = self.waker.take()
+            {
+                waker.wake();
+            }
+        }
+
+        gathered
+    }
+
+    /* wake the given task the next time handle_irq() gathers a reply. this replaces
+       any waker registered earlier, and is forgotten once woken */
+    pub fn register_waker(&mut self, waker: &Waker)
+    {
+        match &self.waker
+        {
+            Some(registered) if registered.will_wake(waker) => (),
+            _ => self.waker = Some(waker.clone())
+        }
+    }
+}
@@ -0,0 +1,118 @@
+/* Receive key events from an HTIF keyboard device
+ *
+ * This is a protocol defined by this crate, not part of HTIF as upstream Spike
+ * and fesvr implement it: stock Spike has no keyboard device, and neither does
+ * this crate's Fesvr model. It needs a custom frontend written to match, such
+ * as a fesvr extended with a device of its own. Keyboard::find() fails with
+ * NotPresent anywhere else.
+ *
+ * In this protocol, the host identifies the device as "keyboard". Command 0
+ * reads a key: like the console's read, the host holds it until a key event is
+ * available, and then answers it with the event's key code as the payload.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault, poll};
+use super::protocol::encode_command;
+use super::transport::{HtifTransport, Mmio};
+
+const COMMAND_READ_KEY: u64 = 0; /* ask for the next key event */
+
+/* the host's identity for keyboard devices */
+const IDENTITY_PREFIX: &str = "keyboard";
+
+/* a keyboard provided by the host */
+#[derive(Debug)]
+pub struct Keyboard
+{
+    device: u8,
+    read_pending: bool /* true if the host has yet to answer a read request */
+}
+
+impl Keyboard
+{
+    /* use the keyboard at the given HTIF device number */
+    pub fn new(htif: &mut HTIF<impl HtifTransport>, device: u8) -> Result<Self, Fault>
+    {
+        match htif.identify(device)?.as_str().starts_with(IDENTITY_PREFIX)
+        {
+            true => Ok(Keyboard { device, read_pending: false }),
+            false => Err(Fault::NotPresent)
+        }
+    }
+
+    /* use the first keyboard provided by the host, if any */
+    pub fn find(htif: &mut HTIF<impl HtifTransport>) -> Result<Self, Fault>
+    {
+        match htif.find_device(IDENTITY_PREFIX)?
+        {
+            Some(dev) => Keyboard::new(htif, dev.number),
+            None => Err(Fault::NotPresent)
+        }
+    }
+
+    /* return the next key code if one is available, or None if not. this never blocks */
+    pub fn try_read_key(&mut self, htif: &mut HTIF<impl HtifTransport>) -> Result<Option<u32>, Fault>
+    {
+        self.receive_key(htif, false)
+    }
+
+    /* like try_read_key(), but polls fromhost even in interrupt-driven mode
+       if blocking is true, as the caller is waiting for the key */
+    fn receive_key(&mut self, htif: &mut HTIF<impl HtifTransport>, blocking: bool) -> Result<Option<u32>, Fault>
+    {
+        /* as with the console, only one read request can be outstanding */
+        if !self.read_pending
+        {
+            htif.write_to_host(encode_command(self.device, COMMAND_READ_KEY as u8, 0))?;
+            self.read_pending = true;
+        }
+
+        match htif.next_reply(self.device, blocking)
+        {
+            Some(reply) =>
+            {
+                self.read_pending = false;
+                Ok(Some(reply.payload as u32))
+            },
+            None => Ok(None)
+        }
+    }
+
+    /* block until a key event arrives, and return its key code */
+    pub fn read_key(&mut self, htif: &mut HTIF<impl HtifTransport>) -> Result<u32, Fault>
+    {
+        let (timeout, backoff) = (htif.timeout, htif.backoff);
+        let mut polls = 0;
+        let result = poll(timeout, backoff, &mut polls, || self.receive_key(htif, true).transpose());
+        htif.count_polls(polls, &result);
+        result?
+    }
+
+    /* iterate over key codes as they become available, stopping when there are
+       no more waiting, or the host fails to respond. the iterator can be created again to pick up later keys */
+    pub fn keys<'a, T: HtifTransport>(&'a mut self, htif: &'a mut HTIF<T>) -> Keys<'a, T>
+    {
+        Keys { keyboard: self, htif }
+    }
+}
+
+/* iterates over pending key codes without blocking */
+pub struct Keys<'a, T = Mmio>
+{
+    keyboard: &'a mut Keyboard,
+    htif: &'a mut HTIF<T>
+}
+
+impl<T: HtifTransport> Iterator for Keys<'_, T>
+{
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32>
+    {
+        self.keyboard.try_read_key(self.htif).ok().flatten()
+    }
+}
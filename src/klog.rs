@@ -0,0 +1,174 @@
+/* A leveled logger for the global host console, with cycle timestamps
+ *
+ * For kernels that want leveled logging without the log crate. The macros
+ * htif_error!(), htif_warn!(), htif_info!(), htif_debug!(), and htif_trace!()
+ * print lines such as "[      104233] WARN  disk not found" to the global
+ * console, where the number is the hart's cycle counter when the line was
+ * logged, or 0 where there isn't one. Lines more detailed than the level set
+ * with set_level(), Info by default, are dropped without being formatted.
+ *
+ * set_colored(true) prints each line's level in a color of its own, errors in
+ * red, warnings in yellow, and so on, for terminals that understand ANSI escape
+ * sequences. It's off by default, so that output captured to a file stays plain.
+ *
+ * The cycle counter must be readable at the current privilege level: outside
+ * M-mode, rdcycle traps unless enabled in mcounteren (and scounteren in U-mode).
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use super::global;
+use super::ansi::Color;
+use super::cycles::read_cycles;
+
+/* how important a line is, from the most to the least */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level
+{
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5
+}
+
+impl Level
+{
+    /* return the level's name as printed, padded to the same width as the others */
+    pub fn as_str(&self) -> &'static str
+    {
+        match self
+        {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN ",
+            Level::Info => "INFO ",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE"
+        }
+    }
+
+    /* return the color the level's name is printed in, when lines are colored */
+    pub fn color(&self) -> Color
+    {
+        match self
+        {
+            Level::Error => Color::Red,
+            Level::Warn => Color::Yellow,
+            Level::Info => Color::Green,
+            Level::Debug => Color::Cyan,
+            Level::Trace => Color::Magenta
+        }
+    }
+}
+
+/* the most detailed level printed */
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/* true if levels are printed in color */
+static COLORED: AtomicBool = AtomicBool::new(false);
+
+/* print each line's level in its color if on, or plainly if not, such as
+   when output is being captured to a file */
+pub fn set_colored(on: bool)
+{
+    COLORED.store(on, Ordering::Relaxed);
+}
+
+/* print lines at the given level and more important, dropping the rest */
+pub fn set_level(level: Level)
+{
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/* return true if lines at the given level are printed */
+pub fn enabled(level: Level) -> bool
+{
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/* used by the logging macros. output is dropped if the host can't be reached */
+#[doc(hidden)]
+pub fn _log(level: Level, args: fmt::Arguments)
+{
+    if !enabled(level)
+    {
+        return;
+    }
+
+    /* read the clock before waiting on another hart for the console */
+    let cycles = read_cycles().unwrap_or(0);
+    let colored = COLORED.load(Ordering::Relaxed);
+    global::with(|console| write_line(console, cycles, level, colored, args));
+}
+
+fn write_line(out: &mut impl Write, cycles: u64, level: Level, colored: bool, args: fmt::Arguments) -> fmt::Result
+{
+    match colored
+    {
+        /* set the foreground color, and reset it after the name, as ansi does */
+        true => writeln!(out, "[{:>12}] \x1b[{}m{}\x1b[0m {}", cycles, 30 + level.color() as u8, level.as_str(), args),
+        false => writeln!(out, "[{:>12}] {} {}", cycles, level.as_str(), args)
+    }
+}
+
+/* log a line at the given level, such as htif_log!(Level::Info, "found {} harts", n) */
+#[macro_export]
+macro_rules! htif_log
+{
+    ($level:expr, $($arg:tt)+) => ($crate::klog::_log($level, format_args!($($arg)+)));
+}
+
+#[macro_export]
+macro_rules! htif_error
+{
+    ($($arg:tt)+) => ($crate::htif_log!($crate::klog::Level::Error, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! htif_warn
+{
+    ($($arg:tt)+) => ($crate::htif_log!($crate::klog::Level::Warn, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! htif_info
+{
+    ($($arg:tt)+) => ($crate::htif_log!($crate::klog::Level::Info, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! htif_debug
+{
+    ($($arg:tt)+) => ($crate::htif_log!($crate::klog::Level::Debug, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! htif_trace
+{
+    ($($arg:tt)+) => ($crate::htif_log!($crate::klog::Level::Trace, $($arg)+));
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn lines()
+    {
+        let mut out = String::new();
+        write_line(&mut out, 104233, Level::Warn, false, format_args!("disk {} not found", 0)).unwrap();
+        assert_eq!(out, "[      104233] WARN  disk 0 not found\n");
+
+        out.clear();
+        write_line(&mut out, 7, Level::Error, true, format_args!("oops")).unwrap();
+        assert_eq!(out, "[           7] \x1b[31mERROR\x1b[0m oops\n");
+
+        assert!(enabled(Level::Error));
+        assert!(!enabled(Level::Debug));
+    }
+}
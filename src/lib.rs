@@ -6,7 +6,9 @@
  * It requires two global symbols to be defined...
  *  tohost
  *  fromhost
- * ...which when written to and read from triggers an API call to the HTIF provider
+ * ...which when written to and read from triggers an API call to the HTIF provider.
+ * They can be given other names with the custom-symbols feature: see symbols.rs
+ * The registers can instead be reached through another HtifTransport: see transport.rs
  * 
  * (c) Chris Williams, 2021.
  *
@@ -17,40 +19,408 @@
 #![cfg_attr(not(test), no_std)]
 #![allow(dead_code)]
 
-use core::ptr::{write_volatile, read_volatile};
+/* unsafe functions explain their requirements in ordinary comments */
+#![allow(clippy::missing_safety_doc)]
 
-extern "C"
-{
-    /* symbols required by spike: writing to and reading
-       from these memory locations is trapped by the simulator
-       and treated as API calls */
-    static mut tohost: u64;
-    static fromhost: u64;
-}
+use core::sync::atomic::{fence, Ordering};
+use core::cell::{Cell, RefCell};
+use core::task::Waker;
+use demux::Demux;
+use lock::SpinLock;
+use stats::Stats;
+use newline::Newlines;
+use transport::{HtifTransport, Mmio};
+use exit::ExitDevice;
+use protocol::encode_command;
+pub use protocol::FromHostReply;
+use backoff::Backoff;
+use trace::Transaction;
+
+pub mod protocol;
+pub mod transport;
+pub mod mmio;
+pub mod mock;
+pub mod exit;
+pub mod abort;
+pub mod trap;
+pub mod backoff;
+pub mod syscall;
+pub mod file;
+pub mod input;
+pub mod block;
+pub mod device;
+pub mod irq;
+pub mod stats;
+pub mod global;
+pub mod klog;
+pub mod hart;
+pub mod keyboard;
+pub mod framebuffer;
+pub mod graphical;
+pub mod net;
+pub mod time;
+pub mod random;
+pub mod terminal;
+pub mod mainvars;
+pub mod newline;
+pub mod ansi;
+pub mod builder;
+pub mod bytes;
+pub mod buffered;
+pub mod reader;
+pub mod txring;
+pub mod rxring;
+pub mod throughput;
+pub mod xmodem;
+pub mod base64;
+pub mod logqueue;
+pub mod trace;
+pub mod fdt;
+mod line;
+mod hexdump;
+mod number;
+mod bulk;
+mod asynch;
+mod demux;
+mod lock;
+mod symbols;
+mod cycles;
+mod utf8;
+mod format;
+mod font;
+
+#[cfg(feature = "panic-handler")]
+mod panic;
+
+#[cfg(feature = "log")]
+pub mod logger;
+
+#[cfg(feature = "defmt")]
+mod defmt_logger;
+
+#[cfg(feature = "embedded-hal-nb")]
+mod hal;
+
+#[cfg(feature = "embedded-io")]
+mod io;
+
+#[cfg(feature = "ufmt")]
+mod ufmt;
+
+#[cfg(feature = "txlog")]
+pub mod txlog;
+
+#[cfg(feature = "gdbstub")]
+pub mod gdb;
+
+#[cfg(feature = "embedded-storage")]
+pub mod storage;
+
+#[cfg(feature = "line-editor")]
+pub mod editor;
+
+#[cfg(feature = "backtrace")]
+pub mod backtrace;
+
+#[cfg(feature = "monitor")]
+pub mod monitor;
+
+#[cfg(feature = "async")]
+mod io_async;
+
+#[cfg(all(feature = "sbi", any(target_arch = "riscv32", target_arch = "riscv64")))]
+pub mod sbi;
+
+#[cfg(all(feature = "semihosting", any(target_arch = "riscv32", target_arch = "riscv64")))]
+pub mod semihosting;
+
+#[cfg(any(test, feature = "sbi", feature = "semihosting"))]
+mod emulated;
+
+#[cfg(any(test, feature = "fesvr-sim"))]
+pub mod fesvr;
 
 /* total register size is 2 x 8-byte words */
 const REG_TOTAL_SIZE: usize = 2 * 8;
 
-const DEVICE_SHIFT:       u64 = 56; /* bits 63-56 contain the device number */
-const DEVICE_CHARIO:      u64 = 1;  /* device 1 is the blocking character device */
+const DEVICE_SYSCALL:     u64 = 0;  /* device 0 is the syscall proxy, which also handles exits */
+const DEVICE_CHARIO:      u64 = 1;  /* device 1 is the blocking character device, used as the console by default */
 
-const COMMAND_SHIFT:      u64 = 48; /* bits 55-48 contain the command number */
 const COMMAND_READ_CHAR:  u64 = 0;  /* read a character from the host console */
 const COMMAND_WRITE_CHAR: u64 = 1;  /* write a character to the host console */
+const CHAR_VALID:         u64 = 0x100; /* set in a read reply's payload alongside the character */
 
-/* possible error conditions supported at this time */
-#[derive(Debug)]
+/* number of polls probe() waits for a host to respond before concluding there isn't one */
+const PROBE_POLLS:        u64 = 100_000;
+
+/* possible error conditions */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Fault
 {
-    Success /* HTIF API calls don't fail */
+    Timeout, /* the host didn't respond in time */
+    Busy, /* the host has yet to consume an earlier request */
+    InvalidResponse, /* the host's reply didn't match the request */
+    TooManyArguments, /* a proxied syscall was given more arguments than the host accepts */
+    HostError(u64), /* a proxied syscall failed on the host with this errno */
+    PathTooLong, /* a host file path was too long to pass to the host */
+    NotPresent, /* the requested device isn't provided by the host */
+    OutOfRange, /* an access fell outside the bounds of a device */
+    Unsupported, /* the host doesn't support the requested operation or setting */
+    NotAligned, /* an access didn't fall on the boundaries a device requires */
+    Format /* formatted output failed in an argument's own formatting, not the host */
+}
+
+impl core::fmt::Display for Fault
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result
+    {
+        match self
+        {
+            Fault::Timeout => write!(f, "host didn't respond in time"),
+            Fault::Busy => write!(f, "host has yet to consume an earlier request"),
+            Fault::InvalidResponse => write!(f, "host's reply didn't match the request"),
+            Fault::TooManyArguments => write!(f, "too many syscall arguments"),
+            Fault::HostError(errno) => write!(f, "host syscall failed with errno {}", errno),
+            Fault::PathTooLong => write!(f, "host file path too long"),
+            Fault::NotPresent => write!(f, "device not present"),
+            Fault::OutOfRange => write!(f, "access out of range"),
+            Fault::Unsupported => write!(f, "operation not supported by the host"),
+            Fault::NotAligned => write!(f, "access not aligned"),
+            Fault::Format => write!(f, "formatting an argument failed")
+        }
+    }
+}
+
+/* a console write that failed part way through */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartialWrite
+{
+    pub written: usize, /* number of bytes the host took before the failure */
+    pub fault: Fault /* why the write failed */
+}
+
+impl From<PartialWrite> for Fault
+{
+    fn from(e: PartialWrite) -> Self
+    {
+        e.fault
+    }
 }
 
 #[derive(Debug)]
-pub struct HTIF {}
+pub struct HTIF<T = Mmio>
+{
+    transport: T, /* how the tohost and fromhost registers are reached */
+    console: u8, /* device number of the blocking character device used as the console */
+    read_pending: bool, /* true if the console has yet to reply to a read request */
+    irq_enabled: bool, /* true if fromhost is drained by handle_irq() rather than polled */
+    waker: Option<Waker>, /* task to wake when handle_irq() gathers a reply */
+    timeout: Option<u64>, /* number of polls to wait for the host before giving up, or None to wait forever */
+    backoff: Backoff, /* how to pause between polls */
+    bulk_output: Option<usize>, /* send console writes of at least this many bytes via the write syscall */
+    trace: Option<fn(Transaction)>, /* called with every word passed to or from the host */
+    echo: bool, /* true if characters read from the console are written back to it */
+    exit_device: ExitDevice, /* where exit() and reset() requests go */
+    newlines: Newlines, /* line ending translations applied to console traffic */
+    after_cr: bool, /* true if the last byte read from the console was a CR */
+    held: Option<u8>, /* a byte read past by read_char(), to be read again next */
+    replies: RefCell<Demux>, /* replies that arrived while waiting on another device */
+    stats: Cell<Stats> /* counters for diagnostics, updated even by shared methods */
+}
+
+/* HTIF can be sent to another hart if its transport can be, but it isn't Sync,
+   as its counters and reply queue are unsynchronized. so each hart
+   that wants to talk to the host needs a driver of its own, or a lock around one, such
+   as global::with(). drivers on different harts can't corrupt each other's requests,
+   as tohost is only written under TO_HOST_LOCK. they can, however, take each other's
+   replies from fromhost, so only one should wait on replies from a given device */
+
+/* serializes writes to tohost across every driver on every hart */
+#[cfg(not(test))]
+static TO_HOST_LOCK: SpinLock<()> = SpinLock::new(());
+
+#[cfg(not(test))]
+fn to_host_lock() -> &'static SpinLock<()>
+{
+    &TO_HOST_LOCK
+}
+
+/* each test runs on a thread of its own with registers of its own, as if on a hart
+   of its own. give each its own TO_HOST_LOCK, so one test holding it can't stall another */
+#[cfg(test)]
+fn to_host_lock() -> &'static SpinLock<()>
+{
+    std::thread_local!
+    {
+        static LOCK: &'static SpinLock<()> = Box::leak(Box::new(SpinLock::new(())));
+    }
+    LOCK.with(|lock| *lock)
+}
 
 impl HTIF
 {
-    pub fn new() -> Result<Self, Fault> { Ok( HTIF {} ) }
+    /* use the tohost and fromhost registers defined by the linker */
+    pub fn new() -> Result<Self, Fault>
+    {
+        Ok(HTIF::from_symbols())
+    }
+
+    /* use the tohost and fromhost registers at the given addresses, for when
+       they're found at run-time, such as from a device tree, rather than at link-time.
+       this is unsafe as the addresses must point to the host's registers */
+    pub unsafe fn at(to_host: *mut u64, from_host: *mut u64) -> Result<Self, Fault>
+    {
+        Ok(HTIF::over(Mmio::at(to_host, from_host)))
+    }
+
+    /* create a driver for the linker-defined registers, which can't fail */
+    fn from_symbols() -> Self
+    {
+        HTIF::over(Mmio::linker())
+    }
+
+    /* like new(), but only succeeds if a host is actually servicing the registers,
+       failing with NotPresent if not. this allows the same binary to run on real
+       hardware, where tohost and fromhost are ordinary memory, and fall back to
+       another console */
+    pub fn probe() -> Result<Self, Fault>
+    {
+        HTIF::from_symbols().probed()
+    }
+
+    /* like at(), but only succeeds if a host is servicing the registers at the given
+       addresses. this is unsafe as the addresses must be valid memory */
+    pub unsafe fn probe_at(to_host: *mut u64, from_host: *mut u64) -> Result<Self, Fault>
+    {
+        HTIF::at(to_host, from_host)?.probed()
+    }
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* return the transport used to reach the host */
+    pub fn transport(&self) -> &T
+    {
+        &self.transport
+    }
+
+    /* talk to the host through the given transport, rather than the registers in memory */
+    pub fn over(transport: T) -> Self
+    {
+        let htif = HTIF::assemble(transport);
+
+        /* an earlier boot stage may have left a reply in fromhost that it never
+           acknowledged, which would otherwise be mistaken for the answer to our
+           first request, and block the host from posting anything else. throw it away */
+        htif.drain_from_host();
+        htif
+    }
+
+    /* fill in a driver with its default settings without touching the registers,
+       so that it can be done in a const context */
+    const fn assemble(transport: T) -> Self
+    {
+        HTIF
+        {
+            transport,
+            console: DEVICE_CHARIO as u8,
+            read_pending: false,
+            irq_enabled: false,
+            waker: None,
+            timeout: None,
+            backoff: Backoff::DEFAULT,
+            bulk_output: None,
+            trace: None,
+            echo: false,
+            exit_device: ExitDevice::Htif,
+            newlines: Newlines::NONE,
+            after_cr: false,
+            held: None,
+            replies: RefCell::new(Demux::new()),
+            stats: Cell::new(Stats::ZERO)
+        }
+    }
+
+    /* return this driver only if a host answers it, or fail with NotPresent */
+    fn probed(self) -> Result<Self, Fault>
+    {
+        let mut htif = self;
+
+        /* ask the host to identify device 0, which is harmless, and see if anything
+           answers within a reasonable time. if not, take back the request so it
+           doesn't linger in memory */
+        let timeout = htif.timeout.replace(PROBE_POLLS);
+        let answered = htif.identify(DEVICE_SYSCALL as u8).is_ok();
+        htif.timeout = timeout;
+
+        match answered
+        {
+            true => Ok(htif),
+            false =>
+            {
+                htif.transport.write_to_host(0);
+                htif.trace(Transaction::ToHost(0));
+                Err(Fault::NotPresent)
+            }
+        }
+    }
+
+    /* use the blocking character device at the given device number as the console,
+       rather than device 1. call this before using the console */
+    pub const fn with_console(mut self, device: u8) -> Self
+    {
+        self.console = device;
+        self
+    }
+
+    /* give up waiting on the host with a Timeout after polling tohost or fromhost
+       the given number of times, rather than waiting forever. a frontend that has
+       died would otherwise hang the hart. requests that lend the host memory, such
+       as proxied syscalls, only time out before the host takes them. see transact().
+       call this before using the driver */
+    pub const fn with_timeout(mut self, polls: u64) -> Self
+    {
+        self.timeout = Some(polls);
+        self
+    }
+
+    /* pause between polls of the host as the given backoff says, rather than spinning
+       once. call this before using the driver */
+    pub const fn with_backoff(mut self, backoff: Backoff) -> Self
+    {
+        self.backoff = backoff;
+        self
+    }
+
+    /* write characters read from the console back to it, for hosts whose terminal
+       doesn't echo input itself */
+    pub const fn with_echo(mut self, echo: bool) -> Self
+    {
+        self.echo = echo;
+        self
+    }
+
+    /* turn echo on or off after construction, such as while reading a password */
+    pub fn set_echo(&mut self, echo: bool)
+    {
+        self.echo = echo;
+    }
+
+    /* send exit() and reset() requests to the given device rather than the HTIF host,
+       such as QEMU's sifive_test register. see exit.rs. this is unsafe as any
+       register given must be valid */
+    pub const unsafe fn with_exit_device(mut self, device: ExitDevice) -> Self
+    {
+        self.exit_device = device;
+        self
+    }
+
+    /* translate line endings written to and read from the console. see newline.rs */
+    pub const fn with_newlines(mut self, newlines: Newlines) -> Self
+    {
+        self.newlines = newlines;
+        self
+    }
 
     /* return size of this controller's MMIO space in bytes */
     pub fn size(&self) -> usize
@@ -59,50 +429,674 @@ impl HTIF
     }
 
     /* centralize reading and writing of API addresses to these unsafe functions */
-    fn write_to_host(&self, val: u64)
+    fn write_to_host(&self, val: u64) -> Result<(), Fault>
     {
-        unsafe { write_volatile(&mut tohost as *mut u64, val) }
+        /* the host zeroes tohost once it has consumed a request. wait for that
+           before issuing the next one, otherwise it'll be overwritten and lost.
+           another driver on another hart may be waiting for the same thing, so
+           check and write under TO_HOST_LOCK, or both could see zero and write.
+           the lock is only held for that check and write, and is retried like
+           the register itself, so a timeout also covers a lock that's never freed.
+           the write is traced once the lock is dropped, so the hook can print */
+        let mut polls = 0;
+        let result = poll(self.timeout, self.backoff, &mut polls, ||
+        {
+            let _guard = to_host_lock().try_lock()?;
+            match self.read_to_host()
+            {
+                0 =>
+                {
+                    /* the host may read memory we've written, such as syscall arguments and
+                       data buffers, as soon as it sees the request. make sure neither the
+                       compiler nor the CPU holds those writes back until after tohost's */
+                    fence(Ordering::SeqCst);
+                    self.transport.write_to_host(val);
+                    Some(())
+                },
+                _ => None
+            }
+        });
 
-        /* do a delay loop as spike seems to drop characters if we write too fast */
-        for _ in 0..100
+        self.count_polls(polls, &result);
+        if result.is_ok()
         {
-            unsafe { read_volatile(&tohost); }
+            self.trace(Transaction::ToHost(val));
+            self.count(|stats| stats.requests = stats.requests.wrapping_add(1));
         }
+        result.map_err(|_| Fault::Busy)
+    }
+
+    /* wait for the host to consume the request in tohost, if any */
+    fn wait_for_consumption(&self) -> Result<(), Fault>
+    {
+        let mut polls = 0;
+        let result = poll(self.timeout, self.backoff, &mut polls, || match self.read_to_host()
+        {
+            0 => Some(()),
+            _ => None
+        });
+
+        self.count_polls(polls, &result);
+        result
+    }
+
+    fn read_to_host(&self) -> u64
+    {
+        self.transport.read_to_host()
     }
 
-    fn read_from_host(&self) -> u64
+    /* fromhost is the only way to get replies from the host, and it won't post
+       another until we zero the register. so the only way to read it is to
+       take its contents, which acknowledges the reply in the process.
+       returns None if there's no reply waiting */
+    fn take_from_host(&self) -> Option<u64>
     {
-        unsafe { read_volatile(&fromhost) }
+        let val = self.transport.read_from_host();
+        if val == 0
+        {
+            return None;
+        }
+
+        /* likewise, the host may have written to memory before replying. stop any
+           reads of that memory from being carried out before fromhost's */
+        fence(Ordering::SeqCst);
+
+        self.trace(Transaction::FromHost(val));
+        self.transport.write_from_host(0);
+        Some(val)
     }
 
+    /* acknowledge and discard any replies waiting in fromhost.
+       returns the number of replies discarded */
+    fn drain_from_host(&self) -> usize
+    {
+        let mut drained = 0;
+        while self.take_from_host().is_some()
+        {
+            drained += 1;
+        }
+
+        self.count(|stats| stats.dropped_replies = stats.dropped_replies.wrapping_add(drained as u64));
+        drained
+    }
+
+    /* take the oldest reply from the given device, if any, without blocking.
+       this acknowledges the reply so the host can post the next one. replies
+       found for other devices are held until those devices ask for them.
+       in interrupt-driven mode, only replies gathered by handle_irq() are checked */
+    pub fn take_reply(&mut self, device: u8) -> Option<FromHostReply>
+    {
+        match self.irq_enabled
+        {
+            true => self.replies.borrow_mut().take(device),
+            false => self.poll_reply(device)
+        }
+    }
+
+    /* take a reply from the given device as take_reply() does, unless the caller is
+       blocking on it, when fromhost is polled even in interrupt-driven mode, as
+       transact() does. the interrupt may well be masked while a hart blocks,
+       such as in a trap handler, and the reply would otherwise never be gathered */
+    pub(crate) fn next_reply(&mut self, device: u8, blocking: bool) -> Option<FromHostReply>
+    {
+        match blocking
+        {
+            true => self.poll_reply(device),
+            false => self.take_reply(device)
+        }
+    }
+
+    /* check held replies, then fromhost, for a reply from the given device */
+    fn poll_reply(&self, device: u8) -> Option<FromHostReply>
+    {
+        if let Some(reply) = self.replies.borrow_mut().take(device)
+        {
+            return Some(reply);
+        }
+
+        let reply = FromHostReply::decode(self.take_from_host()?);
+        if reply.device == device
+        {
+            return Some(reply);
+        }
+
+        self.hold_reply(reply);
+        None
+    }
+
+    /* hold on to a reply for another device, noting if that pushes out an older one */
+    fn hold_reply(&self, reply: FromHostReply)
+    {
+        if self.replies.borrow_mut().push(reply).is_some()
+        {
+            self.count(|stats| stats.dropped_replies = stats.dropped_replies.wrapping_add(1));
+        }
+    }
+
+    /* forcibly return the registers and driver to a clean state, for when an
+       interrupted transaction, such as one cut short by a panic, has left things
+       in a mess. any request the host has yet to consume is abandoned, and any
+       replies waiting in fromhost or held by the driver are thrown away. the host
+       may still answer an abandoned console read, which is harmless: the character
+       is held and returned by the next read. a hart that panicked or trapped while
+       writing a request may also have left TO_HOST_LOCK held, which it will never
+       release, so the lock is forcibly released too. that's sound once this hart has
+       taken over the console, as the holder won't go on to write tohost. but don't
+       recover while another hart may still be mid-write, such as from a trap
+       handler that can return to the interrupted code, as the two may then collide */
+    pub fn recover(&mut self)
+    {
+        unsafe { to_host_lock().raw_unlock() };
+        self.transport.write_to_host(0);
+        self.trace(Transaction::ToHost(0));
+        self.drain_from_host();
+        let held = self.replies.replace(Demux::new()).len();
+        self.count(|stats| stats.dropped_replies = stats.dropped_replies.wrapping_add(held as u64));
+        self.read_pending = false;
+    }
+
+    /* ask the host to end the simulation with the given exit code. zero means
+       success. the host stops the target once it sees the request, so this
+       function never returns */
+    pub fn exit(&self, code: u32) -> !
+    {
+        match self.exit_device
+        {
+            /* device 0 treats a payload with bit 0 set as an exit request,
+               with the exit code in the bits above */
+            /* there's nothing to be done if the host doesn't respond */
+            ExitDevice::Htif =>
+            {
+                let _ = self.write_to_host(encode_command(DEVICE_SYSCALL as u8, 0, ((code as u64) << 1) | 1));
+            },
+            ExitDevice::SifiveTest(reg) => unsafe { exit::sifive_test_write_exit(reg, code) }
+        }
+
+        /* wait for the host to pull the plug */
+        loop
+        {
+            core::hint::spin_loop();
+        }
+    }
+
+    /* ask the host to restart the simulation from scratch, rather than end it.
+       HTIF has no standard way to do this, so look for a device offering a
+       command named "reset" and use that. only returns, with Unsupported,
+       if the host has no such command. a sifive_test exit device can always reset */
+    pub fn reset(&mut self) -> Result<(), Fault>
+    {
+        match self.exit_device
+        {
+            ExitDevice::Htif =>
+            {
+                let (device, command) = self.find_reset_command()?.ok_or(Fault::Unsupported)?;
+                self.write_to_host(encode_command(device, command, 0))?;
+            },
+            ExitDevice::SifiveTest(reg) => unsafe { exit::sifive_test_write_reset(reg) }
+        }
+
+        /* wait for the host to restart us */
+        loop
+        {
+            core::hint::spin_loop();
+        }
+    }
+
+    /* return the device and command numbers of the first command named "reset", if any.
+       devices number their commands from zero, so stop checking a device at the first
+       command without a name */
+    fn find_reset_command(&mut self) -> Result<Option<(u8, u8)>, Fault>
+    {
+        for device in 0..=u8::MAX
+        {
+            if self.identify(device)?.is_empty()
+            {
+                continue;
+            }
+
+            for command in 0..u8::MAX
+            {
+                let name = self.identify_command(device, command)?;
+                if name.is_empty()
+                {
+                    break;
+                }
+
+                if name.as_bytes() == b"reset"
+                {
+                    return Ok(Some((device, command)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /* report to the host that a riscv-tests style test run passed, and end the simulation */
+    pub fn report_pass(&self) -> !
+    {
+        self.exit(0)
+    }
+
+    /* report to the host that test number testnum failed, and end the simulation.
+       riscv-tests number their tests from 1, and testnum must be non-zero
+       otherwise the host will see a pass */
+    pub fn report_fail(&self, testnum: u32) -> !
+    {
+        self.exit(testnum)
+    }
+
+    /* send a request that lends the host memory, such as a syscall block or buffer
+       on the caller's stack, and wait for its reply. the driver's timeout only covers
+       the host taking the request: if it never does, the request is withdrawn, so
+       the host can't find it after the memory is gone. once taken, the host may write
+       to the memory at any point until it replies, so the reply is then waited on
+       however long it takes. otherwise a late reply would land in a dead stack frame */
+    fn transact(&self, request: u64) -> Result<FromHostReply, Fault>
+    {
+        self.write_to_host(request)?;
+        if let Err(fault) = self.wait_for_consumption()
+        {
+            /* only withdraw the request if it's still ours, under the lock that
+               stops anyone else writing a request in the meantime. as with
+               write_to_host(), trace the withdrawal once the lock is dropped */
+            let withdrawn = match to_host_lock().try_lock()
+            {
+                Some(_guard) if self.read_to_host() == request =>
+                {
+                    self.transport.write_to_host(0);
+                    true
+                },
+                _ => false
+            };
+
+            if withdrawn
+            {
+                self.trace(Transaction::ToHost(0));
+            }
+            return Err(fault);
+        }
+
+        let device = FromHostReply::decode(request).device;
+        let backoff = self.backoff;
+        let mut polls = 0;
+        let result = poll(None, backoff, &mut polls, || self.poll_reply(device));
+        self.count_polls(polls, &result);
+        result
+    }
+
+    /* write a byte to the host console. this only returns success once the host has
+       taken the byte. fails with Busy if the host is stuck on an earlier request,
+       or Timeout if it doesn't take this one, when a timeout is set */
     pub fn send_byte(&self, to_send: u8) -> Result<(), Fault>
     {
-        /* write a character to the blocking character IO device */
-        let device = DEVICE_CHARIO << DEVICE_SHIFT;
-        let command = COMMAND_WRITE_CHAR << COMMAND_SHIFT;
-        let byte = (to_send as u64) & 0xff;
-        self.write_to_host(device | command | byte);
+        self.send_bytes(&[to_send])
+    }
+
+    /* write a buffer of bytes to the host console. see write_bytes().
+       as with send_byte(), this only succeeds once the host has taken every byte */
+    pub fn send_bytes(&self, to_send: &[u8]) -> Result<(), Fault>
+    {
+        self.write_bytes(to_send).map_err(|e| e.fault)
+    }
+
+    /* write a string to the host console. see write_bytes() */
+    pub fn write_str(&self, to_send: &str) -> Result<(), PartialWrite>
+    {
+        self.write_bytes(to_send.as_bytes())
+    }
+
+    /* write a buffer of bytes to the host console, handshaking once per byte, or
+       once per call to the host's write syscall if bulk output is on, see bulk.rs.
+       on failure, the error records how many bytes the host is known to have taken */
+    pub fn write_bytes(&self, to_send: &[u8]) -> Result<(), PartialWrite>
+    {
+        if self.is_bulk(to_send.len())
+        {
+            return self.write_bulk(to_send);
+        }
+
+        let request = encode_command(self.console, COMMAND_WRITE_CHAR as u8, 0);
+        let fail = |written: usize, fault|
+        {
+            self.count(|stats| stats.bytes_sent = stats.bytes_sent.wrapping_add(written as u64));
+            Err(PartialWrite { written, fault })
+        };
+
+        for (sent, byte) in to_send.iter().enumerate()
+        {
+            for (part, wire) in newline::translate_output(byte, self.newlines).iter().enumerate()
+            {
+                /* the previous byte on the wire has been taken once this succeeds.
+                   that belongs to this byte, rather than the one before, after a CR */
+                if let Err(fault) = self.write_to_host(request | *wire as u64)
+                {
+                    return fail(if part == 0 { sent.saturating_sub(1) } else { sent }, fault);
+                }
+            }
+        }
+
+        if let Err(fault) = self.wait_for_consumption()
+        {
+            return fail(to_send.len().saturating_sub(1), fault);
+        }
+
+        self.count(|stats| stats.bytes_sent = stats.bytes_sent.wrapping_add(to_send.len() as u64));
         Ok(())
     }
 
-    pub fn read_byte(&self) -> Result<u8, Fault>
+    /* block until a byte arrives from the host console, and return it */
+    pub fn read_byte(&mut self) -> Result<u8, Fault>
     {
-        /* tell the blocking character IO device we want to read a byte */
-        let device = DEVICE_CHARIO << DEVICE_SHIFT;
-        let command = COMMAND_READ_CHAR << COMMAND_SHIFT;
-        self.write_to_host(device | command);
+        let byte = self.take_byte()?;
+        self.echo_bytes(&[byte])?;
+        Ok(byte)
+    }
+
+    /* like read_byte(), but give up with a Timeout if nothing arrives within the given
+       number of ticks of the hart's cycle counter, whatever the driver's own timeout.
+       fails with Unsupported where there's no cycle counter, such as off RISC-V */
+    pub fn read_byte_timeout(&mut self, cycles: u64) -> Result<u8, Fault>
+    {
+        let start = cycles::read_cycles().ok_or(Fault::Unsupported)?;
+        let backoff = self.backoff;
+        let mut polls = 0;
+        let result = poll(None, backoff, &mut polls, || match self.try_take_byte(true)
+        {
+            Ok(None) => match cycles::read_cycles()
+            {
+                Some(now) if now.wrapping_sub(start) < cycles => None,
+                _ => Some(Err(Fault::Timeout))
+            },
+            result => result.transpose()
+        });
 
-        /* raad that byte */
-        Ok((self.read_from_host() & 0xff) as u8)
+        let byte = result?;
+        self.count_polls(polls, &byte);
+        let byte = byte?;
+        self.echo_bytes(&[byte])?;
+        Ok(byte)
+    }
+
+    /* return a byte from the host console if one is available, or None if not.
+       this never blocks, other than to echo the byte */
+    pub fn try_read_byte(&mut self) -> Result<Option<u8>, Fault>
+    {
+        let byte = self.try_take_byte(false)?;
+        if let Some(b) = byte
+        {
+            self.echo_bytes(&[b])?;
+        }
+        Ok(byte)
+    }
+
+    /* like read_byte(), but never echoes */
+    fn take_byte(&mut self) -> Result<u8, Fault>
+    {
+        let (timeout, backoff) = (self.timeout, self.backoff);
+        let mut polls = 0;
+        let result = poll(timeout, backoff, &mut polls, || self.try_take_byte(true).transpose());
+        self.count_polls(polls, &result);
+        result?
+    }
+
+    /* like try_read_byte(), but never echoes. blocking is true if the
+       caller is waiting for the byte, see next_reply() */
+    fn try_take_byte(&mut self, blocking: bool) -> Result<Option<u8>, Fault>
+    {
+        if let Some(byte) = self.held.take()
+        {
+            return Ok(Some(byte));
+        }
+
+        /* an LF dropped after a CR may have another byte right behind it */
+        loop
+        {
+            let byte = match self.try_receive_byte(blocking)?
+            {
+                Some(byte) => byte,
+                None => return Ok(None)
+            };
+
+            let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+            if let Some(translated) = newline::translate_input(byte, after_cr, self.newlines)
+            {
+                return Ok(Some(translated));
+            }
+        }
+    }
+
+    /* hand a byte to the host console untranslated, without waiting for the host to take it */
+    pub(crate) fn send_raw_byte(&self, byte: u8) -> Result<(), Fault>
+    {
+        self.write_to_host(encode_command(self.console, COMMAND_WRITE_CHAR as u8, byte as u64))?;
+        self.count(|stats| stats.bytes_sent = stats.bytes_sent.wrapping_add(1));
+        Ok(())
+    }
+
+    /* wait for a byte from the host console, untranslated and without echo, polling
+       up to the given number of times, or the driver's timeout if None */
+    pub(crate) fn receive_raw_byte(&mut self, polls: Option<u64>) -> Result<u8, Fault>
+    {
+        let (timeout, backoff) = (polls.or(self.timeout), self.backoff);
+        let mut count = 0;
+        let result = poll(timeout, backoff, &mut count, || self.try_receive_byte(true).transpose());
+        self.count_polls(count, &result);
+        result?
+    }
+
+    /* fetch a byte from the host console, untranslated, if one is available.
+       blocking is true if the caller is waiting for the byte, see next_reply() */
+    fn try_receive_byte(&mut self, blocking: bool) -> Result<Option<u8>, Fault>
+    {
+        /* the host holds on to a read request until a character is available,
+           so only issue a new request once the last one has been answered.
+           every request is answered with a character, none can be spare */
+        if !self.read_pending
+        {
+            self.write_to_host(encode_command(self.console, COMMAND_READ_CHAR as u8, 0))?;
+            self.read_pending = true;
+        }
+
+        match self.next_reply(self.console, blocking)
+        {
+            Some(reply) =>
+            {
+                /* the reply must answer a read, and carry nothing but a character */
+                self.read_pending = false;
+                match reply.command as u64 == COMMAND_READ_CHAR && reply.payload & !0xff == CHAR_VALID
+                {
+                    true =>
+                    {
+                        self.count(|stats| stats.bytes_received = stats.bytes_received.wrapping_add(1));
+                        Ok(Some((reply.payload & 0xff) as u8))
+                    },
+                    false => Err(Fault::InvalidResponse)
+                }
+            },
+            None => Ok(None)
+        }
+    }
+
+    /* write bytes back to the console if echo is on */
+    fn echo_bytes(&self, bytes: &[u8]) -> Result<(), Fault>
+    {
+        match self.echo
+        {
+            true => self.send_bytes(bytes),
+            false => Ok(())
+        }
+    }
+}
+
+/* allow formatted output to the host console with write!() */
+impl<T: HtifTransport> core::fmt::Write for HTIF<T>
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result
+    {
+        self.write_all(s.as_bytes()).map_err(|_| core::fmt::Error)
+    }
+}
+
+/* call check until it returns something, giving up with a Timeout after the
+   given number of polls. if no limit is given, this waits forever. polls is
+   set to the number of times check came up empty */
+fn poll<T>(timeout: Option<u64>, backoff: Backoff, polls: &mut u64, mut check: impl FnMut() -> Option<T>) -> Result<T, Fault>
+{
+    *polls = 0;
+    loop
+    {
+        if let Some(val) = check()
+        {
+            return Ok(val);
+        }
+
+        if let Some(limit) = timeout
+        {
+            if *polls >= limit
+            {
+                return Err(Fault::Timeout);
+            }
+        }
+
+        backoff.pause(*polls);
+        *polls += 1;
     }
 }
 
 #[cfg(test)]
 mod tests
 {
+    use super::*;
+    use mock::MockHtif;
+    use protocol::{DEVICE_SHIFT, COMMAND_SHIFT};
+
     #[test]
     fn it_works()
     {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn decode_reply()
+    {
+        let reply = FromHostReply::decode((1 << DEVICE_SHIFT) | (0 << COMMAND_SHIFT) | 0x100 | b'x' as u64);
+        assert_eq!(reply, FromHostReply { device: 1, command: 0, payload: 0x100 | b'x' as u64 });
+
+        let reply = FromHostReply::decode(0xffff_1234_5678_9abc);
+        assert_eq!(reply, FromHostReply { device: 0xff, command: 0xff, payload: 0x1234_5678_9abc });
+        assert_eq!(reply.encode(), 0xffff_1234_5678_9abc);
+    }
+
+    const CONSOLE_WRITE: u64 = (DEVICE_CHARIO << DEVICE_SHIFT) | (COMMAND_WRITE_CHAR << COMMAND_SHIFT);
+    const CONSOLE_READ: u64 = (DEVICE_CHARIO << DEVICE_SHIFT) | (COMMAND_READ_CHAR << COMMAND_SHIFT);
+
+    fn char_reply(ch: u8) -> FromHostReply
+    {
+        FromHostReply { device: DEVICE_CHARIO as u8, command: COMMAND_READ_CHAR as u8, payload: CHAR_VALID | ch as u64 }
+    }
+
+    #[test]
+    fn console_write()
+    {
+        let htif = HTIF::over(MockHtif::<8>::new()).with_newlines(Newlines::TERMINAL);
+        assert_eq!(htif.send_bytes(b"a\n"), Ok(()));
+        assert_eq!(htif.transport().pop_sent(), Some(CONSOLE_WRITE | b'a' as u64));
+        assert_eq!(htif.transport().pop_sent(), Some(CONSOLE_WRITE | b'\r' as u64));
+        assert_eq!(htif.transport().pop_sent(), Some(CONSOLE_WRITE | b'\n' as u64));
+        assert_eq!(htif.transport().pop_sent(), None);
+        assert_eq!(htif.stats().bytes_sent, 2);
+    }
+
+    #[test]
+    fn console_read_holds_other_replies()
+    {
+        let mut htif = HTIF::over(MockHtif::<8>::new());
+        let other = FromHostReply { device: 2, command: 0, payload: 42 };
+        htif.transport().push_reply(other);
+        htif.transport().push_reply(char_reply(b'x'));
+
+        assert_eq!(htif.read_byte(), Ok(b'x'));
+        assert_eq!(htif.transport().pop_sent(), Some(CONSOLE_READ));
+        assert_eq!(htif.transport().replies_pending(), 0);
+        assert_eq!(htif.take_reply(2), Some(other));
+    }
+
+    std::thread_local!
+    {
+        static HOOK_SENT: Cell<Option<Result<(), Fault>>> = const { Cell::new(None) };
+    }
+
+    /* a trace hook that prints through a driver of its own */
+    fn printing_hook(_: Transaction)
+    {
+        let other = HTIF::over(MockHtif::<8>::new()).with_timeout(1_000_000);
+        HOOK_SENT.with(|sent| sent.set(Some(other.send_byte(b'!'))));
+    }
+
+    #[test]
+    fn trace_hook_can_print()
+    {
+        /* the hook would find tohost locked if it were called under TO_HOST_LOCK */
+        let htif = HTIF::over(MockHtif::<8>::new()).with_trace(printing_hook);
+        assert_eq!(htif.send_byte(b'a'), Ok(()));
+        assert_eq!(HOOK_SENT.with(|sent| sent.get()), Some(Ok(())));
+    }
+
+    #[test]
+    fn recover_releases_tohost_lock()
+    {
+        /* a hart that stopped mid-write never drops its guard */
+        let mut htif = HTIF::over(MockHtif::<8>::new()).with_timeout(10);
+        core::mem::forget(to_host_lock().lock());
+        assert_eq!(htif.send_byte(b'a'), Err(Fault::Busy));
+
+        htif.recover();
+        assert_eq!(htif.send_byte(b'a'), Ok(()));
+    }
+
+    #[test]
+    fn blocking_read_with_irq_masked()
+    {
+        /* no handle_irq() is coming, so a blocking read must find the reply itself,
+           while a non-blocking one leaves fromhost alone */
+        let mut htif = HTIF::over(MockHtif::<8>::new());
+        htif.enable_interrupts();
+        htif.transport().push_reply(char_reply(b'x'));
+
+        assert_eq!(htif.try_read_byte(), Ok(None));
+        assert_eq!(htif.transport().replies_pending(), 1);
+        assert_eq!(htif.read_byte(), Ok(b'x'));
+        assert_eq!(htif.transport().replies_pending(), 0);
+    }
+
+    #[test]
+    fn stalled_host()
+    {
+        let mut htif = HTIF::over(MockHtif::<8>::new()).with_timeout(10);
+        htif.transport().set_stalled(true);
+        assert_eq!(htif.send_byte(b'a'), Err(Fault::Timeout));
+        assert_eq!(htif.send_byte(b'b'), Err(Fault::Busy));
+        assert_eq!(htif.stats().timeouts, 2);
+
+        htif.recover();
+        htif.transport().set_stalled(false);
+        assert_eq!(htif.transport().pop_sent(), None);
+        assert_eq!(htif.send_byte(b'c'), Ok(()));
+        assert_eq!(htif.transport().pop_sent(), Some(CONSOLE_WRITE | b'c' as u64));
+    }
+
+    #[test]
+    fn poll_timeout()
+    {
+        let mut checks = 0;
+        let mut polls = 0;
+        assert_eq!(poll(Some(10), Backoff::None, &mut polls, || { checks += 1; if checks == 5 { Some(checks) } else { None } }), Ok(5));
+        assert_eq!(polls, 4);
+
+        let mut checks = 0;
+        assert_eq!(poll(Some(10), Backoff::None, &mut polls, || { checks += 1; None::<()> }), Err(Fault::Timeout));
+        assert_eq!(checks, 11);
+        assert_eq!(polls, 10);
+    }
 }
@@ -0,0 +1,125 @@
+/* line and record input from the host console
+ *
+ * read_line() assembles a line in the caller's buffer from single characters,
+ * handling backspace and delete, so that simple interactive shells don't have to.
+ * read_until() reads raw records, such as those piped in by a host script.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+
+/* moves the cursor back over an erased character */
+const ERASE_ECHO: &[u8] = b"\x08 \x08";
+
+/* what to do with a character typed into a line */
+#[derive(Debug, PartialEq, Eq)]
+enum Edit
+{
+    Insert, /* add the character to the end of the line */
+    Erase, /* remove the last character in the line */
+    Enter, /* the line is complete */
+    Ignore /* nothing to erase, or no room for the character */
+}
+
+/* decide what to do with byte given a line of len characters in a buffer of capacity bytes */
+fn edit(byte: u8, len: usize, capacity: usize) -> Edit
+{
+    match byte
+    {
+        b'\r' | b'\n' => Edit::Enter,
+        BACKSPACE | DELETE if len > 0 => Edit::Erase,
+        BACKSPACE | DELETE => Edit::Ignore,
+        _ if len < capacity => Edit::Insert,
+        _ => Edit::Ignore
+    }
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* read a line from the host console into buf until Enter is pressed, and return
+       the line's length in bytes, excluding the CR or LF that ended it. characters
+       that don't fit in buf are dropped. each edit is echoed if with_echo() is set */
+    pub fn read_line(&mut self, buf: &mut [u8]) -> Result<usize, Fault>
+    {
+        let mut len = 0;
+        loop
+        {
+            /* echo edits rather than every character read */
+            let byte = self.take_byte()?;
+            match edit(byte, len, buf.len())
+            {
+                Edit::Insert =>
+                {
+                    buf[len] = byte;
+                    len += 1;
+                    self.echo_bytes(&[byte])?;
+                },
+                Edit::Erase =>
+                {
+                    len -= 1;
+                    self.echo_bytes(ERASE_ECHO)?;
+                },
+                Edit::Enter =>
+                {
+                    /* the console's newline translation adds a CR if the terminal needs one */
+                    self.echo_bytes(b"\n")?;
+                    return Ok(len);
+                },
+                Edit::Ignore => ()
+            }
+        }
+    }
+
+    /* read bytes from the host console into buf up to and including the first delim,
+       and return the number of bytes read. this stops early, without the delimiter,
+       if buf fills first. no editing is done */
+    pub fn read_until(&mut self, delim: u8, buf: &mut [u8]) -> Result<usize, Fault>
+    {
+        for (len, slot) in buf.iter_mut().enumerate()
+        {
+            *slot = self.read_byte()?;
+            if *slot == delim
+            {
+                return Ok(len + 1);
+            }
+        }
+
+        Ok(buf.len())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::fesvr::Fesvr;
+    use crate::newline::Newlines;
+
+    #[test]
+    fn line_editing()
+    {
+        assert_eq!(edit(b'a', 0, 4), Edit::Insert);
+        assert_eq!(edit(b'a', 4, 4), Edit::Ignore);
+        assert_eq!(edit(b'\r', 4, 4), Edit::Enter);
+        assert_eq!(edit(b'\n', 0, 4), Edit::Enter);
+        assert_eq!(edit(BACKSPACE, 2, 4), Edit::Erase);
+        assert_eq!(edit(DELETE, 0, 4), Edit::Ignore);
+    }
+
+    #[test]
+    fn enter_echo()
+    {
+        let mut htif = HTIF::over(Fesvr::<64>::new()).with_newlines(Newlines::TERMINAL).with_echo(true);
+        let mut buf = [0; 8];
+        htif.transport().type_input(b"ab\r");
+        assert_eq!(htif.read_line(&mut buf), Ok(2));
+        assert_eq!(&*htif.transport().output(), b"ab\r\n");
+    }
+}
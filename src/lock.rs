@@ -0,0 +1,118 @@
+/* Simple spinlock for sharing the driver between harts
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+pub struct SpinLock<T>
+{
+    locked: AtomicBool,
+    data: UnsafeCell<T>
+}
+
+/* only one hart at a time can get to the data, so it can be shared if it can be sent */
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+unsafe impl<T: Send> Send for SpinLock<T> {}
+
+impl<T> SpinLock<T>
+{
+    pub const fn new(data: T) -> Self
+    {
+        SpinLock { locked: AtomicBool::new(false), data: UnsafeCell::new(data) }
+    }
+
+    /* spin until the lock is ours */
+    pub fn lock(&self) -> SpinLockGuard<'_, T>
+    {
+        loop
+        {
+            if let Some(guard) = self.try_lock()
+            {
+                return guard;
+            }
+
+            core::hint::spin_loop();
+        }
+    }
+
+    /* take the lock if it's free, or return None if not */
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>>
+    {
+        match self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+        {
+            Ok(_) => Some(SpinLockGuard { lock: self }),
+            Err(_) => None
+        }
+    }
+
+    /* spin until the lock is ours, without a guard to unlock it, and return the data.
+       for users whose locking is split across calls. unsafe as the caller must call
+       raw_unlock() when done, and not use the data after */
+    pub unsafe fn raw_lock(&self) -> *mut T
+    {
+        core::mem::forget(self.lock());
+        self.data.get()
+    }
+
+    /* release a lock taken with raw_lock() */
+    pub unsafe fn raw_unlock(&self)
+    {
+        self.locked.store(false, Ordering::Release);
+    }
+}
+
+/* access to the locked data, which is unlocked when this is dropped */
+pub struct SpinLockGuard<'a, T>
+{
+    lock: &'a SpinLock<T>
+}
+
+impl<T> Deref for SpinLockGuard<'_, T>
+{
+    type Target = T;
+
+    fn deref(&self) -> &T
+    {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T>
+{
+    fn deref_mut(&mut self) -> &mut T
+    {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T>
+{
+    fn drop(&mut self)
+    {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn excludes()
+    {
+        let lock = SpinLock::new(1);
+        {
+            let mut guard = lock.lock();
+            *guard += 1;
+            assert!(lock.try_lock().is_none());
+        }
+
+        assert_eq!(*lock.try_lock().unwrap(), 2);
+    }
+}
@@ -0,0 +1,47 @@
+/* Backend for the log crate that writes records to the global host console
+ *
+ * Enabled by the log feature. Call init() once, early on, and the log crate's
+ * macros will print lines of the form "[LEVEL] target: message".
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::fmt::Write;
+use log::{Log, Metadata, Record, LevelFilter, SetLoggerError};
+use super::global;
+
+pub struct HtifLogger;
+
+static LOGGER: HtifLogger = HtifLogger;
+
+impl Log for HtifLogger
+{
+    fn enabled(&self, metadata: &Metadata) -> bool
+    {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record)
+    {
+        if !self.enabled(record.metadata())
+        {
+            return;
+        }
+
+        /* hold the console for the whole line so it isn't interleaved with another hart's */
+        global::with(|console| writeln!(console, "[{}] {}: {}", record.level(), record.target(), record.args()));
+    }
+
+    /* output isn't buffered */
+    fn flush(&self) {}
+}
+
+/* install HtifLogger as the log crate's logger, logging records up to the given level */
+pub fn init(level: LevelFilter) -> Result<(), SetLoggerError>
+{
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(level);
+    Ok(())
+}
@@ -0,0 +1,283 @@
+/* Lock-free log queue for SMP kernels
+ *
+ * Writing to the host console means waiting on it, a byte at a time, with
+ * every other hart that wants to log waiting on the writer. A LogQueue lets any
+ * hart hand over log output without waiting on anything: push() copies the
+ * bytes into a fixed-size ring, and a single hart, such as hart 0 in its idle
+ * loop, or whichever takes the HTIF interrupt, sends them on with drain().
+ *
+ * static LOG: LogQueue<4096> = LogQueue::new();
+ * LOG.push(b"hart 3 up\n");
+ * LOG.drain(&htif)?;
+ *
+ * Each push() is kept together, so lines from different harts never interleave.
+ * When there isn't room for everything pushed, it's thrown away and counted,
+ * rather than making the pushing hart wait.
+ *
+ * The ring is Dmitry Vyukov's bounded queue, in which each slot carries a
+ * sequence number saying which lap of the ring it's ready for. A producer claims
+ * a run of slots by advancing the tail over them, fills them, then publishes
+ * each by bumping its sequence number. The consumer frees slots in order, so
+ * if the last slot of a run is free, so are those before it.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::sync::atomic::{AtomicU8, AtomicUsize, AtomicBool, Ordering};
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+
+/* bytes are sent to the host this many at a time */
+const DRAIN_CHUNK: usize = 64;
+
+struct Slot
+{
+    /* the position this slot is ready for, less the slot's index. the producer
+       writing position p waits for p, and the consumer reading it for p + 1 */
+    seq: AtomicUsize,
+    byte: AtomicU8
+}
+
+impl Slot
+{
+    /* only used to fill in the ring at construction, when each slot gets its own copy */
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Slot = Slot { seq: AtomicUsize::new(0), byte: AtomicU8::new(0) };
+}
+
+pub struct LogQueue<const N: usize>
+{
+    slots: [Slot; N],
+    tail: AtomicUsize, /* next position to be claimed by a producer */
+    head: AtomicUsize, /* next position to be read by the consumer */
+    draining: AtomicBool, /* true while a hart is draining the queue */
+    dropped: AtomicUsize /* bytes thrown away for lack of room */
+}
+
+impl<const N: usize> Default for LogQueue<N>
+{
+    fn default() -> Self
+    {
+        LogQueue::new()
+    }
+}
+
+impl<const N: usize> LogQueue<N>
+{
+    /* a queue holding up to N bytes. this is const so that the queue can be placed in a static.
+       N must be a power of two, so that slots stay in step when positions wrap around */
+    pub const fn new() -> Self
+    {
+        assert!(N.is_power_of_two(), "a LogQueue's size must be a power of two");
+        LogQueue
+        {
+            slots: [Slot::EMPTY; N],
+            tail: AtomicUsize::new(0),
+            head: AtomicUsize::new(0),
+            draining: AtomicBool::new(false),
+            dropped: AtomicUsize::new(0)
+        }
+    }
+
+    /* queue the given bytes to be sent together, from any hart, without waiting.
+       returns false, throwing the bytes away, if there isn't room for all of them */
+    pub fn push(&self, bytes: &[u8]) -> bool
+    {
+        if bytes.is_empty()
+        {
+            return true;
+        }
+
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        let claimed = loop
+        {
+            if bytes.len() > N
+            {
+                break None;
+            }
+
+            /* the run is free if its last slot is ready for this lap */
+            let last = pos.wrapping_add(bytes.len() - 1);
+            match self.seq(last).wrapping_sub(last) as isize
+            {
+                0 => match self.tail.compare_exchange_weak(pos, pos.wrapping_add(bytes.len()),
+                    Ordering::Relaxed, Ordering::Relaxed)
+                {
+                    Ok(_) => break Some(pos),
+                    Err(now) => pos = now
+                },
+
+                /* the consumer has yet to free the slot */
+                diff if diff < 0 => break None,
+
+                /* another producer got here first */
+                _ => pos = self.tail.load(Ordering::Relaxed)
+            }
+        };
+
+        match claimed
+        {
+            Some(start) =>
+            {
+                for (offset, byte) in bytes.iter().enumerate()
+                {
+                    let pos = start.wrapping_add(offset);
+                    let slot = &self.slots[pos % N];
+                    slot.byte.store(*byte, Ordering::Relaxed);
+                    self.set_seq(pos, pos.wrapping_add(1));
+                }
+                true
+            },
+            None =>
+            {
+                self.dropped.fetch_add(bytes.len(), Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    /* queue a string. see push() */
+    pub fn push_str(&self, s: &str) -> bool
+    {
+        self.push(s.as_bytes())
+    }
+
+    /* send everything queued to the host console, returning the number of bytes
+       sent. if another hart is already draining the queue, this returns straight
+       away, leaving it to that hart. bytes the host fails to take are lost */
+    pub fn drain(&self, htif: &HTIF<impl HtifTransport>) -> Result<usize, Fault>
+    {
+        if self.draining.swap(true, Ordering::Acquire)
+        {
+            return Ok(0);
+        }
+
+        let result = self.drain_to(|bytes| htif.send_bytes(bytes));
+        self.draining.store(false, Ordering::Release);
+        result
+    }
+
+    /* return the number of bytes thrown away so far for lack of room */
+    pub fn dropped(&self) -> usize
+    {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /* hand everything queued to send, a chunk at a time. only one hart may do this at once */
+    fn drain_to(&self, mut send: impl FnMut(&[u8]) -> Result<(), Fault>) -> Result<usize, Fault>
+    {
+        let mut chunk = [0; DRAIN_CHUNK];
+        let mut sent = 0;
+        loop
+        {
+            let mut len = 0;
+            while len < DRAIN_CHUNK
+            {
+                match self.pop()
+                {
+                    Some(byte) =>
+                    {
+                        chunk[len] = byte;
+                        len += 1;
+                    },
+                    None => break
+                }
+            }
+
+            if len == 0
+            {
+                return Ok(sent);
+            }
+
+            send(&chunk[..len])?;
+            sent += len;
+        }
+    }
+
+    /* take the oldest published byte, if any */
+    fn pop(&self) -> Option<u8>
+    {
+        let pos = self.head.load(Ordering::Relaxed);
+        if self.seq(pos) != pos.wrapping_add(1)
+        {
+            return None;
+        }
+
+        let byte = self.slots[pos % N].byte.load(Ordering::Relaxed);
+
+        /* make the slot ready for the next lap's producer */
+        self.set_seq(pos, pos.wrapping_add(N));
+        self.head.store(pos.wrapping_add(1), Ordering::Relaxed);
+        Some(byte)
+    }
+
+    /* return the position the slot for the given position is ready for */
+    fn seq(&self, pos: usize) -> usize
+    {
+        let index = pos % N;
+        self.slots[index].seq.load(Ordering::Acquire).wrapping_add(index)
+    }
+
+    fn set_seq(&self, pos: usize, seq: usize)
+    {
+        let index = pos % N;
+        self.slots[index].seq.store(seq.wrapping_sub(index), Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::vec::Vec;
+
+    fn drain(queue: &LogQueue<16>) -> Vec<u8>
+    {
+        let mut out = Vec::new();
+        queue.drain_to(|bytes| { out.extend_from_slice(bytes); Ok(()) }).unwrap();
+        out
+    }
+
+    #[test]
+    fn push_and_drain()
+    {
+        let queue = LogQueue::<16>::new();
+        assert!(queue.push(b"hello "));
+        assert!(queue.push(b"world\n"));
+        assert!(!queue.push(b"no room here"));
+        assert_eq!(queue.dropped(), 12);
+        assert_eq!(drain(&queue), b"hello world\n");
+
+        /* and again, across the end of the ring */
+        assert!(queue.push(b"0123456789abcdef"));
+        assert_eq!(drain(&queue), b"0123456789abcdef");
+    }
+
+    #[test]
+    fn many_producers()
+    {
+        static QUEUE: LogQueue<16> = LogQueue::new();
+        let harts: Vec<_> = (b'a'..=b'd').map(|c| std::thread::spawn(move ||
+        {
+            for _ in 0..250
+            {
+                while !QUEUE.push(&[c; 4])
+                {
+                    std::thread::yield_now();
+                }
+            }
+        })).collect();
+
+        /* consume meanwhile, the runs of each producer's bytes always being whole */
+        let mut out = Vec::new();
+        while out.len() < 4 * 4 * 250
+        {
+            out.extend(drain(&QUEUE));
+            std::thread::yield_now();
+        }
+        harts.into_iter().for_each(|hart| hart.join().unwrap());
+        assert!(out.chunks(4).all(|run| run.iter().all(|b| *b == run[0])));
+    }
+}
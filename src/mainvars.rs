@@ -0,0 +1,126 @@
+/* Fetch the arguments and environment the host passed to the target program
+ *
+ * The getmainvars syscall has the host fill a buffer in target memory with an
+ * argc word, a NULL-terminated array of argv pointers, a NULL-terminated array of
+ * envp pointers, and then the strings themselves. The pointers are target addresses
+ * within the buffer. These are the arguments given to Spike after the program's path.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+use super::syscall::SYS_GETMAINVARS;
+
+const WORD_SIZE: usize = 8;
+
+/* arguments and environment variables as laid out by the host in a buffer */
+#[derive(Debug)]
+pub struct MainVars<'a>
+{
+    buf: &'a [u8],
+    base: u64 /* target address of buf, which the host's pointers are relative to */
+}
+
+impl<'a> MainVars<'a>
+{
+    /* iterate over the program's arguments */
+    pub fn args(&self) -> Vars<'a>
+    {
+        Vars { buf: self.buf, base: self.base, index: 1 }
+    }
+
+    /* iterate over the program's environment variables, as NAME=value strings */
+    pub fn env(&self) -> Vars<'a>
+    {
+        /* envp starts after the argv pointers and their NULL terminator */
+        Vars { buf: self.buf, base: self.base, index: self.argc() + 2 }
+    }
+
+    /* return the number of arguments */
+    pub fn argc(&self) -> usize
+    {
+        word(self.buf, 0).unwrap_or(0) as usize
+    }
+}
+
+/* iterates over a NULL-terminated array of string pointers in the buffer */
+pub struct Vars<'a>
+{
+    buf: &'a [u8],
+    base: u64,
+    index: usize /* word index of the next pointer */
+}
+
+impl<'a> Iterator for Vars<'a>
+{
+    type Item = &'a [u8];
+
+    /* stop at the NULL terminator, or at anything that doesn't point into the buffer */
+    fn next(&mut self) -> Option<&'a [u8]>
+    {
+        let ptr = word(self.buf, self.index)?;
+        let start = ptr.checked_sub(self.base)? as usize;
+        let string = self.buf.get(start..)?;
+        let len = string.iter().position(|&b| b == 0)?;
+
+        self.index += 1;
+        Some(&string[..len])
+    }
+}
+
+/* read the given 8-byte word from the buffer, or None if it's out of bounds */
+fn word(buf: &[u8], index: usize) -> Option<u64>
+{
+    let bytes = buf.get(index * WORD_SIZE..(index + 1) * WORD_SIZE)?;
+    let mut word = [0; WORD_SIZE];
+    word.copy_from_slice(bytes);
+    Some(u64::from_le_bytes(word))
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* have the host write the program's arguments and environment into buf, which
+       must be at a physical address. fails with a HostError if buf is too small */
+    pub fn main_vars<'a>(&mut self, buf: &'a mut [u8]) -> Result<MainVars<'a>, Fault>
+    {
+        let base = buf.as_mut_ptr() as u64;
+        self.proxy_syscall(SYS_GETMAINVARS, &[base, buf.len() as u64])?;
+        Ok(MainVars { buf, base })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parse_args()
+    {
+        /* argc = 2, argv[0], argv[1], NULL, envp NULL, strings, as the host lays them out */
+        let base: u64 = 0x8000_0000;
+        let mut buf = [0u8; 64];
+        buf[0..8].copy_from_slice(&2u64.to_le_bytes());
+        buf[8..16].copy_from_slice(&(base + 40).to_le_bytes());
+        buf[16..24].copy_from_slice(&(base + 46).to_le_bytes());
+        buf[40..46].copy_from_slice(b"hello\0");
+        buf[46..50].copy_from_slice(b"you\0");
+
+        let vars = MainVars { buf: &buf, base };
+        assert_eq!(vars.argc(), 2);
+
+        let mut args = vars.args();
+        assert_eq!(args.next(), Some(&b"hello"[..]));
+        assert_eq!(args.next(), Some(&b"you"[..]));
+        assert_eq!(args.next(), None);
+        assert_eq!(vars.env().next(), None);
+
+        /* pointers outside the buffer end the iteration */
+        buf[8..16].copy_from_slice(&(base - 8).to_le_bytes());
+        let vars = MainVars { buf: &buf, base };
+        assert_eq!(vars.args().next(), None);
+    }
+}
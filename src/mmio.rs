@@ -0,0 +1,214 @@
+/* Volatile access to memory-mapped registers
+ *
+ * MmioReg wraps the address of a register so that every access to it is
+ * volatile, and so can't be merged, reordered, or optimized away by the compiler.
+ * Mmio uses it for tohost and fromhost, and other MMIO drivers in the same
+ * kernel can use it for theirs.
+ *
+ * Volatile accesses are only ordered against each other. When a device reads or
+ * writes ordinary memory, such as a DMA buffer, around an access to one of its
+ * registers, use write_release() and read_acquire() to keep those in order too.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::ptr::{write_volatile, read_volatile};
+use core::sync::atomic::{fence, Ordering};
+
+/* a value that can be read from and written to a register */
+pub trait RegisterValue: Copy
+{
+    /* read the register at the given address. this is unsafe as the address
+       must be valid for a volatile read of the value */
+    unsafe fn read_reg(reg: *const Self) -> Self;
+
+    /* write the register at the given address. this is unsafe as the address
+       must be valid for a volatile write of the value */
+    unsafe fn write_reg(reg: *mut Self, val: Self);
+}
+
+macro_rules! register_value
+{
+    ($($ty:ty),*) =>
+    {
+        $(
+            impl RegisterValue for $ty
+            {
+                unsafe fn read_reg(reg: *const Self) -> Self
+                {
+                    read_volatile(reg)
+                }
+
+                unsafe fn write_reg(reg: *mut Self, val: Self)
+                {
+                    write_volatile(reg, val)
+                }
+            }
+        )*
+    };
+}
+
+register_value!(u8, u16, u32, usize);
+
+/* 64-bit targets can access 64-bit registers in one go */
+#[cfg(target_pointer_width = "64")]
+register_value!(u64);
+
+/* 32-bit targets have to access 64-bit registers in halves, low half first in memory.
+   the device may update a register between our reads of its halves, so re-read
+   the high half to make sure it didn't change underneath us */
+#[cfg(target_pointer_width = "32")]
+impl RegisterValue for u64
+{
+    unsafe fn read_reg(reg: *const Self) -> Self
+    {
+        let halves = reg as *const u32;
+        loop
+        {
+            let hi = read_volatile(halves.add(1));
+            let lo = read_volatile(halves);
+            if read_volatile(halves.add(1)) == hi
+            {
+                return ((hi as u64) << 32) | lo as u64;
+            }
+        }
+    }
+
+    /* see write_order() */
+    unsafe fn write_reg(reg: *mut Self, val: Self)
+    {
+        let halves = reg as *mut u32;
+        for (half, word) in write_order(val)
+        {
+            write_volatile(halves.add(half), word);
+        }
+    }
+}
+
+/* return the halves of val, as indexes into the register's pair of words and their
+   values, in the order a 32-bit target should write them. the host acts on tohost
+   as soon as it sees it change from zero, and requests are only written while
+   tohost is zero. so write any zero half first, which changes nothing, leaving
+   the other to land in a single write, and the host never sees half a request.
+   this covers syscalls and exits, whose high half is zero, and requests with a
+   zero payload, such as console reads. requests with both halves non-zero, such
+   as console writes, can't be written atomically: the host may act on the high
+   half alone, a request with a zero payload, such as a write of NUL, before the
+   low half lands. the high half goes first anyway, as the low half alone would be
+   taken for a syscall or exit. clearing fromhost is safe in either order: the
+   host waits for the whole register to be zero before posting a reply */
+#[cfg(any(test, target_pointer_width = "32"))]
+fn write_order(val: u64) -> [(usize, u32); 2]
+{
+    let (lo, hi) = ((0, val as u32), (1, (val >> 32) as u32));
+    match lo.1
+    {
+        0 => [lo, hi],
+        _ => [hi, lo]
+    }
+}
+
+/* a register holding a value of type T */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmioReg<T>
+{
+    reg: *mut T
+}
+
+/* a register is only an address, which is fine to hand to another hart */
+unsafe impl<T> Send for MmioReg<T> {}
+
+impl<T: RegisterValue> MmioReg<T>
+{
+    /* the register at the given address. this is unsafe as the address must
+       stay valid for volatile reads and writes for as long as the MmioReg is used */
+    pub const unsafe fn new(reg: *mut T) -> Self
+    {
+        MmioReg { reg }
+    }
+
+    /* return the register's address */
+    pub fn addr(&self) -> *mut T
+    {
+        self.reg
+    }
+
+    pub fn read(&self) -> T
+    {
+        unsafe { T::read_reg(self.reg) }
+    }
+
+    pub fn write(&self, val: T)
+    {
+        unsafe { T::write_reg(self.reg, val) }
+    }
+
+    /* replace the register's contents with what the given function makes of them.
+       this is a read followed by a write, so isn't atomic */
+    pub fn modify(&self, f: impl FnOnce(T) -> T)
+    {
+        self.write(f(self.read()))
+    }
+
+    /* read the register, and stop any later reads of memory being carried out
+       before it, for memory the device writes before updating the register */
+    pub fn read_acquire(&self) -> T
+    {
+        let val = self.read();
+        fence(Ordering::SeqCst);
+        val
+    }
+
+    /* write the register once all earlier writes to memory have been carried out,
+       for memory the device reads once it sees the register change */
+    pub fn write_release(&self, val: T)
+    {
+        fence(Ordering::SeqCst);
+        self.write(val)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn read_write_modify()
+    {
+        let mut word = 0u64;
+        let reg = unsafe { MmioReg::new(&mut word as *mut u64) };
+
+        reg.write(0x0101_0000_0000_0041);
+        reg.modify(|val| val | 0x100);
+        assert_eq!(reg.read(), 0x0101_0000_0000_0141);
+        reg.write_release(0);
+        assert_eq!(reg.read_acquire(), 0);
+    }
+
+    /* return every value the host could see in a zeroed register during a split write */
+    fn observed(val: u64) -> [u64; 2]
+    {
+        let mut halves = [0u32; 2];
+        write_order(val).map(|(half, word)|
+        {
+            halves[half] = word;
+            ((halves[1] as u64) << 32) | halves[0] as u64
+        })
+    }
+
+    #[test]
+    fn split_writes()
+    {
+        /* a syscall, an exit, and a console read each become visible in one go */
+        for val in [0x8000_1040, ((3 << 1) | 1), 0x0100_0000_0000_0000]
+        {
+            assert_eq!(observed(val), [0, val]);
+        }
+
+        /* a console write exposes its high half first, never its low half */
+        assert_eq!(observed(0x0101_0000_0000_0041), [0x0101_0000_0000_0000, 0x0101_0000_0000_0041]);
+    }
+}
@@ -0,0 +1,175 @@
+/* A stand-in for the host, for testing code built on the driver without Spike
+ *
+ * MockHtif is an HtifTransport that records every request written to tohost, and
+ * hands scripted replies to the driver through fromhost, one after another:
+ *
+ * let mut htif = HTIF::over(MockHtif::new());
+ * htif.transport().push_reply(FromHostReply { device: 1, command: 0, payload: 0x100 | b'y' as u64 });
+ * assert_eq!(htif.read_byte(), Ok(b'y'));
+ * assert_eq!(htif.transport().pop_sent(), Some(1 << 56));
+ *
+ * Requests are taken as soon as they're written, as if the host were instant,
+ * unless the mock is stalled, when they sit in tohost until it's unstalled.
+ * Only the registers are mocked: the host's side of memory shared with it, such
+ * as syscall return values, isn't.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::cell::{Cell, RefCell};
+use super::FromHostReply;
+use super::transport::HtifTransport;
+
+/* first in, first out store of register words */
+#[derive(Debug)]
+pub(crate) struct Queue<const N: usize>
+{
+    words: [u64; N],
+    head: usize, /* index of the oldest word */
+    pub(crate) len: usize
+}
+
+impl<const N: usize> Queue<N>
+{
+    pub(crate) const fn new() -> Self
+    {
+        Queue { words: [0; N], head: 0, len: 0 }
+    }
+
+    /* add a word, returning false if there's no room */
+    pub(crate) fn push(&mut self, word: u64) -> bool
+    {
+        if self.len == N
+        {
+            return false;
+        }
+
+        self.words[(self.head + self.len) % N] = word;
+        self.len += 1;
+        true
+    }
+
+    pub(crate) fn peek(&self) -> Option<u64>
+    {
+        match self.len
+        {
+            0 => None,
+            _ => Some(self.words[self.head])
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<u64>
+    {
+        let word = self.peek()?;
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(word)
+    }
+}
+
+/* records up to N requests, and holds up to N scripted replies */
+#[derive(Debug)]
+pub struct MockHtif<const N: usize = 64>
+{
+    to_host: Cell<u64>, /* a request the mock has yet to take, while stalled */
+    stalled: Cell<bool>,
+    sent: RefCell<Queue<N>>,
+    replies: RefCell<Queue<N>>
+}
+
+impl<const N: usize> Default for MockHtif<N>
+{
+    fn default() -> Self
+    {
+        MockHtif::new()
+    }
+}
+
+impl<const N: usize> MockHtif<N>
+{
+    pub fn new() -> Self
+    {
+        MockHtif
+        {
+            to_host: Cell::new(0),
+            stalled: Cell::new(false),
+            sent: RefCell::new(Queue::new()),
+            replies: RefCell::new(Queue::new())
+        }
+    }
+
+    /* queue a reply for the driver to find in fromhost after any queued before it.
+       panics if N replies are already queued, as the script is then broken */
+    pub fn push_reply(&self, reply: FromHostReply)
+    {
+        assert!(self.replies.borrow_mut().push(reply.encode()), "too many replies queued on the mock host");
+    }
+
+    /* return the number of queued replies the driver has yet to take */
+    pub fn replies_pending(&self) -> usize
+    {
+        self.replies.borrow().len
+    }
+
+    /* return the oldest request taken from tohost that hasn't been popped yet.
+       once N requests are waiting, later ones are dropped */
+    pub fn pop_sent(&self) -> Option<u64>
+    {
+        self.sent.borrow_mut().pop()
+    }
+
+    /* while stalled, requests are left in tohost, as if the host were busy or dead.
+       unstalling takes the request waiting there, if any */
+    pub fn set_stalled(&self, stalled: bool)
+    {
+        self.stalled.set(stalled);
+        if !stalled
+        {
+            self.take_request();
+        }
+    }
+
+    fn take_request(&self)
+    {
+        let request = self.to_host.replace(0);
+        if request != 0
+        {
+            self.sent.borrow_mut().push(request);
+        }
+    }
+}
+
+impl<const N: usize> HtifTransport for MockHtif<N>
+{
+    fn read_to_host(&self) -> u64
+    {
+        self.to_host.get()
+    }
+
+    fn write_to_host(&self, val: u64)
+    {
+        self.to_host.set(val);
+        if !self.stalled.get()
+        {
+            self.take_request();
+        }
+    }
+
+    fn read_from_host(&self) -> u64
+    {
+        self.replies.borrow().peek().unwrap_or(0)
+    }
+
+    /* zeroing fromhost acknowledges the reply in it, making way for the next */
+    fn write_from_host(&self, val: u64)
+    {
+        let mut replies = self.replies.borrow_mut();
+        match val
+        {
+            0 => { replies.pop(); },
+            _ => panic!("the driver should only ever zero fromhost")
+        }
+    }
+}
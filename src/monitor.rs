@@ -0,0 +1,244 @@
+/* A minimal debug monitor on the host console
+ *
+ * Enabled by the monitor feature. run() prompts for commands on the console
+ * until told to continue or exit, so that a kernel that has crashed, or
+ * reached a point of interest, can be inspected under Spike without a debugger:
+ *
+ *  peek <addr> [size]          print the 1, 2, 4, or 8-byte value at addr, 8 if not given
+ *  poke <addr> <value> [size]  write a 1, 2, 4, or 8-byte value to addr
+ *  dump <addr> <len>           print len bytes from addr as a hex dump
+ *  csr <name>                  print a CSR, such as mcause or satp
+ *  continue                    leave the monitor, returning from run()
+ *  exit [code]                 end the simulation with the given exit code, 0 if not given
+ *  help                        list the commands
+ *
+ * Numbers are decimal, or hex with a 0x prefix. Reading a CSR the hart can't
+ * access at its current privilege level traps, so only ask for those it can.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::ptr::{read_volatile, write_volatile};
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+
+/* longest command line accepted */
+const MAX_LINE_LEN: usize = 80;
+
+const PROMPT: &str = "monitor> ";
+
+const HELP: &str = "\
+peek <addr> [size]          print the 1, 2, 4, or 8-byte value at addr
+poke <addr> <value> [size]  write a 1, 2, 4, or 8-byte value to addr
+dump <addr> <len>           hex dump len bytes from addr
+csr <name>                  print a CSR
+continue                    leave the monitor
+exit [code]                 end the simulation
+";
+
+/* a command typed at the prompt */
+#[derive(Debug, PartialEq, Eq)]
+enum Command<'a>
+{
+    Peek { addr: usize, size: usize },
+    Poke { addr: usize, value: u64, size: usize },
+    Dump { addr: usize, len: usize },
+    Csr(&'a str),
+    Continue,
+    Exit(u32),
+    Help
+}
+
+/* parse a decimal number, or a hex one prefixed with 0x */
+fn parse_number(word: &str) -> Result<u64, &'static str>
+{
+    let parsed = match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => word.parse()
+    };
+    parsed.map_err(|_| "bad number")
+}
+
+fn parse_size(word: Option<&str>) -> Result<usize, &'static str>
+{
+    match word.map(parse_number).transpose()?
+    {
+        None => Ok(8),
+        Some(size @ (1 | 2 | 4 | 8)) => Ok(size as usize),
+        Some(_) => Err("size must be 1, 2, 4, or 8")
+    }
+}
+
+/* turn a line into a command, or None if it's blank */
+fn parse(line: &str) -> Result<Option<Command<'_>>, &'static str>
+{
+    let mut words = line.split_whitespace();
+    let name = match words.next()
+    {
+        Some(name) => name,
+        None => return Ok(None)
+    };
+    let mut arg = || words.next().ok_or("missing argument");
+
+    let command = match name
+    {
+        "peek" =>
+        {
+            let addr = parse_number(arg()?)? as usize;
+            Command::Peek { addr, size: parse_size(arg().ok())? }
+        },
+        "poke" =>
+        {
+            let addr = parse_number(arg()?)? as usize;
+            let value = parse_number(arg()?)?;
+            Command::Poke { addr, value, size: parse_size(arg().ok())? }
+        },
+        "dump" =>
+        {
+            let addr = parse_number(arg()?)? as usize;
+            Command::Dump { addr, len: parse_number(arg()?)? as usize }
+        },
+        "csr" => Command::Csr(arg()?),
+        "continue" | "c" => Command::Continue,
+        "exit" => Command::Exit(arg().ok().map(parse_number).transpose()?.unwrap_or(0) as u32),
+        "help" | "?" => Command::Help,
+        _ => return Err("unknown command, try help")
+    };
+    Ok(Some(command))
+}
+
+/* read the named CSR, or return None if it isn't one the monitor knows */
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+fn read_csr(name: &str) -> Option<usize>
+{
+    /* the CSR must be named in the instruction itself, so there's one per CSR */
+    macro_rules! csrs
+    {
+        ($($csr:literal),*) => (match name
+        {
+            $($csr =>
+            {
+                let val: usize;
+                unsafe { core::arch::asm!(concat!("csrr {}, ", $csr), out(reg) val, options(nomem, nostack)) };
+                Some(val)
+            },)*
+            _ => None
+        });
+    }
+
+    csrs!("mstatus", "misa", "medeleg", "mideleg", "mie", "mtvec", "mscratch", "mepc", "mcause", "mtval",
+        "mip", "mhartid", "sstatus", "sie", "stvec", "sscratch", "sepc", "scause", "stval", "sip", "satp",
+        "cycle", "time", "instret")
+}
+
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+fn read_csr(_name: &str) -> Option<usize>
+{
+    None
+}
+
+/* prompt for and carry out commands on the given console until told to continue.
+   this is unsafe as the commands read and write whatever memory they're told to */
+pub unsafe fn run(htif: &mut HTIF<impl HtifTransport>) -> Result<(), Fault>
+{
+    let mut line = [0; MAX_LINE_LEN];
+    loop
+    {
+        htif.send_bytes(PROMPT.as_bytes())?;
+        let len = htif.read_line(&mut line)?;
+        let command = match core::str::from_utf8(&line[..len])
+        {
+            Ok(text) => parse(text),
+            Err(_) => Err("commands must be text")
+        };
+
+        match command
+        {
+            Ok(Some(Command::Continue)) => return Ok(()),
+            Ok(Some(command)) => execute(htif, command)?,
+            Ok(None) => (),
+            Err(msg) => htif.try_write_fmt(format_args!("error: {}\n", msg))?
+        }
+    }
+}
+
+/* carry out a command other than continue */
+unsafe fn execute(htif: &mut HTIF<impl HtifTransport>, command: Command) -> Result<(), Fault>
+{
+    let unaligned = |addr: usize, size: usize| addr & (size - 1) != 0;
+    match command
+    {
+        Command::Peek { addr, size } | Command::Poke { addr, size, .. } if unaligned(addr, size) =>
+            htif.send_bytes(b"error: address must be aligned to the size\n"),
+
+        Command::Peek { addr, size } =>
+        {
+            let val = match size
+            {
+                1 => read_volatile(addr as *const u8) as u64,
+                2 => read_volatile(addr as *const u16) as u64,
+                4 => read_volatile(addr as *const u32) as u64,
+                _ => read_volatile(addr as *const u64)
+            };
+            htif.try_write_fmt(format_args!("{:#x}: {:#0w$x}\n", addr, val, w = 2 + size * 2))
+        },
+        Command::Poke { addr, value, size } =>
+        {
+            match size
+            {
+                1 => write_volatile(addr as *mut u8, value as u8),
+                2 => write_volatile(addr as *mut u16, value as u16),
+                4 => write_volatile(addr as *mut u32, value as u32),
+                _ => write_volatile(addr as *mut u64, value)
+            }
+            Ok(())
+        },
+        Command::Dump { addr, len } => htif.hexdump(addr as u64, core::slice::from_raw_parts(addr as *const u8, len)),
+        Command::Csr(name) => match read_csr(name)
+        {
+            Some(val) => htif.try_write_fmt(format_args!("{} = {:#x}\n", name, val)),
+            None => htif.try_write_fmt(format_args!("error: unknown CSR {}\n", name))
+        },
+        Command::Exit(code) => htif.exit(code),
+        Command::Help => htif.send_bytes(HELP.as_bytes()),
+        Command::Continue => Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::fesvr::Fesvr;
+
+    #[test]
+    fn parsing()
+    {
+        assert_eq!(parse("  peek 0x80000000 4"), Ok(Some(Command::Peek { addr: 0x8000_0000, size: 4 })));
+        assert_eq!(parse("poke 16 0xff"), Ok(Some(Command::Poke { addr: 16, value: 0xff, size: 8 })));
+        assert_eq!(parse("exit"), Ok(Some(Command::Exit(0))));
+        assert_eq!(parse(""), Ok(None));
+        assert_eq!(parse("peek 1 3"), Err("size must be 1, 2, 4, or 8"));
+        assert_eq!(parse("dump 0x10"), Err("missing argument"));
+        assert_eq!(parse("frobnicate"), Err("unknown command, try help"));
+    }
+
+    #[test]
+    fn session()
+    {
+        let mut word: u32 = 0x1234_5678;
+        let addr = core::ptr::addr_of_mut!(word) as usize;
+        let mut htif = HTIF::over(Fesvr::<256>::new());
+
+        let script = format!("peek {:#x} 4\npoke {:#x} 0xcafe 4\nbogus\nc\n", addr, addr);
+        htif.transport().type_input(script.as_bytes());
+        assert_eq!(unsafe { run(&mut htif) }, Ok(()));
+        assert_eq!(unsafe { read_volatile(addr as *const u32) }, 0xcafe);
+
+        let output = String::from_utf8(htif.transport().output().to_vec()).unwrap();
+        assert_eq!(output, format!("monitor> {:#x}: 0x12345678\nmonitor> monitor> error: unknown command, try help\nmonitor> ", addr));
+    }
+}
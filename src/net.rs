@@ -0,0 +1,102 @@
+/* Send and receive raw Ethernet frames via an HTIF network device
+ *
+ * This is a protocol defined by this crate, not part of HTIF as upstream Spike
+ * and fesvr implement it: stock fesvr has no network device, and neither does
+ * this crate's Fesvr model. It needs a custom frontend written to match, such
+ * as a fesvr extended to bridge the device to a TAP interface on the host.
+ * NetDevice::find() fails with NotPresent anywhere else.
+ *
+ * In this protocol, the host identifies the device as "net". Command 0 sends a
+ * frame and command 1 receives one. Each command's payload is the physical
+ * address of a descriptor naming a frame buffer in target memory, which the
+ * host copies to or from directly.
+ * Sends are acknowledged once the frame is on its way. Receives are answered
+ * straight away with the length of the frame copied in, or zero if none was waiting,
+ * so no buffer is left in the host's hands between calls.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::protocol::encode_command;
+use super::transport::HtifTransport;
+
+const COMMAND_SEND:    u64 = 0; /* transmit the frame in the described buffer */
+const COMMAND_RECEIVE: u64 = 1; /* copy the next received frame into the described buffer */
+
+/* the host's identity for network devices */
+const IDENTITY_PREFIX: &str = "net";
+
+/* largest frame the host will send or receive: 1500 bytes of payload plus a 14-byte header */
+pub const MAX_FRAME_SIZE: usize = 1514;
+
+/* describes a frame buffer to the host */
+#[repr(C, align(8))]
+struct Descriptor
+{
+    addr: u64, /* physical address of the frame buffer */
+    len: u64   /* length of the frame to send, or size of the buffer to receive into */
+}
+
+/* a network interface provided by the host */
+#[derive(Debug)]
+pub struct NetDevice
+{
+    device: u8
+}
+
+impl NetDevice
+{
+    /* use the network device at the given HTIF device number */
+    pub fn new(htif: &mut HTIF<impl HtifTransport>, device: u8) -> Result<Self, Fault>
+    {
+        match htif.identify(device)?.as_str().starts_with(IDENTITY_PREFIX)
+        {
+            true => Ok(NetDevice { device }),
+            false => Err(Fault::NotPresent)
+        }
+    }
+
+    /* use the first network device provided by the host, if any */
+    pub fn find(htif: &mut HTIF<impl HtifTransport>) -> Result<Self, Fault>
+    {
+        match htif.find_device(IDENTITY_PREFIX)?
+        {
+            Some(dev) => NetDevice::new(htif, dev.number),
+            None => Err(Fault::NotPresent)
+        }
+    }
+
+    /* transmit a whole Ethernet frame, which must be at a physical address */
+    pub fn send_frame(&self, htif: &mut HTIF<impl HtifTransport>, frame: &[u8]) -> Result<(), Fault>
+    {
+        if frame.len() > MAX_FRAME_SIZE
+        {
+            return Err(Fault::OutOfRange);
+        }
+
+        self.command(htif, COMMAND_SEND, frame.as_ptr() as u64, frame.len())?;
+        Ok(())
+    }
+
+    /* copy the next received frame, if any, into buf, which must be at a physical
+       address. returns the frame's length, or None if no frame was waiting. frames
+       too long for buf are truncated, so use a buffer of at least MAX_FRAME_SIZE */
+    pub fn recv_frame(&self, htif: &mut HTIF<impl HtifTransport>, buf: &mut [u8]) -> Result<Option<usize>, Fault>
+    {
+        match self.command(htif, COMMAND_RECEIVE, buf.as_mut_ptr() as u64, buf.len())?
+        {
+            0 => Ok(None),
+            len => Ok(Some(core::cmp::min(len as usize, buf.len())))
+        }
+    }
+
+    /* issue a command for the given buffer, and return the host's reply payload */
+    fn command(&self, htif: &mut HTIF<impl HtifTransport>, command: u64, addr: u64, len: usize) -> Result<u64, Fault>
+    {
+        let descriptor = Descriptor { addr, len: len as u64 };
+        Ok(htif.transact(encode_command(self.device, command as u8, &descriptor as *const Descriptor as u64))?.payload)
+    }
+}
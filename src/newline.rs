@@ -0,0 +1,66 @@
+/* newline translation for the host console
+ *
+ * Host terminals and guest software frequently disagree over how lines end.
+ * Output can have each LF expanded to CRLF, and input can have CR and CRLF
+ * collapsed to LF, so the guest only ever deals with LF.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+/* which translations to apply to console traffic. none are applied by default */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Newlines
+{
+    pub crlf_output: bool, /* send each LF written as CR LF */
+    pub lf_input: bool /* deliver each CR or CR LF read as a single LF */
+}
+
+impl Newlines
+{
+    /* pass bytes through untouched */
+    pub const NONE: Newlines = Newlines { crlf_output: false, lf_input: false };
+
+    /* what a raw terminal on the host usually needs */
+    pub const TERMINAL: Newlines = Newlines { crlf_output: true, lf_input: true };
+}
+
+/* return the bytes to send to the host for one byte written */
+pub(crate) fn translate_output(byte: &u8, newlines: Newlines) -> &[u8]
+{
+    match newlines.crlf_output && *byte == b'\n'
+    {
+        true => b"\r\n",
+        false => core::slice::from_ref(byte)
+    }
+}
+
+/* return the byte to deliver for one byte read, or None to drop it,
+   given whether the byte read before it was a CR */
+pub(crate) fn translate_input(byte: u8, after_cr: bool, newlines: Newlines) -> Option<u8>
+{
+    match byte
+    {
+        b'\r' if newlines.lf_input => Some(b'\n'),
+        b'\n' if newlines.lf_input && after_cr => None,
+        _ => Some(byte)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn translation()
+    {
+        assert_eq!(translate_output(&b'\n', Newlines::TERMINAL), b"\r\n");
+        assert_eq!(translate_output(&b'\n', Newlines::NONE), b"\n");
+        assert_eq!(translate_input(b'\r', false, Newlines::TERMINAL), Some(b'\n'));
+        assert_eq!(translate_input(b'\n', true, Newlines::TERMINAL), None);
+        assert_eq!(translate_input(b'\n', false, Newlines::TERMINAL), Some(b'\n'));
+        assert_eq!(translate_input(b'\r', false, Newlines::NONE), Some(b'\r'));
+    }
+}
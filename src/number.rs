@@ -0,0 +1,98 @@
+/* print numbers to the host console without core::fmt
+ *
+ * core::fmt brings a good deal of code, and panic paths, into a small kernel.
+ * These format integers into a buffer on the stack and send that as-is.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+
+pub(crate) const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/* u64::MAX has 16 hex digits and 20 decimal digits */
+const MAX_HEX_DIGITS: usize = 16;
+const MAX_DEC_DIGITS: usize = 20;
+
+/* format val in lowercase hex, padded with zeros to at least min_digits */
+fn format_hex(val: u64, min_digits: usize, out: &mut [u8; MAX_HEX_DIGITS]) -> &[u8]
+{
+    let mut start = MAX_HEX_DIGITS;
+    let mut remaining = val;
+    while start > 0 && (remaining != 0 || MAX_HEX_DIGITS - start < min_digits.max(1))
+    {
+        start -= 1;
+        out[start] = HEX_DIGITS[(remaining & 0xf) as usize];
+        remaining >>= 4;
+    }
+
+    &out[start..]
+}
+
+/* format val in decimal */
+fn format_dec(val: u64, out: &mut [u8; MAX_DEC_DIGITS]) -> &[u8]
+{
+    let mut start = MAX_DEC_DIGITS;
+    let mut remaining = val;
+    loop
+    {
+        start -= 1;
+        out[start] = b'0' + (remaining % 10) as u8;
+        remaining /= 10;
+        if remaining == 0
+        {
+            return &out[start..];
+        }
+    }
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* print val to the host console in hex, prefixed with 0x */
+    pub fn write_u64_hex(&self, val: u64) -> Result<(), Fault>
+    {
+        let mut digits = [0; MAX_HEX_DIGITS];
+        self.send_bytes(b"0x")?;
+        self.send_bytes(format_hex(val, 1, &mut digits))
+    }
+
+    /* print val to the host console in decimal */
+    pub fn write_u64_dec(&self, val: u64) -> Result<(), Fault>
+    {
+        let mut digits = [0; MAX_DEC_DIGITS];
+        self.send_bytes(format_dec(val, &mut digits))
+    }
+
+    /* print an address to the host console in hex, prefixed with 0x and
+       padded with zeros to the width of a pointer, so that columns line up */
+    pub fn write_ptr<P>(&self, ptr: *const P) -> Result<(), Fault>
+    {
+        let mut digits = [0; MAX_HEX_DIGITS];
+        self.send_bytes(b"0x")?;
+        self.send_bytes(format_hex(ptr as usize as u64, core::mem::size_of::<usize>() * 2, &mut digits))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn formatting()
+    {
+        let mut hex = [0; MAX_HEX_DIGITS];
+        assert_eq!(format_hex(0, 1, &mut hex), b"0");
+        assert_eq!(format_hex(0xdead, 1, &mut hex), b"dead");
+        assert_eq!(format_hex(0xdead, 8, &mut hex), b"0000dead");
+        assert_eq!(format_hex(u64::MAX, 16, &mut hex), b"ffffffffffffffff");
+
+        let mut dec = [0; MAX_DEC_DIGITS];
+        assert_eq!(format_dec(0, &mut dec), b"0");
+        assert_eq!(format_dec(1234, &mut dec), b"1234");
+        assert_eq!(format_dec(u64::MAX, &mut dec), b"18446744073709551615");
+    }
+}
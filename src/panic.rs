@@ -0,0 +1,67 @@
+/* Panic handler that reports the panic on the host console and ends the simulation
+ *
+ * Enabled by the panic-handler feature. The code that panicked may have been
+ * using the driver, so this steals the registers and recovers them from whatever
+ * state they were left in, then exits with a failure code. That includes the
+ * lock serializing writes to tohost, should the panic have struck while it was
+ * held. With the txlog feature, it also prints the transactions leading up to
+ * the panic.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::fmt::{Display, Write};
+use super::HTIF;
+use super::transport::HtifTransport;
+
+/* exit code reported to the host on panic */
+const PANIC_EXIT_CODE: u32 = 1;
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> !
+{
+    let mut htif = unsafe { HTIF::steal() };
+    report(&mut htif, info);
+    htif.exit(PANIC_EXIT_CODE)
+}
+
+/* take over the given driver and print the panic on it */
+fn report<T: HtifTransport>(htif: &mut HTIF<T>, info: &impl Display)
+{
+    /* recovering adds to the transaction log, so take a copy of what led up to the panic first */
+    #[cfg(feature = "txlog")]
+    let log = super::txlog::snapshot();
+    htif.recover();
+
+    /* there's nothing else to be done if this fails */
+    let _ = writeln!(htif, "{}", info);
+    #[cfg(feature = "txlog")]
+    let _ = log.dump(htif);
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::fesvr::Fesvr;
+
+    #[test]
+    fn panic_holding_tohost_lock()
+    {
+        /* a panic mid-write leaves the lock held, as the guard is never dropped
+           on targets that abort rather than unwind */
+        let result = std::panic::catch_unwind(||
+        {
+            core::mem::forget(crate::to_host_lock().lock());
+            panic!("mid-write");
+        });
+        assert!(result.is_err());
+
+        let mut htif = HTIF::over(Fesvr::<1024>::new()).with_timeout(10);
+        report(&mut htif, &"mid-write");
+        assert!(htif.transport().output().starts_with(b"mid-write\n"));
+    }
+}
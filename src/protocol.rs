@@ -0,0 +1,80 @@
+/* Pack and unpack HTIF's protocol words
+ *
+ * Every request written to tohost, and every reply read from fromhost, is a
+ * 64-bit word made of three fields: the device number in bits 63-56, the
+ * command in bits 55-48, and a payload in bits 47-0. These functions convert
+ * between words and their fields without touching the registers, so they can
+ * be tested, and fuzzed, on their own.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+pub const DEVICE_SHIFT:  u64 = 56; /* bits 63-56 contain the device number */
+pub const COMMAND_SHIFT: u64 = 48; /* bits 55-48 contain the command number */
+pub const PAYLOAD_MASK:  u64 = (1 << COMMAND_SHIFT) - 1; /* bits 47-0 contain the payload */
+
+/* a reply from the host, decoded from the fromhost register */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FromHostReply
+{
+    pub device: u8,
+    pub command: u8,
+    pub payload: u64
+}
+
+impl FromHostReply
+{
+    /* split a raw fromhost word into its device, command, and payload fields */
+    pub const fn decode(val: u64) -> Self
+    {
+        decode_reply(val)
+    }
+
+    /* pack the reply's fields into a fromhost word, the reverse of decode() */
+    pub const fn encode(&self) -> u64
+    {
+        encode_command(self.device, self.command, self.payload)
+    }
+}
+
+/* return the word asking the given device to carry out the given command. payload
+   bits that don't fit in the word's 48 are dropped */
+pub const fn encode_command(device: u8, command: u8, payload: u64) -> u64
+{
+    ((device as u64) << DEVICE_SHIFT) | ((command as u64) << COMMAND_SHIFT) | (payload & PAYLOAD_MASK)
+}
+
+/* split a word into its device, command, and payload fields. every word is valid */
+pub const fn decode_reply(val: u64) -> FromHostReply
+{
+    FromHostReply
+    {
+        device: (val >> DEVICE_SHIFT) as u8,
+        command: (val >> COMMAND_SHIFT) as u8,
+        payload: val & PAYLOAD_MASK
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn round_trip()
+    {
+        /* a cheap pseudo-random walk through the words, as a stand-in for a property test */
+        let mut word: u64 = 0x9e37_79b9_7f4a_7c15;
+        for _ in 0..1000
+        {
+            let reply = decode_reply(word);
+            assert_eq!(encode_command(reply.device, reply.command, reply.payload), word);
+            word = word.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        }
+
+        assert_eq!(encode_command(1, 1, b'a' as u64), 0x0101_0000_0000_0061);
+        assert_eq!(encode_command(0, 0, u64::MAX), PAYLOAD_MASK);
+    }
+}
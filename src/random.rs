@@ -0,0 +1,48 @@
+/* Random numbers from the host via the syscall proxy
+ *
+ * A simulated system has no entropy of its own to speak of, so host_random()
+ * reads the host's /dev/urandom, which fesvr can do as it proxies file access.
+ * This is as good as the host's random numbers, though anything tracing the
+ * simulation can see them, so they're for seeding, not secrets.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::file::{HostFile, O_RDONLY};
+use super::transport::HtifTransport;
+
+const URANDOM: &str = "/dev/urandom";
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* fill buf with random bytes from the host. buf must be at a physical address,
+       as the host writes to it directly */
+    pub fn host_random(&mut self, buf: &mut [u8]) -> Result<(), Fault>
+    {
+        let urandom = HostFile::open(self, URANDOM, O_RDONLY)?;
+
+        /* close the file even if reading it failed, but report the read's failure first */
+        let filled = fill(self, &urandom, buf);
+        let closed = urandom.close(self);
+        filled.and(closed)
+    }
+}
+
+/* read from file until buf is full */
+fn fill(htif: &mut HTIF<impl HtifTransport>, file: &HostFile, mut buf: &mut [u8]) -> Result<(), Fault>
+{
+    while !buf.is_empty()
+    {
+        /* /dev/urandom never runs dry, so a host that returns nothing can't be trusted to fill buf */
+        let read = match file.read(htif, buf)?
+        {
+            0 => return Err(Fault::InvalidResponse),
+            read => read.min(buf.len())
+        };
+        buf = &mut buf[read..];
+    }
+    Ok(())
+}
@@ -0,0 +1,82 @@
+/* buffered input from the host console, with lookahead
+ *
+ * Parsers often need to look at the next character before deciding what to do
+ * with it, or to put back one they've read. BufferedReader holds up to N such
+ * characters in front of the console, handing them out again before reading more.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::transport::{HtifTransport, Mmio};
+
+pub struct BufferedReader<'a, const N: usize, T = Mmio>
+{
+    htif: &'a mut HTIF<T>,
+    pushed: [u8; N], /* bytes waiting to be read again, the next to be read last */
+    len: usize
+}
+
+impl<'a, const N: usize, T: HtifTransport> BufferedReader<'a, N, T>
+{
+    /* read from the given console, allowing up to N bytes to be pushed back.
+       peeking needs room for one, so N should be at least 1 */
+    pub fn new(htif: &'a mut HTIF<T>) -> Self
+    {
+        BufferedReader { htif, pushed: [0; N], len: 0 }
+    }
+
+    /* block until a byte is available, and return it */
+    pub fn read(&mut self) -> Result<u8, Fault>
+    {
+        match self.len
+        {
+            0 => self.htif.read_byte(),
+            _ =>
+            {
+                self.len -= 1;
+                Ok(self.pushed[self.len])
+            }
+        }
+    }
+
+    /* block until a byte is available, and return it without consuming it */
+    pub fn peek(&mut self) -> Result<u8, Fault>
+    {
+        let byte = self.read()?;
+        self.unread(byte)?;
+        Ok(byte)
+    }
+
+    /* like peek(), but return None rather than block if no byte is ready */
+    pub fn try_peek(&mut self) -> Result<Option<u8>, Fault>
+    {
+        if self.len > 0
+        {
+            return Ok(Some(self.pushed[self.len - 1]));
+        }
+
+        let byte = self.htif.try_read_byte()?;
+        if let Some(b) = byte
+        {
+            self.unread(b)?;
+        }
+        Ok(byte)
+    }
+
+    /* push a byte back so that it's the next one read. fails with OutOfRange
+       if N bytes are already waiting, or with a zero-sized buffer */
+    pub fn unread(&mut self, byte: u8) -> Result<(), Fault>
+    {
+        if self.len == N
+        {
+            return Err(Fault::OutOfRange);
+        }
+
+        self.pushed[self.len] = byte;
+        self.len += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+/* Buffered input from the host console
+ *
+ * The host only holds one character at a time for the guest, and won't post
+ * the next until that one is taken, so keystrokes back up in the host while the
+ * kernel is busy elsewhere, and can be lost if its terminal overflows. An RxRing
+ * collects characters as they arrive, whenever the kernel calls service(), such
+ * as from a timer tick, its idle loop, or after handle_irq() in its HTIF
+ * interrupt handler, and hands them out later through read().
+ *
+ * let mut input = RxRing::<64>::new();
+ * input.service(&mut htif)?;
+ * while let Some(key) = input.read() { ... }
+ *
+ * Characters are translated and echoed as read_byte() would. Those that arrive
+ * when the ring is full are thrown away and counted as overruns.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+
+#[derive(Debug)]
+pub struct RxRing<const N: usize>
+{
+    ring: [u8; N],
+    head: usize, /* index of the oldest byte waiting to be read */
+    len: usize, /* number of bytes waiting to be read */
+    overruns: u64 /* number of bytes thrown away as the ring was full */
+}
+
+impl<const N: usize> Default for RxRing<N>
+{
+    fn default() -> Self
+    {
+        RxRing::new()
+    }
+}
+
+impl<const N: usize> RxRing<N>
+{
+    /* collect up to N bytes of input. this is const so that the ring can be placed in a static */
+    pub const fn new() -> Self
+    {
+        RxRing { ring: [0; N], head: 0, len: 0, overruns: 0 }
+    }
+
+    /* move every byte that has arrived at the given console into the ring, without
+       waiting for more. returns the number of bytes that arrived */
+    pub fn service(&mut self, htif: &mut HTIF<impl HtifTransport>) -> Result<usize, Fault>
+    {
+        let mut arrived = 0;
+        while let Some(byte) = htif.try_read_byte()?
+        {
+            arrived += 1;
+            match self.len < N
+            {
+                true =>
+                {
+                    self.ring[(self.head + self.len) % N] = byte;
+                    self.len += 1;
+                },
+                false => self.overruns += 1
+            }
+        }
+        Ok(arrived)
+    }
+
+    /* return the oldest byte collected, or None if there are none */
+    pub fn read(&mut self) -> Option<u8>
+    {
+        if self.len == 0
+        {
+            return None;
+        }
+
+        let byte = self.ring[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(byte)
+    }
+
+    /* return the number of bytes waiting to be read */
+    pub fn len(&self) -> usize
+    {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len == 0
+    }
+
+    /* return the number of bytes thrown away so far as the ring was full */
+    pub fn overruns(&self) -> u64
+    {
+        self.overruns
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::FromHostReply;
+    use crate::mock::MockHtif;
+
+    fn key(c: u8) -> FromHostReply
+    {
+        FromHostReply { device: 1, command: 0, payload: 0x100 | c as u64 }
+    }
+
+    #[test]
+    fn service()
+    {
+        let mut htif = HTIF::over(MockHtif::<8>::new());
+        let mut input = RxRing::<2>::new();
+
+        for c in b"abc"
+        {
+            htif.transport().push_reply(key(*c));
+        }
+
+        /* everything waiting at the host is taken, whether or not there's room */
+        assert_eq!(input.service(&mut htif), Ok(3));
+        assert_eq!(input.overruns(), 1);
+        assert_eq!(htif.transport().replies_pending(), 0);
+
+        assert_eq!(input.read(), Some(b'a'));
+        assert_eq!(input.read(), Some(b'b'));
+        assert_eq!(input.read(), None);
+        assert_eq!(input.service(&mut htif), Ok(0));
+    }
+}
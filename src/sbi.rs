@@ -0,0 +1,176 @@
+/* An HTIF transport over the RISC-V Supervisor Binary Interface
+ *
+ * Enabled by the sbi feature, on RISC-V targets. An S-mode kernel running under
+ * an SBI implementation such as OpenSBI can't reach tohost and fromhost, but it
+ * can ask the SBI to print and read characters. SbiConsole plays the part of the
+ * host for the console, so the same console API works in either case:
+ *
+ * let mut htif = HTIF::over(SbiConsole::detect());
+ *
+ * Console writes and reads, on whichever device the driver uses as its console,
+ * are turned into SBI calls, and exit() into an SBI system shutdown, by an
+ * EmulatedHost. SBI has nothing matching the other HTIF devices, so they all
+ * identify as absent, and proxied syscalls, including bulk output, fail with
+ * ENOSYS. Leave bulk output off.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::arch::asm;
+use super::transport::HtifTransport;
+use super::emulated::{Backend, EmulatedHost};
+
+/* SBI extension and function IDs */
+const EID_LEGACY_PUTCHAR: usize = 0x01;
+const EID_LEGACY_GETCHAR: usize = 0x02;
+const EID_LEGACY_SHUTDOWN: usize = 0x08;
+const EID_BASE: usize = 0x10;
+const FID_PROBE_EXTENSION: usize = 3;
+const EID_DBCN: usize = 0x4442434e;
+const FID_DBCN_READ: usize = 1;
+const FID_DBCN_WRITE_BYTE: usize = 2;
+const EID_SRST: usize = 0x53525354;
+const FID_SRST_RESET: usize = 0;
+const SRST_SHUTDOWN: usize = 0;
+const SRST_NO_REASON: usize = 0;
+const SRST_FAILURE: usize = 1;
+
+/* which SBI console calls to use */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbiCalls
+{
+    Legacy, /* the original console_putchar and console_getchar, deprecated but widespread */
+    Dbcn /* the debug console extension. reads pass it the address of a byte on the stack,
+            which SBI takes as a physical address, so the stack must be identity mapped */
+}
+
+#[derive(Debug)]
+pub struct SbiConsole
+{
+    calls: SbiCalls,
+    host: EmulatedHost
+}
+
+impl SbiConsole
+{
+    /* use the given SBI console calls */
+    pub const fn new(calls: SbiCalls) -> Self
+    {
+        SbiConsole { calls, host: EmulatedHost::new() }
+    }
+
+    /* use the debug console extension if the SBI implementation has it, or the legacy calls if not */
+    pub fn detect() -> Self
+    {
+        let (error, available) = unsafe { ecall(EID_BASE, FID_PROBE_EXTENSION, EID_DBCN, 0, 0) };
+        match error == 0 && available != 0
+        {
+            true => SbiConsole::new(SbiCalls::Dbcn),
+            false => SbiConsole::new(SbiCalls::Legacy)
+        }
+    }
+}
+
+impl Backend for SbiConsole
+{
+    fn put_byte(&self, byte: u8)
+    {
+        unsafe
+        {
+            match self.calls
+            {
+                SbiCalls::Legacy => { ecall(EID_LEGACY_PUTCHAR, 0, byte as usize, 0, 0); },
+                SbiCalls::Dbcn => { ecall(EID_DBCN, FID_DBCN_WRITE_BYTE, byte as usize, 0, 0); }
+            }
+        }
+    }
+
+    /* return a character if the SBI has one, without blocking */
+    fn get_byte(&self) -> Option<u8>
+    {
+        unsafe
+        {
+            match self.calls
+            {
+                SbiCalls::Legacy =>
+                {
+                    /* legacy calls return their result in a0, and -1 if there's nothing */
+                    let (ch, _) = ecall(EID_LEGACY_GETCHAR, 0, 0, 0, 0);
+                    match ch
+                    {
+                        0..=0xff => Some(ch as u8),
+                        _ => None
+                    }
+                },
+                SbiCalls::Dbcn =>
+                {
+                    let mut byte = 0u8;
+                    let addr = &mut byte as *mut u8 as usize;
+                    let (error, read) = ecall(EID_DBCN, FID_DBCN_READ, 1, addr, 0);
+                    match error == 0 && read == 1
+                    {
+                        true => Some(byte),
+                        false => None
+                    }
+                }
+            }
+        }
+    }
+
+    /* power off, reporting failure if code isn't zero */
+    fn exit(&self, code: u64)
+    {
+        let reason = match code
+        {
+            0 => SRST_NO_REASON,
+            _ => SRST_FAILURE
+        };
+
+        unsafe
+        {
+            /* fall back to the legacy call if system reset isn't available */
+            ecall(EID_SRST, FID_SRST_RESET, SRST_SHUTDOWN, reason, 0);
+            ecall(EID_LEGACY_SHUTDOWN, 0, 0, 0, 0);
+        }
+    }
+}
+
+impl HtifTransport for SbiConsole
+{
+    fn read_to_host(&self) -> u64
+    {
+        self.host.read_to_host()
+    }
+
+    fn write_to_host(&self, val: u64)
+    {
+        self.host.write_to_host(self, val)
+    }
+
+    fn read_from_host(&self) -> u64
+    {
+        self.host.read_from_host(self)
+    }
+
+    fn write_from_host(&self, val: u64)
+    {
+        self.host.write_from_host(val)
+    }
+}
+
+/* make an SBI call, returning its error and value */
+unsafe fn ecall(eid: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize) -> (isize, isize)
+{
+    let error: isize;
+    let value: isize;
+    asm!("ecall",
+         inlateout("a0") arg0 as isize => error,
+         inlateout("a1") arg1 as isize => value,
+         in("a2") arg2,
+         in("a6") fid,
+         in("a7") eid,
+         options(nostack));
+    (error, value)
+}
@@ -0,0 +1,124 @@
+/* An HTIF transport over RISC-V semihosting
+ *
+ * Enabled by the semihosting feature, on RISC-V targets. Debuggers and emulators
+ * that don't implement HTIF, such as OpenOCD and QEMU with -semihosting, often
+ * offer semihosting instead: a magic sequence around an EBREAK that asks the
+ * debugger to carry out a request on the target's behalf. Semihosting plays the
+ * part of the host for the console, so the same console API works either way:
+ *
+ * let mut htif = HTIF::over(Semihosting::new());
+ *
+ * Console writes, and exit(), go to the debugger. Semihosting can only wait for a
+ * character, so a console read blocks inside the transport, even via
+ * try_read_byte(). Other HTIF devices identify as absent, and proxied syscalls,
+ * including bulk output, fail with ENOSYS, as they do over SBI.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::arch::asm;
+use super::transport::HtifTransport;
+use super::emulated::{Backend, EmulatedHost};
+
+/* semihosting operations */
+const SYS_WRITEC: usize = 0x03;
+const SYS_READC: usize = 0x07;
+const SYS_EXIT: usize = 0x18;
+const SYS_EXIT_EXTENDED: usize = 0x20;
+
+/* reason given for an exit */
+const ADP_STOPPED_APPLICATION_EXIT: usize = 0x20026;
+
+#[derive(Debug)]
+pub struct Semihosting
+{
+    host: EmulatedHost
+}
+
+impl Default for Semihosting
+{
+    fn default() -> Self
+    {
+        Semihosting::new()
+    }
+}
+
+impl Semihosting
+{
+    pub const fn new() -> Self
+    {
+        Semihosting { host: EmulatedHost::new() }
+    }
+}
+
+impl Backend for Semihosting
+{
+    fn put_byte(&self, byte: u8)
+    {
+        unsafe { semihost(SYS_WRITEC, &byte as *const u8 as usize); }
+    }
+
+    /* blocks until the debugger has a character */
+    fn get_byte(&self) -> Option<u8>
+    {
+        Some(unsafe { semihost(SYS_READC, 0) } as u8)
+    }
+
+    /* 64-bit targets can pass an exit code with SYS_EXIT. 32-bit targets can't,
+       so use the extended call, which takes the same block on either */
+    fn exit(&self, code: u64)
+    {
+        let block = [ADP_STOPPED_APPLICATION_EXIT, code as usize];
+        let op = match cfg!(target_pointer_width = "64")
+        {
+            true => SYS_EXIT,
+            false => SYS_EXIT_EXTENDED
+        };
+
+        unsafe { semihost(op, block.as_ptr() as usize); }
+    }
+}
+
+impl HtifTransport for Semihosting
+{
+    fn read_to_host(&self) -> u64
+    {
+        self.host.read_to_host()
+    }
+
+    fn write_to_host(&self, val: u64)
+    {
+        self.host.write_to_host(self, val)
+    }
+
+    fn read_from_host(&self) -> u64
+    {
+        self.host.read_from_host(self)
+    }
+
+    fn write_from_host(&self, val: u64)
+    {
+        self.host.write_from_host(val)
+    }
+}
+
+/* make a semihosting request, passing param, and return the result. the debugger
+   recognizes the request by the uncompressed instructions either side of the
+   EBREAK, which must all be in the same page, hence the alignment */
+unsafe fn semihost(op: usize, param: usize) -> usize
+{
+    let result: usize;
+    asm!(".option push",
+         ".option norvc",
+         ".balign 16",
+         "slli zero, zero, 0x1f",
+         "ebreak",
+         "srai zero, zero, 0x7",
+         ".option pop",
+         inlateout("a0") op => result,
+         in("a1") param,
+         options(nostack));
+    result
+}
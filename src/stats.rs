@@ -0,0 +1,71 @@
+/* Count what the driver has been up to, to help diagnose slow or lossy consoles
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::HTIF;
+use super::transport::HtifTransport;
+
+/* snapshot of the driver's counters since it was created or its stats were reset */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats
+{
+    pub bytes_sent: u64,      /* bytes taken by the host console */
+    pub bytes_received: u64,  /* bytes read from the host console */
+    pub requests: u64,        /* requests written to tohost, each a handshake with the host */
+    pub retries: u64,         /* polls of tohost or fromhost that found the host not ready */
+    pub timeouts: u64,        /* operations that gave up waiting on the host */
+    pub dropped_replies: u64  /* replies thrown away, whether stale, unclaimed, or abandoned */
+}
+
+impl Stats
+{
+    /* every counter at zero, for const contexts where Default isn't available */
+    pub const ZERO: Stats = Stats
+    {
+        bytes_sent: 0,
+        bytes_received: 0,
+        requests: 0,
+        retries: 0,
+        timeouts: 0,
+        dropped_replies: 0
+    };
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* return a snapshot of the driver's counters */
+    pub fn stats(&self) -> Stats
+    {
+        self.stats.get()
+    }
+
+    /* zero the driver's counters */
+    pub fn reset_stats(&self)
+    {
+        self.stats.set(Stats::default());
+    }
+
+    /* update the driver's counters, which wrap rather than overflow, as they run forever */
+    pub(crate) fn count(&self, update: impl FnOnce(&mut Stats))
+    {
+        let mut stats = self.stats.get();
+        update(&mut stats);
+        self.stats.set(stats);
+    }
+
+    /* account for a poll() of the host that checked polls + 1 times */
+    pub(crate) fn count_polls<R>(&self, polls: u64, result: &Result<R, super::Fault>)
+    {
+        self.count(|stats|
+        {
+            stats.retries = stats.retries.wrapping_add(polls);
+            if result.is_err()
+            {
+                stats.timeouts = stats.timeouts.wrapping_add(1);
+            }
+        });
+    }
+}
@@ -0,0 +1,230 @@
+/* embedded-storage traits for the HTIF block device
+ *
+ * Enabled by the embedded-storage feature. A DiskStorage borrows the driver and
+ * owns a BlockDevice, and implements ReadStorage and Storage, so no_std
+ * filesystem crates can mount a disk image attached to Spike with +disk=<file>:
+ *
+ * let disk = BlockDevice::find(&mut htif)?;
+ * let storage = DiskStorage::new(&mut htif, disk);
+ *
+ * Reads and writes may start at any byte, and are carried out a sector at a
+ * time via a buffer on the stack, which must be at a physical address. Writes
+ * that only cover part of a sector read the rest of it first.
+ *
+ * embedded-storage has no block device traits as such, so the NOR flash ones are
+ * implemented too, for crates built on them: reads and writes work at any size
+ * and alignment, and erasing fills whole sectors with 0xff, as erased flash reads.
+ * Offsets are 32 bits wide, so only the first 4GiB of a larger disk can be reached.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::ops::Range;
+use embedded_storage::{ReadStorage, Storage};
+use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash};
+use super::{HTIF, Fault};
+use super::block::{BlockDevice, SECTOR_SIZE};
+use super::transport::{HtifTransport, Mmio};
+
+/* bytes erased flash reads as */
+const ERASED: u8 = 0xff;
+
+impl NorFlashError for Fault
+{
+    fn kind(&self) -> NorFlashErrorKind
+    {
+        match self
+        {
+            Fault::OutOfRange => NorFlashErrorKind::OutOfBounds,
+            Fault::NotAligned => NorFlashErrorKind::NotAligned,
+            _ => NorFlashErrorKind::Other
+        }
+    }
+}
+
+pub struct DiskStorage<'a, T = Mmio>
+{
+    htif: &'a mut HTIF<T>,
+    disk: BlockDevice
+}
+
+impl<'a, T: HtifTransport> DiskStorage<'a, T>
+{
+    /* access the given disk via the given driver */
+    pub fn new(htif: &'a mut HTIF<T>, disk: BlockDevice) -> Self
+    {
+        DiskStorage { htif, disk }
+    }
+
+    /* return the disk, to use it directly */
+    pub fn into_inner(self) -> BlockDevice
+    {
+        self.disk
+    }
+
+    /* fail with OutOfRange if len bytes from offset would go beyond the end of the disk */
+    fn check(&self, offset: u32, len: usize) -> Result<(), Fault>
+    {
+        match offset as u64 + len as u64 <= self.disk.capacity()
+        {
+            true => Ok(()),
+            false => Err(Fault::OutOfRange)
+        }
+    }
+}
+
+/* split len bytes from offset into the sectors they cover, returning each sector's number,
+   the range of bytes within it, and the range of bytes within the whole transfer */
+fn pieces(offset: u32, len: usize) -> impl Iterator<Item = (u64, Range<usize>, Range<usize>)>
+{
+    let mut done = 0;
+    core::iter::from_fn(move ||
+    {
+        if done == len
+        {
+            return None;
+        }
+
+        let position = offset as u64 + done as u64;
+        let start = (position % SECTOR_SIZE as u64) as usize;
+        let size = (SECTOR_SIZE - start).min(len - done);
+        let piece = (position / SECTOR_SIZE as u64, start..start + size, done..done + size);
+        done += size;
+        Some(piece)
+    })
+}
+
+/* return the sectors covered by an erase from byte from up to byte to, failing with
+   NotAligned if either isn't on a sector boundary, or OutOfRange if to is before from */
+fn erased_sectors(from: u32, to: u32) -> Result<Range<u64>, Fault>
+{
+    let aligned = |offset: u32| offset as usize & (SECTOR_SIZE - 1) == 0;
+    match (aligned(from) && aligned(to), from <= to)
+    {
+        (false, _) => Err(Fault::NotAligned),
+        (true, false) => Err(Fault::OutOfRange),
+        (true, true) => Ok(from as u64 / SECTOR_SIZE as u64..to as u64 / SECTOR_SIZE as u64)
+    }
+}
+
+impl<T: HtifTransport> ReadStorage for DiskStorage<'_, T>
+{
+    type Error = Fault;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Fault>
+    {
+        self.check(offset, bytes.len())?;
+
+        let mut sector = [0; SECTOR_SIZE];
+        for (number, within, range) in pieces(offset, bytes.len())
+        {
+            self.disk.read_sector(self.htif, number, &mut sector)?;
+            bytes[range].copy_from_slice(&sector[within]);
+        }
+        Ok(())
+    }
+
+    /* the disk's size in bytes, up to the 4GiB that offsets can reach */
+    fn capacity(&self) -> usize
+    {
+        let reachable = self.disk.capacity().min(u32::MAX as u64 + 1);
+        match reachable > usize::MAX as u64
+        {
+            true => usize::MAX,
+            false => reachable as usize
+        }
+    }
+}
+
+impl<T: HtifTransport> Storage for DiskStorage<'_, T>
+{
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Fault>
+    {
+        self.check(offset, bytes.len())?;
+
+        let mut sector = [0; SECTOR_SIZE];
+        for (number, within, range) in pieces(offset, bytes.len())
+        {
+            /* keep the parts of the sector this write doesn't cover */
+            if within.len() < SECTOR_SIZE
+            {
+                self.disk.read_sector(self.htif, number, &mut sector)?;
+            }
+            sector[within].copy_from_slice(&bytes[range]);
+            self.disk.write_sector(self.htif, number, &sector)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: HtifTransport> ErrorType for DiskStorage<'_, T>
+{
+    type Error = Fault;
+}
+
+impl<T: HtifTransport> ReadNorFlash for DiskStorage<'_, T>
+{
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Fault>
+    {
+        ReadStorage::read(self, offset, bytes)
+    }
+
+    fn capacity(&self) -> usize
+    {
+        ReadStorage::capacity(self)
+    }
+}
+
+impl<T: HtifTransport> NorFlash for DiskStorage<'_, T>
+{
+    const WRITE_SIZE: usize = 1;
+    const ERASE_SIZE: usize = SECTOR_SIZE;
+
+    /* fill the sectors from byte from up to byte to with 0xff. both must be on sector
+       boundaries, or this fails with NotAligned */
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Fault>
+    {
+        let sectors = erased_sectors(from, to)?;
+        self.check(from, (to - from) as usize)?;
+
+        let erased = [ERASED; SECTOR_SIZE];
+        for number in sectors
+        {
+            self.disk.write_sector(self.htif, number, &erased)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Fault>
+    {
+        Storage::write(self, offset, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn split_into_sectors()
+    {
+        let split: Vec<_> = pieces(500, 600).collect();
+        assert_eq!(split, [(0, 500..512, 0..12), (1, 0..512, 12..524), (2, 0..76, 524..600)]);
+        assert_eq!(pieces(1024, 512).collect::<Vec<_>>(), [(2, 0..512, 0..512)]);
+        assert_eq!(pieces(7, 0).count(), 0);
+    }
+
+    #[test]
+    fn erase_alignment()
+    {
+        assert_eq!(erased_sectors(512, 1536), Ok(1..3));
+        assert_eq!(erased_sectors(1, 512).map_err(|e| e.kind()), Err(NorFlashErrorKind::NotAligned));
+        assert_eq!(erased_sectors(0, 100).map_err(|e| e.kind()), Err(NorFlashErrorKind::NotAligned));
+        assert_eq!(erased_sectors(1024, 512).map_err(|e| e.kind()), Err(NorFlashErrorKind::OutOfBounds));
+    }
+}
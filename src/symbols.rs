@@ -0,0 +1,130 @@
+/* Find the linker's tohost and fromhost registers
+ *
+ * Spike looks the registers up by name, so by default the driver expects the
+ * executable to define two symbols called tohost and fromhost. Some toolchains
+ * and bootloaders export them under other names, or need them in a particular
+ * section. With the custom-symbols feature, the crate no longer refers to
+ * tohost and fromhost itself, and the executable must instead use htif_symbols!
+ * exactly once to tell the driver where its registers are:
+ *
+ *  mmio_htif::htif_symbols!(_tohost, _fromhost);
+ *
+ * binds the driver to registers the executable defines elsewhere as _tohost and
+ * _fromhost, for example in assembly or a linker script, and
+ *
+ *  mmio_htif::htif_symbols!(define _tohost, _fromhost, section = ".htif");
+ *
+ * defines them, zeroed, in the .htif section. HTIF::new(), global::take() and
+ * everything else that uses the linker's registers then finds them via the macro.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+#[cfg(not(feature = "custom-symbols"))]
+mod linker
+{
+    use core::ptr::addr_of_mut;
+
+    extern "C"
+    {
+        /* symbols required by spike: writing to and reading
+           from these memory locations is trapped by the simulator
+           and treated as API calls */
+        static mut tohost: u64;
+        static mut fromhost: u64;
+    }
+
+    pub fn to_host() -> *mut u64
+    {
+        addr_of_mut!(tohost)
+    }
+
+    pub fn from_host() -> *mut u64
+    {
+        addr_of_mut!(fromhost)
+    }
+}
+
+#[cfg(feature = "custom-symbols")]
+mod linker
+{
+    extern "C"
+    {
+        /* defined by htif_symbols! in the executable */
+        fn __mmio_htif_tohost() -> *mut u64;
+        fn __mmio_htif_fromhost() -> *mut u64;
+    }
+
+    pub fn to_host() -> *mut u64
+    {
+        unsafe { __mmio_htif_tohost() }
+    }
+
+    pub fn from_host() -> *mut u64
+    {
+        unsafe { __mmio_htif_fromhost() }
+    }
+}
+
+pub(crate) use linker::{to_host, from_host};
+
+/* bind the driver to the given tohost and fromhost symbols, defining them
+   first, in the given section if there is one, when prefixed with define.
+   this needs the custom-symbols feature, and must be used exactly once */
+#[macro_export]
+macro_rules! htif_symbols
+{
+    (define $to_host:ident, $from_host:ident, section = $section:literal) =>
+    {
+        #[no_mangle]
+        #[link_section = $section]
+        static mut $to_host: u64 = 0;
+
+        #[no_mangle]
+        #[link_section = $section]
+        static mut $from_host: u64 = 0;
+
+        $crate::htif_symbols!(@accessors $to_host, $from_host);
+    };
+
+    (define $to_host:ident, $from_host:ident) =>
+    {
+        #[no_mangle]
+        static mut $to_host: u64 = 0;
+
+        #[no_mangle]
+        static mut $from_host: u64 = 0;
+
+        $crate::htif_symbols!(@accessors $to_host, $from_host);
+    };
+
+    ($to_host:ident, $from_host:ident) =>
+    {
+        extern "C"
+        {
+            static mut $to_host: u64;
+            static mut $from_host: u64;
+        }
+
+        $crate::htif_symbols!(@accessors $to_host, $from_host);
+    };
+
+    (@accessors $to_host:ident, $from_host:ident) =>
+    {
+        #[no_mangle]
+        #[allow(unused_unsafe)]
+        extern "C" fn __mmio_htif_tohost() -> *mut u64
+        {
+            unsafe { core::ptr::addr_of_mut!($to_host) }
+        }
+
+        #[no_mangle]
+        #[allow(unused_unsafe)]
+        extern "C" fn __mmio_htif_fromhost() -> *mut u64
+        {
+            unsafe { core::ptr::addr_of_mut!($from_host) }
+        }
+    };
+}
@@ -0,0 +1,141 @@
+/* Proxy system calls to the host via HTIF device 0
+ *
+ * The target fills in a block of memory with a syscall number and its arguments,
+ * and writes the block's address to tohost. The host carries out the call,
+ * writes the return value into the first word of the block, and replies via fromhost.
+ * Syscall numbers and semantics follow Linux's generic ABI, as used by Spike's fesvr.
+ *
+ * Any addresses passed to the host, including the block itself, must be physical
+ * addresses: the host accesses target memory directly, bypassing any paging.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::ptr::{write_volatile, read_volatile};
+use super::{HTIF, Fault, DEVICE_SYSCALL};
+use super::protocol::encode_command;
+use super::transport::HtifTransport;
+
+/* syscalls supported by fesvr */
+pub const SYS_GETCWD:      u64 = 17;
+pub const SYS_FCNTL:       u64 = 25;
+pub const SYS_MKDIRAT:     u64 = 34;
+pub const SYS_UNLINKAT:    u64 = 35;
+pub const SYS_LINKAT:      u64 = 37;
+pub const SYS_RENAMEAT:    u64 = 38;
+pub const SYS_FTRUNCATE:   u64 = 46;
+pub const SYS_FACCESSAT:   u64 = 48;
+pub const SYS_CHDIR:       u64 = 49;
+pub const SYS_OPENAT:      u64 = 56;
+pub const SYS_CLOSE:       u64 = 57;
+pub const SYS_LSEEK:       u64 = 62;
+pub const SYS_READ:        u64 = 63;
+pub const SYS_WRITE:       u64 = 64;
+pub const SYS_PREAD:       u64 = 67;
+pub const SYS_PWRITE:      u64 = 68;
+pub const SYS_FSTATAT:     u64 = 79;
+pub const SYS_FSTAT:       u64 = 80;
+pub const SYS_EXIT:        u64 = 93;
+pub const SYS_GETMAINVARS: u64 = 2011;
+
+/* syscalls that fesvr doesn't proxy, though other frontends may. beware
+   fesvr treats unknown syscalls as fatal errors and ends the simulation */
+pub const SYS_IOCTL:         u64 = 29;
+pub const SYS_CLOCK_GETTIME: u64 = 113;
+pub const SYS_GETTIMEOFDAY:  u64 = 169;
+
+/* the host reads and writes back this many 8-byte words per call:
+   the syscall number followed by up to seven arguments */
+const MAGIC_MEM_WORDS: usize = 8;
+const MAX_ARGS: usize = MAGIC_MEM_WORDS - 1;
+
+/* the host replies with this payload once the call is complete */
+const SYSCALL_DONE: u64 = 1;
+
+/* the host returns -errno on failure, and errno values never exceed this */
+const MAX_ERRNO: u64 = 4095;
+
+/* block of memory passed to the host for each call. its first word holds the
+   syscall number on the way in, and the return value on the way out */
+#[repr(C, align(64))]
+struct MagicMem([u64; MAGIC_MEM_WORDS]);
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* ask the host to perform syscall number num with the given arguments, of
+       which there can be at most seven. returns the call's result, or the host's
+       errno in a HostError if the call failed */
+    pub fn proxy_syscall(&self, num: u64, args: &[u64]) -> Result<u64, Fault>
+    {
+        if args.len() > MAX_ARGS
+        {
+            return Err(Fault::TooManyArguments);
+        }
+
+        /* the host reads this block behind the compiler's back, so fill it in
+           with volatile writes to make sure the values land before the request */
+        let mut mem = MagicMem([0; MAGIC_MEM_WORDS]);
+        let words = mem.0.as_mut_ptr();
+        unsafe
+        {
+            write_volatile(words, num);
+            for (index, arg) in args.iter().enumerate()
+            {
+                write_volatile(words.add(index + 1), *arg);
+            }
+        }
+
+        /* command 0 on the syscall device, with the block's address as the payload.
+           the block's alignment keeps bit 0 clear, so this won't look like an exit */
+        if self.transact(encode_command(DEVICE_SYSCALL as u8, 0, words as u64))?.payload != SYSCALL_DONE
+        {
+            return Err(Fault::InvalidResponse);
+        }
+
+        syscall_result(unsafe { read_volatile(words) })
+    }
+}
+
+/* turn a raw syscall return value into a result, separating out -errno failures */
+fn syscall_result(result: u64) -> Result<u64, Fault>
+{
+    let errno = result.wrapping_neg();
+    match errno
+    {
+        1..=MAX_ERRNO => Err(Fault::HostError(errno)),
+        _ => Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::mock::MockHtif;
+
+    #[test]
+    fn result_decoding()
+    {
+        assert!(matches!(syscall_result(0), Ok(0)));
+        assert!(matches!(syscall_result(42), Ok(42)));
+        assert!(matches!(syscall_result(-2i64 as u64), Err(Fault::HostError(2))));
+        assert!(matches!(syscall_result(-4095i64 as u64), Err(Fault::HostError(4095))));
+        assert!(syscall_result(-4096i64 as u64).is_ok());
+    }
+
+    #[test]
+    fn untaken_call_withdrawn()
+    {
+        /* the host never takes the request, so it must not be left in tohost
+           pointing at the block on this stack frame */
+        let htif = HTIF::over(MockHtif::<8>::new()).with_timeout(10);
+        htif.transport().set_stalled(true);
+        assert_eq!(htif.proxy_syscall(SYS_CLOSE, &[3]), Err(Fault::Timeout));
+        assert_eq!(htif.transport().read_to_host(), 0);
+
+        htif.transport().set_stalled(false);
+        assert_eq!(htif.transport().pop_sent(), None);
+    }
+}
@@ -0,0 +1,107 @@
+/* Query and control the host's terminal via the syscall proxy
+ *
+ * These proxy ioctl() on the host's standard input and output, so they need
+ * a frontend that proxies it, which fesvr doesn't: it ends the simulation if
+ * asked for a syscall it doesn't know.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::ptr::read_volatile;
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+use super::syscall::SYS_IOCTL;
+
+/* the host's standard input and output, which are the terminal if there is one */
+const STDIN: u64 = 0;
+const STDOUT: u64 = 1;
+
+/* ioctl() requests, from Linux's generic ABI */
+const TCGETS: u64 = 0x5401; /* get the terminal's settings */
+const TCSETS: u64 = 0x5402; /* change the terminal's settings */
+const TIOCGWINSZ: u64 = 0x5413; /* get the terminal's size */
+
+/* terminal settings touched by set_input_mode() */
+const ICANON: u32 = 0o2; /* c_lflag: gather input into lines */
+const ECHO: u32 = 0o10; /* c_lflag: echo input */
+const VTIME: usize = 5; /* c_cc: how long a raw read waits, in tenths of a second */
+const VMIN: usize = 6; /* c_cc: how many bytes a raw read waits for */
+const NCCS: usize = 19;
+
+/* how the host's terminal delivers keystrokes */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode
+{
+    Cooked, /* line by line once Enter is pressed, echoed and editable by the terminal */
+    Raw /* each keystroke as it's pressed, without echo */
+}
+
+/* layout of the host's struct winsize */
+#[repr(C)]
+struct WinSize
+{
+    rows: u16,
+    cols: u16,
+    x_pixels: u16,
+    y_pixels: u16
+}
+
+/* layout of the host kernel's struct termios, as passed to ioctl() */
+#[repr(C)]
+struct Termios
+{
+    iflag: u32,
+    oflag: u32,
+    cflag: u32,
+    lflag: u32,
+    line: u8,
+    cc: [u8; NCCS]
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* return the host terminal's width and height in characters, or None if
+       output isn't going to a terminal, or its size isn't known */
+    pub fn terminal_size(&mut self) -> Option<(u16, u16)>
+    {
+        let mut size = WinSize { rows: 0, cols: 0, x_pixels: 0, y_pixels: 0 };
+        let ptr = &mut size as *mut WinSize;
+        self.proxy_syscall(SYS_IOCTL, &[STDOUT, TIOCGWINSZ, ptr as u64]).ok()?;
+
+        /* the host wrote into size behind the compiler's back. a terminal
+           that doesn't know its size, such as a serial line, reports zero */
+        let size = unsafe { read_volatile(ptr) };
+        match (size.cols, size.rows)
+        {
+            (0, _) | (_, 0) => None,
+            dimensions => Some(dimensions)
+        }
+    }
+
+    /* switch the host's terminal between delivering input line by line and key by key.
+       signal keys such as ctrl-c still work in raw mode, so the simulation can be stopped */
+    pub fn set_input_mode(&mut self, mode: InputMode) -> Result<(), Fault>
+    {
+        let mut termios = Termios { iflag: 0, oflag: 0, cflag: 0, lflag: 0, line: 0, cc: [0; NCCS] };
+        let ptr = &mut termios as *mut Termios;
+        self.proxy_syscall(SYS_IOCTL, &[STDIN, TCGETS, ptr as u64])?;
+
+        /* the host wrote into termios behind the compiler's back */
+        let mut termios = unsafe { read_volatile(ptr) };
+        match mode
+        {
+            InputMode::Cooked => termios.lflag |= ICANON | ECHO,
+            InputMode::Raw =>
+            {
+                termios.lflag &= !(ICANON | ECHO);
+                termios.cc[VMIN] = 1;
+                termios.cc[VTIME] = 0;
+            }
+        }
+
+        self.proxy_syscall(SYS_IOCTL, &[STDIN, TCSETS, &termios as *const Termios as u64])?;
+        Ok(())
+    }
+}
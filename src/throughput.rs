@@ -0,0 +1,109 @@
+/* Measure how fast the host console takes output
+ *
+ * How much buffering and what backoff suit a host depend on how quickly it
+ * services the console, which differs wildly between Spike, QEMU, and FPGA
+ * bridges to fesvr, and with how they're configured. measure_throughput()
+ * writes a known pattern to the console, timing it with the hart's cycle
+ * counter, and reports what it took:
+ *
+ * let result = htif.measure_throughput(4096)?;
+ * writeln!(htif, "{}", result);
+ *
+ * The cycle counter must be readable at the current privilege level: outside
+ * M-mode, rdcycle traps unless enabled in mcounteren (and scounteren in U-mode).
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::fmt;
+use super::{HTIF, Fault};
+use super::cycles::read_cycles;
+use super::transport::HtifTransport;
+
+/* the pattern repeats the printable ASCII characters, in lines of this length */
+const LINE_LEN: usize = 64;
+const FIRST_CHAR: u8 = b'!';
+const CHARS: usize = (b'~' - FIRST_CHAR + 1) as usize;
+
+/* results of a throughput measurement */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Throughput
+{
+    pub bytes: usize, /* bytes written to the console */
+    pub cycles: u64,  /* cycles taken to write them */
+    pub retries: u64  /* polls of the host that found it not ready meanwhile */
+}
+
+impl Throughput
+{
+    /* return the average number of cycles each byte took */
+    pub fn cycles_per_byte(&self) -> u64
+    {
+        self.cycles / (self.bytes.max(1) as u64)
+    }
+}
+
+impl fmt::Display for Throughput
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{} bytes in {} cycles, {} cycles per byte, {} retries",
+            self.bytes, self.cycles, self.cycles_per_byte(), self.retries)
+    }
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* write len bytes of a test pattern to the console, and return how long it took.
+       fails with Unsupported where there's no cycle counter, such as off RISC-V */
+    pub fn measure_throughput(&self, len: usize) -> Result<Throughput, Fault>
+    {
+        let mut line = [0; LINE_LEN];
+        let retries = self.stats().retries;
+        let start = read_cycles().ok_or(Fault::Unsupported)?;
+
+        let mut sent = 0;
+        while sent < len
+        {
+            let chunk = (len - sent).min(LINE_LEN);
+            for (index, byte) in line[..chunk].iter_mut().enumerate()
+            {
+                *byte = pattern(sent + index);
+            }
+            self.send_bytes(&line[..chunk])?;
+            sent += chunk;
+        }
+
+        let cycles = read_cycles().ok_or(Fault::Unsupported)?.wrapping_sub(start);
+        /* the counter wraps, and may be reset part way through, which mustn't panic */
+        Ok(Throughput { bytes: len, cycles, retries: self.stats().retries.wrapping_sub(retries) })
+    }
+}
+
+/* return the byte at the given position in the test pattern */
+fn pattern(index: usize) -> u8
+{
+    match index % LINE_LEN
+    {
+        pos if pos == LINE_LEN - 1 => b'\n',
+        _ => FIRST_CHAR + (index % CHARS) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_pattern()
+    {
+        let line: Vec<u8> = (0..LINE_LEN).map(pattern).collect();
+        assert_eq!(&line[..4], b"!\"#$");
+        assert_eq!(line[LINE_LEN - 1], b'\n');
+        assert!(line[..LINE_LEN - 1].iter().all(|b| b.is_ascii_graphic()));
+        assert_eq!(Throughput { bytes: 4, cycles: 10, retries: 0 }.cycles_per_byte(), 2);
+    }
+}
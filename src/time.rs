@@ -0,0 +1,94 @@
+/* Read the host's clocks via the syscall proxy
+ *
+ * These need a frontend that proxies the relevant syscalls, which fesvr doesn't:
+ * it ends the simulation if asked for a syscall it doesn't know.
+ *
+ * host_time() reads the wall clock, which can jump. To measure how long a
+ * simulation run really takes, use an Instant, which reads the host's
+ * monotonic clock:
+ *
+ * let start = Instant::now(&mut htif)?;
+ * run_benchmark();
+ * let taken = start.elapsed(&mut htif)?;
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::ptr::read_volatile;
+use core::time::Duration;
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+use super::syscall::{SYS_GETTIMEOFDAY, SYS_CLOCK_GETTIME};
+
+/* the host's clock that never goes backwards */
+const CLOCK_MONOTONIC: u64 = 1;
+
+/* layout of the host's struct timeval */
+#[repr(C)]
+struct TimeVal
+{
+    secs: i64,
+    usecs: i64
+}
+
+/* layout of the host's struct timespec */
+#[repr(C)]
+struct TimeSpec
+{
+    secs: i64,
+    nsecs: i64
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* return the host's wall-clock time as seconds and microseconds since the Unix epoch */
+    pub fn host_time(&mut self) -> Result<(u64, u64), Fault>
+    {
+        let mut tv = TimeVal { secs: 0, usecs: 0 };
+        let ptr = &mut tv as *mut TimeVal;
+        self.proxy_syscall(SYS_GETTIMEOFDAY, &[ptr as u64, 0])?;
+
+        /* the host wrote into tv behind the compiler's back */
+        let tv = unsafe { read_volatile(ptr) };
+        Ok((tv.secs as u64, tv.usecs as u64))
+    }
+}
+
+/* a moment on the host's monotonic clock. only differences between instants mean anything */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant
+{
+    nanos: u64 /* nanoseconds since some point the host chose */
+}
+
+impl Instant
+{
+    /* read the host's monotonic clock */
+    pub fn now(htif: &mut HTIF<impl HtifTransport>) -> Result<Self, Fault>
+    {
+        let mut ts = TimeSpec { secs: 0, nsecs: 0 };
+        let ptr = &mut ts as *mut TimeSpec;
+        htif.proxy_syscall(SYS_CLOCK_GETTIME, &[CLOCK_MONOTONIC, ptr as u64])?;
+
+        /* the host wrote into ts behind the compiler's back */
+        let ts = unsafe { read_volatile(ptr) };
+        let nanos = (ts.secs as u64).checked_mul(1_000_000_000)
+            .and_then(|nanos| nanos.checked_add(ts.nsecs as u64))
+            .ok_or(Fault::InvalidResponse)?;
+        Ok(Instant { nanos })
+    }
+
+    /* return the time from earlier to this instant, or zero if earlier is in fact later */
+    pub fn duration_since(&self, earlier: Instant) -> Duration
+    {
+        Duration::from_nanos(self.nanos.saturating_sub(earlier.nanos))
+    }
+
+    /* return the time that's passed on the host since this instant */
+    pub fn elapsed(&self, htif: &mut HTIF<impl HtifTransport>) -> Result<Duration, Fault>
+    {
+        Ok(Instant::now(htif)?.duration_since(*self))
+    }
+}
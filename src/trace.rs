@@ -0,0 +1,81 @@
+/* Trace the driver's traffic with the host
+ *
+ * A driver built with_trace() calls the given function with every word it
+ * writes to tohost, including the zeroes that withdraw abandoned requests, and
+ * every reply it reads from fromhost, so that a kernel can mirror HTIF traffic
+ * into its own tracing when debugging protocol problems. Polls that find
+ * fromhost empty aren't traced, as there would be far too many.
+ *
+ * The function is called once the word has passed, outside the lock that
+ * serializes writes to tohost, so it may print through another driver, or the
+ * global console if that isn't what's being traced. It mustn't use the same
+ * driver, which is mid-transaction: it'll typically record the word for later.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::HTIF;
+use super::transport::HtifTransport;
+
+/* a word passing between the driver and the host */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transaction
+{
+    ToHost(u64), /* written to tohost */
+    FromHost(u64) /* read from fromhost */
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* call the given function with every word written to tohost or read from fromhost.
+       call this before using the driver */
+    pub const fn with_trace(mut self, hook: fn(Transaction)) -> Self
+    {
+        self.trace = Some(hook);
+        self
+    }
+
+    /* pass a transaction to the trace hook, if there is one */
+    pub(crate) fn trace(&self, transaction: Transaction)
+    {
+        #[cfg(feature = "txlog")]
+        super::txlog::record(transaction);
+
+        if let Some(hook) = self.trace
+        {
+            hook(transaction);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use core::cell::RefCell;
+    use crate::FromHostReply;
+    use crate::mock::MockHtif;
+
+    std::thread_local!
+    {
+        static TRACED: RefCell<Vec<Transaction>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record(transaction: Transaction)
+    {
+        TRACED.with(|traced| traced.borrow_mut().push(transaction));
+    }
+
+    #[test]
+    fn traced()
+    {
+        let mut htif = HTIF::over(MockHtif::<8>::new()).with_trace(record);
+        let reply = FromHostReply { device: 1, command: 0, payload: 0x100 | b'k' as u64 };
+        htif.transport().push_reply(reply);
+        assert_eq!(htif.read_byte(), Ok(b'k'));
+
+        TRACED.with(|traced| assert_eq!(*traced.borrow(), [Transaction::ToHost(1 << 56), Transaction::FromHost(reply.encode())]));
+    }
+}
@@ -0,0 +1,98 @@
+/* Access to the tohost and fromhost registers
+ *
+ * The driver only ever touches the host through an HtifTransport, so that
+ * everything built on top, from the console to the block device, can run over
+ * something other than the registers in memory, such as a test double.
+ * Mmio, the registers in memory, is the default, and is what HTIF::new() uses.
+ *
+ * A transport only moves words to and from the registers. The driver takes care
+ * of the handshakes, and of fencing memory the host reads or writes around them.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::mmio::MmioReg;
+
+pub trait HtifTransport
+{
+    /* return the contents of tohost */
+    fn read_to_host(&self) -> u64;
+
+    /* replace the contents of tohost */
+    fn write_to_host(&self, val: u64);
+
+    /* return the contents of fromhost */
+    fn read_from_host(&self) -> u64;
+
+    /* replace the contents of fromhost */
+    fn write_from_host(&self, val: u64);
+}
+
+/* tohost and fromhost as 64-bit words in memory, watched by the host.
+   registers is None for the linker's registers, which are looked up on each
+   access as, with the custom-symbols feature, finding them means calling into
+   the executable, which can't be done in a const fn */
+#[derive(Debug, Clone, Copy)]
+pub struct Mmio
+{
+    pub(crate) registers: Option<(MmioReg<u64>, MmioReg<u64>)>
+}
+
+impl Mmio
+{
+    /* the tohost and fromhost registers defined by the linker */
+    pub const fn linker() -> Self
+    {
+        Mmio { registers: None }
+    }
+
+    /* the tohost and fromhost registers at the given addresses. this is unsafe
+       as the addresses must point to the host's registers */
+    pub const unsafe fn at(to_host: *mut u64, from_host: *mut u64) -> Self
+    {
+        Mmio { registers: Some((MmioReg::new(to_host), MmioReg::new(from_host))) }
+    }
+
+    fn tohost_reg(&self) -> MmioReg<u64>
+    {
+        match self.registers
+        {
+            Some((to_host, _)) => to_host,
+            None => unsafe { MmioReg::new(super::symbols::to_host()) }
+        }
+    }
+
+    fn fromhost_reg(&self) -> MmioReg<u64>
+    {
+        match self.registers
+        {
+            Some((_, from_host)) => from_host,
+            None => unsafe { MmioReg::new(super::symbols::from_host()) }
+        }
+    }
+}
+
+impl HtifTransport for Mmio
+{
+    fn read_to_host(&self) -> u64
+    {
+        self.tohost_reg().read()
+    }
+
+    fn write_to_host(&self, val: u64)
+    {
+        self.tohost_reg().write(val)
+    }
+
+    fn read_from_host(&self) -> u64
+    {
+        self.fromhost_reg().read()
+    }
+
+    fn write_from_host(&self, val: u64)
+    {
+        self.fromhost_reg().write(val)
+    }
+}
@@ -0,0 +1,155 @@
+/* Print the state of a hart that trapped
+ *
+ * Kernels save the registers in different places when they take a trap, so
+ * dump_trap() takes them as an array indexed by register number, x0 to x31,
+ * along with what the trap CSRs said, and prints them as, for example:
+ *
+ * trap: load page fault (mcause 0x000000000000000d)
+ *   mepc 0x0000000080001234  mtval 0x0000000000000000
+ *   zero 0x0000000000000000  ra   0x00000000800011f0  sp   0x0000000080020f80  gp   0x0000000080003800
+ *   ...
+ *
+ * For S-mode kernels, pass scause, sepc, and stval in their place.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::fmt;
+use core::mem::size_of;
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+
+/* width of a register printed in hex, including its 0x */
+const REG_WIDTH: usize = 2 + size_of::<usize>() * 2;
+
+/* the top bit of mcause is set for interrupts, and clear for exceptions */
+const INTERRUPT: usize = 1 << (usize::BITS - 1);
+
+/* registers per line of the dump */
+const REGS_PER_LINE: usize = 4;
+
+const ABI_NAMES: [&str; 32] =
+[
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2",
+    "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7",
+    "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6"
+];
+
+/* the CSRs describing a trap */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrapCsrs
+{
+    pub mcause: usize, /* why the trap was taken */
+    pub mepc: usize, /* the address of the instruction trapped on, or to return to */
+    pub mtval: usize /* the faulting address or instruction, for some causes */
+}
+
+impl TrapCsrs
+{
+    /* read the M-mode trap CSRs, which is only possible in M-mode */
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    pub fn read() -> Self
+    {
+        let (mcause, mepc, mtval): (usize, usize, usize);
+        unsafe
+        {
+            core::arch::asm!("csrr {}, mcause", "csrr {}, mepc", "csrr {}, mtval",
+                out(reg) mcause, out(reg) mepc, out(reg) mtval, options(nomem, nostack));
+        }
+        TrapCsrs { mcause, mepc, mtval }
+    }
+
+    /* describe the cause of the trap, or return None if it isn't a standard one */
+    pub fn cause(&self) -> Option<&'static str>
+    {
+        let code = self.mcause & !INTERRUPT;
+        let name = match (self.mcause & INTERRUPT != 0, code)
+        {
+            (true, 1) => "supervisor software interrupt",
+            (true, 3) => "machine software interrupt",
+            (true, 5) => "supervisor timer interrupt",
+            (true, 7) => "machine timer interrupt",
+            (true, 9) => "supervisor external interrupt",
+            (true, 11) => "machine external interrupt",
+            (false, 0) => "instruction address misaligned",
+            (false, 1) => "instruction access fault",
+            (false, 2) => "illegal instruction",
+            (false, 3) => "breakpoint",
+            (false, 4) => "load address misaligned",
+            (false, 5) => "load access fault",
+            (false, 6) => "store address misaligned",
+            (false, 7) => "store access fault",
+            (false, 8) => "environment call from U-mode",
+            (false, 9) => "environment call from S-mode",
+            (false, 11) => "environment call from M-mode",
+            (false, 12) => "instruction page fault",
+            (false, 13) => "load page fault",
+            (false, 15) => "store page fault",
+            _ => return None
+        };
+        Some(name)
+    }
+}
+
+/* a trap's CSRs and registers, formatted as a dump */
+struct TrapDump<'a>
+{
+    csrs: &'a TrapCsrs,
+    regs: &'a [usize; 32]
+}
+
+impl fmt::Display for TrapDump<'_>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        let csrs = self.csrs;
+        writeln!(f, "trap: {} (mcause {:#0w$x})", csrs.cause().unwrap_or("unknown cause"), csrs.mcause, w = REG_WIDTH)?;
+        writeln!(f, "  mepc {:#0w$x}  mtval {:#0w$x}", csrs.mepc, csrs.mtval, w = REG_WIDTH)?;
+
+        for (line, regs) in self.regs.chunks(REGS_PER_LINE).enumerate()
+        {
+            for (index, reg) in regs.iter().enumerate()
+            {
+                write!(f, "  {:<4} {:#0w$x}", ABI_NAMES[line * REGS_PER_LINE + index], reg, w = REG_WIDTH)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* print the cause of a trap, its CSRs, and the registers x0 to x31 as saved on taking it */
+    pub fn dump_trap(&mut self, csrs: &TrapCsrs, regs: &[usize; 32]) -> Result<(), Fault>
+    {
+        self.try_write_fmt(format_args!("{}", TrapDump { csrs, regs }))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn dump_format()
+    {
+        let csrs = TrapCsrs { mcause: 13, mepc: 0x8000_1234, mtval: 0 };
+        let mut regs = [0; 32];
+        regs[1] = 0x8000_11f0;
+        let dump = TrapDump { csrs: &csrs, regs: &regs }.to_string();
+
+        let mut lines = dump.lines();
+        assert_eq!(lines.next(), Some("trap: load page fault (mcause 0x000000000000000d)"));
+        assert_eq!(lines.next(), Some("  mepc 0x0000000080001234  mtval 0x0000000000000000"));
+        assert_eq!(lines.next(), Some("  zero 0x0000000000000000  ra   0x00000000800011f0  sp   0x0000000000000000  gp   0x0000000000000000"));
+        assert_eq!(lines.count(), 7);
+
+        assert_eq!(TrapCsrs { mcause: INTERRUPT | 7, mepc: 0, mtval: 0 }.cause(), Some("machine timer interrupt"));
+        assert_eq!(TrapCsrs { mcause: 10, mepc: 0, mtval: 0 }.cause(), None);
+    }
+}
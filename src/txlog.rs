@@ -0,0 +1,194 @@
+/* Remember the most recent traffic with the host, for post-mortem debugging
+ *
+ * Enabled by the txlog feature. Every transaction passed to the trace hook, from
+ * every driver on every hart, is also recorded in a static ring holding the last
+ * ENTRIES of them, whether or not the driver has a hook. When something goes
+ * wrong mid-transaction, dump() prints what led up to it. The panic-handler
+ * feature's panic handler does this itself.
+ *
+ * Recording and snapshots never wait: a transaction that arrives while another
+ * hart is recording one is counted as missed instead, as is a snapshot taken
+ * mid-record, which comes back empty.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use super::{HTIF, Fault};
+use super::lock::SpinLock;
+use super::protocol::FromHostReply;
+use super::trace::Transaction;
+use super::transport::HtifTransport;
+
+/* the number of transactions remembered */
+pub const ENTRIES: usize = 64;
+
+/* the last ENTRIES transactions, oldest first */
+#[derive(Debug, Clone, Copy)]
+pub struct Snapshot
+{
+    entries: [Transaction; ENTRIES],
+    len: usize,
+    missed: usize /* transactions that couldn't be recorded */
+}
+
+struct Log
+{
+    entries: [Transaction; ENTRIES],
+    next: usize, /* where the next transaction goes */
+    len: usize
+}
+
+static LOG: SpinLock<Log> = SpinLock::new(Log
+{
+    entries: [Transaction::ToHost(0); ENTRIES],
+    next: 0,
+    len: 0
+});
+
+static MISSED: AtomicUsize = AtomicUsize::new(0);
+
+/* add a transaction to the log */
+pub(crate) fn record(transaction: Transaction)
+{
+    match LOG.try_lock()
+    {
+        Some(mut log) =>
+        {
+            let next = log.next;
+            log.entries[next] = transaction;
+            log.next = (next + 1) % ENTRIES;
+            log.len = (log.len + 1).min(ENTRIES);
+        },
+
+        /* whoever holds the lock is mid-record, and will be done in moments. but
+           don't wait for them, as it might be a panic handler that interrupted them */
+        None => { MISSED.fetch_add(1, Ordering::Relaxed); }
+    }
+}
+
+/* copy the log as it stands, such as before recovering from a mid-transaction failure,
+   which adds to the log. like record(), this never waits: if the log is mid-record,
+   possibly by the code a panic interrupted, the snapshot is empty and counted as missed */
+pub fn snapshot() -> Snapshot
+{
+    match LOG.try_lock()
+    {
+        Some(log) => log.snapshot(MISSED.load(Ordering::Relaxed)),
+        None => Snapshot
+        {
+            entries: [Transaction::ToHost(0); ENTRIES],
+            len: 0,
+            missed: MISSED.fetch_add(1, Ordering::Relaxed).wrapping_add(1)
+        }
+    }
+}
+
+impl Log
+{
+    /* copy the transactions out of the ring, oldest first */
+    fn snapshot(&self, missed: usize) -> Snapshot
+    {
+        let mut entries = [Transaction::ToHost(0); ENTRIES];
+        let oldest = (self.next + ENTRIES - self.len) % ENTRIES;
+        for (index, entry) in entries.iter_mut().take(self.len).enumerate()
+        {
+            *entry = self.entries[(oldest + index) % ENTRIES];
+        }
+        Snapshot { entries, len: self.len, missed }
+    }
+}
+
+/* print the log to the given console */
+pub fn dump(htif: &mut HTIF<impl HtifTransport>) -> Result<(), Fault>
+{
+    snapshot().dump(htif)
+}
+
+impl Snapshot
+{
+    /* return the transactions, oldest first */
+    pub fn transactions(&self) -> &[Transaction]
+    {
+        &self.entries[..self.len]
+    }
+
+    /* print the transactions, oldest first, to the given console */
+    pub fn dump(&self, htif: &mut HTIF<impl HtifTransport>) -> Result<(), Fault>
+    {
+        htif.try_write_fmt(format_args!("{}", self))
+    }
+
+    fn write(&self, out: &mut impl Write) -> core::fmt::Result
+    {
+        writeln!(out, "last {} HTIF transactions, oldest first:", self.len)?;
+        for transaction in self.transactions()
+        {
+            let (direction, word) = match transaction
+            {
+                Transaction::ToHost(word) => ("tohost  ", *word),
+                Transaction::FromHost(word) => ("fromhost", *word)
+            };
+            let fields = FromHostReply::decode(word);
+            writeln!(out, "  {} {:#018x} device {} command {} payload {:#x}",
+                direction, word, fields.device, fields.command, fields.payload)?;
+        }
+
+        match self.missed
+        {
+            0 => Ok(()),
+            missed => writeln!(out, "  ({} more not recorded)", missed)
+        }
+    }
+}
+
+/* print the transactions as dump() does, such as into a log of one's own */
+impl core::fmt::Display for Snapshot
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result
+    {
+        self.write(f)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn snapshot_order()
+    {
+        let mut snap = Snapshot { entries: [Transaction::ToHost(0); ENTRIES], len: 2, missed: 0 };
+        snap.entries[0] = Transaction::ToHost(0x0101_0000_0000_0061);
+        snap.entries[1] = Transaction::FromHost(0x0100_0000_0000_0179);
+
+        let mut out = String::new();
+        snap.write(&mut out).unwrap();
+        assert_eq!(out, "last 2 HTIF transactions, oldest first:\n\
+            \x20 tohost   0x0101000000000061 device 1 command 1 payload 0x61\n\
+            \x20 fromhost 0x0100000000000179 device 1 command 0 payload 0x179\n");
+    }
+
+    #[test]
+    fn snapshot_wrapped()
+    {
+        /* ENTRIES + 3 transactions have been recorded, so the oldest surviving
+           one is the fourth, which sits just after the newest */
+        let mut log = Log { entries: [Transaction::ToHost(0); ENTRIES], next: 3, len: ENTRIES };
+        for (index, entry) in log.entries.iter_mut().enumerate()
+        {
+            *entry = Transaction::ToHost(match index < 3 { true => (index + ENTRIES) as u64, false => index as u64 });
+        }
+
+        let snap = log.snapshot(0);
+        assert_eq!(snap.transactions().len(), ENTRIES);
+        for (index, transaction) in snap.transactions().iter().enumerate()
+        {
+            assert_eq!(*transaction, Transaction::ToHost(index as u64 + 3));
+        }
+    }
+}
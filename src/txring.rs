@@ -0,0 +1,184 @@
+/* Deferred output to the host console
+ *
+ * Each byte sent to the host console costs a handshake, and send_byte() waits
+ * for the host to take it, so logging runs at the speed of the simulator's
+ * console. A TxRing owns the driver and queues output in a ring instead, so that
+ * send_byte() and write_str() return straight away, and the handshakes happen
+ * when the kernel calls pump() from its idle loop, or flush() when it must
+ * be sure output has gone, such as before exiting.
+ *
+ * let mut console = TxRing::<256>::new(HTIF::new()?);
+ * console.write_str("booting\n")?;
+ * while idle { console.pump()?; }
+ *
+ * Newline translation is applied as bytes are queued, so the ring holds what
+ * will be sent. When the ring is full, the oldest byte is sent, waiting on the
+ * host, to make room for the next.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault, newline, COMMAND_WRITE_CHAR};
+use super::protocol::encode_command;
+use super::transport::{HtifTransport, Mmio};
+
+pub struct TxRing<const N: usize, T: HtifTransport = Mmio>
+{
+    htif: HTIF<T>,
+    ring: [u8; N],
+    head: usize, /* index of the oldest byte waiting to be sent */
+    len: usize /* number of bytes waiting to be sent */
+}
+
+impl<const N: usize, T: HtifTransport> TxRing<N, T>
+{
+    /* queue up to N bytes of output for the given driver. this is const so that
+       the ring can be placed in a static with HtifBuilder::build_static() */
+    pub const fn new(htif: HTIF<T>) -> Self
+    {
+        TxRing { htif, ring: [0; N], head: 0, len: 0 }
+    }
+
+    /* return the driver, such as to read from the console */
+    pub fn htif(&mut self) -> &mut HTIF<T>
+    {
+        &mut self.htif
+    }
+
+    /* send everything queued, and return the driver. output is lost if the host fails to take it */
+    pub fn into_inner(mut self) -> HTIF<T>
+    {
+        let _ = self.flush();
+        self.htif
+    }
+
+    /* queue a byte for the host console */
+    pub fn send_byte(&mut self, byte: u8) -> Result<(), Fault>
+    {
+        self.write(&[byte])
+    }
+
+    /* queue a string for the host console */
+    pub fn write_str(&mut self, s: &str) -> Result<(), Fault>
+    {
+        self.write(s.as_bytes())
+    }
+
+    /* queue bytes for the host console, making room by sending the oldest when the
+       ring is full. on failure, bytes from the failed one onwards aren't queued */
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), Fault>
+    {
+        /* a zero-sized ring can't hold anything, so send straight through */
+        if N == 0
+        {
+            return self.htif.send_bytes(bytes);
+        }
+
+        for byte in bytes
+        {
+            let wire = newline::translate_output(byte, self.htif.newlines);
+            while N - self.len < wire.len()
+            {
+                self.send_oldest()?;
+            }
+
+            for b in wire
+            {
+                self.ring[(self.head + self.len) % N] = *b;
+                self.len += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /* send queued bytes for as long as the host is ready for them, without
+       waiting on it. returns the number of bytes sent */
+    pub fn pump(&mut self) -> Result<usize, Fault>
+    {
+        let mut sent = 0;
+        while self.len > 0 && self.htif.read_to_host() == 0
+        {
+            self.send_oldest()?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    /* send everything queued, and wait for the host to take it. on failure,
+       what wasn't sent remains queued, to be tried again later */
+    pub fn flush(&mut self) -> Result<(), Fault>
+    {
+        while self.len > 0
+        {
+            self.send_oldest()?;
+        }
+        self.htif.wait_for_consumption()
+    }
+
+    /* return the number of bytes waiting to be sent */
+    pub fn queued(&self) -> usize
+    {
+        self.len
+    }
+
+    /* hand the oldest byte to the host, waiting for tohost to be free if need be */
+    fn send_oldest(&mut self) -> Result<(), Fault>
+    {
+        self.htif.write_to_host(encode_command(self.htif.console, COMMAND_WRITE_CHAR as u8, self.ring[self.head] as u64))?;
+        self.htif.count(|stats| stats.bytes_sent = stats.bytes_sent.wrapping_add(1));
+
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Ok(())
+    }
+}
+
+impl<const N: usize, T: HtifTransport> core::fmt::Write for TxRing<N, T>
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result
+    {
+        self.write(s.as_bytes()).map_err(|_| core::fmt::Error)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::mock::MockHtif;
+    use crate::newline::Newlines;
+
+    const CONSOLE_WRITE: u64 = encode_command(1, COMMAND_WRITE_CHAR as u8, 0);
+
+    #[test]
+    fn deferred_output()
+    {
+        let htif = HTIF::over(MockHtif::<8>::new()).with_newlines(Newlines::TERMINAL).with_timeout(10);
+        let mut ring = TxRing::<4, _>::new(htif);
+
+        /* nothing goes to the host until pumped */
+        assert_eq!(ring.write_str("a\n"), Ok(()));
+        assert_eq!(ring.queued(), 3);
+        assert_eq!(ring.htif().transport().pop_sent(), None);
+
+        /* a busy host takes nothing, and the request being made waits in tohost */
+        ring.htif().transport().set_stalled(true);
+        assert_eq!(ring.pump(), Ok(1));
+        assert_eq!(ring.pump(), Ok(0));
+        ring.htif().transport().set_stalled(false);
+        assert_eq!(ring.pump(), Ok(2));
+
+        /* filling the ring sends the oldest byte to make room */
+        assert_eq!(ring.write_str("bcdef"), Ok(()));
+        assert_eq!(ring.queued(), 4);
+        assert_eq!(ring.flush(), Ok(()));
+
+        let sent: Vec<u8> = core::iter::from_fn(|| ring.htif().transport().pop_sent())
+            .map(|request| { assert_eq!(request & !0xff, CONSOLE_WRITE); request as u8 })
+            .collect();
+        assert_eq!(sent, b"a\r\nbcdef");
+    }
+}
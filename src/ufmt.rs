@@ -0,0 +1,34 @@
+/* ufmt output to the host console
+ *
+ * Enabled by the ufmt feature. uwrite!() and uwriteln!() can then print to
+ * the console, either directly or through a BufferedWriter.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use ufmt_write::uWrite;
+use super::{HTIF, Fault};
+use super::buffered::BufferedWriter;
+use super::transport::HtifTransport;
+
+impl<T: HtifTransport> uWrite for HTIF<T>
+{
+    type Error = Fault;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Fault>
+    {
+        self.send_bytes(s.as_bytes())
+    }
+}
+
+impl<const N: usize, T: HtifTransport> uWrite for BufferedWriter<'_, N, T>
+{
+    type Error = Fault;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Fault>
+    {
+        self.write(s.as_bytes())
+    }
+}
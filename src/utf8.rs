@@ -0,0 +1,117 @@
+/* UTF-8 characters to and from the host console
+ *
+ * The console carries bytes, so a character outside ASCII arrives as a sequence
+ * of two to four of them. read_char() gathers a whole sequence and decodes it,
+ * and write_char() sends one, so that text handled a character at a time
+ * isn't mangled. Malformed input reads as U+FFFD, the replacement character:
+ * a byte that cuts a sequence short isn't lost, but is read next.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::transport::HtifTransport;
+
+/* return the length of the sequence started by the given byte, or None if it can't start one */
+fn sequence_len(lead: u8) -> Option<usize>
+{
+    match lead
+    {
+        0x00..=0x7f => Some(1),
+        0xc2..=0xdf => Some(2),
+        0xe0..=0xef => Some(3),
+        0xf0..=0xf4 => Some(4),
+        _ => None
+    }
+}
+
+fn is_continuation(byte: u8) -> bool
+{
+    byte & 0xc0 == 0x80
+}
+
+/* decode a complete sequence, rejecting overlong encodings and surrogates */
+fn decode(sequence: &[u8]) -> char
+{
+    match core::str::from_utf8(sequence)
+    {
+        Ok(s) => s.chars().next().unwrap_or(char::REPLACEMENT_CHARACTER),
+        Err(_) => char::REPLACEMENT_CHARACTER
+    }
+}
+
+impl<T: HtifTransport> HTIF<T>
+{
+    /* block until a whole character arrives from the host console, and return it.
+       the character is echoed once complete, if echo is on */
+    pub fn read_char(&mut self) -> Result<char, Fault>
+    {
+        let mut sequence = [0; 4];
+        sequence[0] = self.take_byte()?;
+
+        let c = match sequence_len(sequence[0])
+        {
+            Some(len) =>
+            {
+                let mut complete = true;
+                for slot in &mut sequence[1..len]
+                {
+                    let byte = self.take_byte()?;
+                    if !is_continuation(byte)
+                    {
+                        /* this belongs to whatever comes next */
+                        self.held = Some(byte);
+                        complete = false;
+                        break;
+                    }
+                    *slot = byte;
+                }
+
+                match complete
+                {
+                    true => decode(&sequence[..len]),
+                    false => char::REPLACEMENT_CHARACTER
+                }
+            },
+            None => char::REPLACEMENT_CHARACTER
+        };
+
+        let mut echo = [0; 4];
+        self.echo_bytes(c.encode_utf8(&mut echo).as_bytes())?;
+        Ok(c)
+    }
+
+    /* write a character to the host console as UTF-8. see send_byte() */
+    pub fn write_char(&self, c: char) -> Result<(), Fault>
+    {
+        let mut sequence = [0; 4];
+        self.send_bytes(c.encode_utf8(&mut sequence).as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::FromHostReply;
+    use crate::mock::MockHtif;
+
+    #[test]
+    fn characters()
+    {
+        let mut htif = HTIF::over(MockHtif::<16>::new());
+        for byte in "é€".bytes().chain(*b"\xe2A\xc0")
+        {
+            htif.transport().push_reply(FromHostReply { device: 1, command: 0, payload: 0x100 | byte as u64 });
+        }
+
+        assert_eq!(htif.read_char(), Ok('é'));
+        assert_eq!(htif.read_char(), Ok('€'));
+        assert_eq!(htif.read_char(), Ok(char::REPLACEMENT_CHARACTER));
+        assert_eq!(htif.read_char(), Ok('A'));
+        assert_eq!(htif.read_char(), Ok(char::REPLACEMENT_CHARACTER));
+        assert_eq!(decode(b"\xed\xa0\x80"), char::REPLACEMENT_CHARACTER);
+    }
+}
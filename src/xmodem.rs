@@ -0,0 +1,384 @@
+/* XMODEM file transfers over the host console
+ *
+ * So that a host script driving Spike's stdin can push a binary or data file
+ * into a running system, or pull one out, with tools such as sx and rx:
+ *
+ * let len = Xmodem::new(&mut htif).receive(&mut buf)?;
+ * Xmodem::new(&mut htif).send(&core_dump)?;
+ *
+ * Blocks are checked with CRC-16 where the other end supports it, falling
+ * back to the original arithmetic checksum where it doesn't. 1K blocks, as
+ * sent by XMODEM-1K, are accepted. YMODEM's batch header isn't understood.
+ * XMODEM has no notion of length, so the last block is padded with SUB bytes,
+ * which are received along with the rest.
+ *
+ * Bytes go to and from the host untouched: the driver's newline translation
+ * and echo settings are ignored.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See README and LICENSE for usage and copying.
+ */
+
+use super::{HTIF, Fault};
+use super::transport::{HtifTransport, Mmio};
+
+const SOH: u8 = 0x01; /* starts a 128-byte block */
+const STX: u8 = 0x02; /* starts a 1024-byte block */
+const EOT: u8 = 0x04; /* ends the transfer */
+const ACK: u8 = 0x06; /* block received */
+const NAK: u8 = 0x15; /* block not received, or start with checksums */
+const CAN: u8 = 0x18; /* cancel the transfer */
+const CRC: u8 = b'C'; /* start with CRCs */
+const SUB: u8 = 0x1a; /* pads the last block */
+
+const BLOCK_LEN: usize = 128;
+const BLOCK_1K_LEN: usize = 1024;
+
+/* how many times a block, or the request to start, is tried before giving up */
+const MAX_RETRIES: usize = 10;
+
+/* requests for CRCs sent before falling back to checksums */
+const CRC_TRIES: usize = 3;
+
+/* polls of the console waited for each byte from the other end, by default */
+pub const DEFAULT_POLLS: u64 = 10_000_000;
+
+/* how each block is checked */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Check
+{
+    Checksum,
+    Crc
+}
+
+/* return the arithmetic checksum of the given bytes */
+fn checksum(data: &[u8]) -> u8
+{
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/* return the CRC-16 of the given bytes, as XMODEM calculates it */
+fn crc16(data: &[u8]) -> u16
+{
+    data.iter().fold(0, |crc, byte|
+    {
+        (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| match crc & 0x8000
+        {
+            0 => crc << 1,
+            _ => (crc << 1) ^ 0x1021
+        })
+    })
+}
+
+pub struct Xmodem<'a, T = Mmio>
+{
+    htif: &'a mut HTIF<T>,
+    polls: u64 /* polls to wait for each byte from the other end */
+}
+
+impl<'a, T: HtifTransport> Xmodem<'a, T>
+{
+    /* transfer files over the given driver's console */
+    pub fn new(htif: &'a mut HTIF<T>) -> Self
+    {
+        Xmodem { htif, polls: DEFAULT_POLLS }
+    }
+
+    /* wait up to the given number of polls for each byte from the other end, rather than DEFAULT_POLLS */
+    pub fn with_timeout(mut self, polls: u64) -> Self
+    {
+        self.polls = polls;
+        self
+    }
+
+    /* receive a file into buf, and return its length including the padding in its last
+       block. fails with OutOfRange if the file doesn't fit, Timeout if the sender goes
+       quiet or its blocks keep failing, and InvalidResponse if it cancels the transfer */
+    pub fn receive(&mut self, buf: &mut [u8]) -> Result<usize, Fault>
+    {
+        let mut block = [0; BLOCK_1K_LEN];
+        let mut expected: u8 = 1;
+        let mut len = 0;
+        let mut retries = 0;
+
+        /* ask for CRCs, then checksums, until the sender starts */
+        let mut check = Check::Crc;
+        let mut started = false;
+
+        loop
+        {
+            if retries == MAX_RETRIES
+            {
+                self.cancel()?;
+                return Err(Fault::Timeout);
+            }
+
+            if !started
+            {
+                if retries == CRC_TRIES
+                {
+                    check = Check::Checksum;
+                }
+                self.send_byte(match check { Check::Crc => CRC, Check::Checksum => NAK })?;
+            }
+
+            let block_len = match self.receive_byte()
+            {
+                Ok(SOH) => BLOCK_LEN,
+                Ok(STX) => BLOCK_1K_LEN,
+                Ok(EOT) =>
+                {
+                    self.send_byte(ACK)?;
+                    return Ok(len);
+                },
+                Ok(CAN) => return Err(Fault::InvalidResponse),
+                Ok(_) | Err(Fault::Timeout) =>
+                {
+                    retries += 1;
+                    if started
+                    {
+                        self.purge()?;
+                        self.send_byte(NAK)?;
+                    }
+                    continue;
+                },
+                Err(e) => return Err(e)
+            };
+            started = true;
+
+            match self.receive_block(&mut block[..block_len], check)
+            {
+                Ok(Some(number)) if number == expected =>
+                {
+                    let fit = buf.get_mut(len..len + block_len).ok_or(Fault::OutOfRange);
+                    match fit
+                    {
+                        Ok(dest) => dest.copy_from_slice(&block[..block_len]),
+                        Err(e) =>
+                        {
+                            self.cancel()?;
+                            return Err(e);
+                        }
+                    }
+
+                    len += block_len;
+                    expected = expected.wrapping_add(1);
+                    retries = 0;
+                    self.send_byte(ACK)?;
+                },
+
+                /* the sender missed our ACK for the last block, and sent it again */
+                Ok(Some(number)) if number == expected.wrapping_sub(1) => self.send_byte(ACK)?,
+
+                /* the sender has lost its place */
+                Ok(Some(_)) =>
+                {
+                    self.cancel()?;
+                    return Err(Fault::InvalidResponse);
+                },
+
+                Ok(None) | Err(Fault::Timeout) =>
+                {
+                    retries += 1;
+                    self.purge()?;
+                    self.send_byte(NAK)?;
+                },
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    /* send data as a file, padding the last block with SUB bytes. fails with Timeout
+       if the receiver goes quiet or keeps refusing a block, and InvalidResponse if
+       it cancels the transfer */
+    pub fn send(&mut self, data: &[u8]) -> Result<(), Fault>
+    {
+        /* the receiver starts the transfer, and picks how blocks are checked */
+        let mut retries = 0;
+        let check = loop
+        {
+            match self.receive_byte()
+            {
+                Ok(CRC) => break Check::Crc,
+                Ok(NAK) => break Check::Checksum,
+                Ok(CAN) => return Err(Fault::InvalidResponse),
+                Ok(_) | Err(Fault::Timeout) if retries < MAX_RETRIES => retries += 1,
+                Ok(_) | Err(Fault::Timeout) => return Err(Fault::Timeout),
+                Err(e) => return Err(e)
+            }
+        };
+
+        let mut block = [SUB; BLOCK_LEN];
+        for (index, chunk) in data.chunks(BLOCK_LEN).enumerate()
+        {
+            block[..chunk.len()].copy_from_slice(chunk);
+            block[chunk.len()..].fill(SUB);
+            self.send_block(&block, (index + 1) as u8, check)?;
+        }
+
+        self.send_until_ack(|xmodem| xmodem.send_byte(EOT))
+    }
+
+    /* send a block, retrying until the receiver acknowledges it */
+    fn send_block(&mut self, block: &[u8; BLOCK_LEN], number: u8, check: Check) -> Result<(), Fault>
+    {
+        self.send_until_ack(|xmodem|
+        {
+            xmodem.send_byte(SOH)?;
+            xmodem.send_byte(number)?;
+            xmodem.send_byte(!number)?;
+            for byte in block.iter()
+            {
+                xmodem.send_byte(*byte)?;
+            }
+
+            match check
+            {
+                Check::Checksum => xmodem.send_byte(checksum(block)),
+                Check::Crc =>
+                {
+                    let crc = crc16(block);
+                    xmodem.send_byte((crc >> 8) as u8)?;
+                    xmodem.send_byte(crc as u8)
+                }
+            }
+        })
+    }
+
+    /* call transmit until the receiver answers with an ACK */
+    fn send_until_ack(&mut self, mut transmit: impl FnMut(&mut Self) -> Result<(), Fault>) -> Result<(), Fault>
+    {
+        for _ in 0..MAX_RETRIES
+        {
+            transmit(self)?;
+            match self.receive_byte()
+            {
+                Ok(ACK) => return Ok(()),
+                Ok(CAN) => return Err(Fault::InvalidResponse),
+                Ok(_) | Err(Fault::Timeout) => (),
+                Err(e) => return Err(e)
+            }
+        }
+        Err(Fault::Timeout)
+    }
+
+    /* read the rest of a block after its header byte into block, and return its
+       number, or None if it arrived damaged */
+    fn receive_block(&mut self, block: &mut [u8], check: Check) -> Result<Option<u8>, Fault>
+    {
+        let number = self.receive_byte()?;
+        let inverse = self.receive_byte()?;
+        for byte in block.iter_mut()
+        {
+            *byte = self.receive_byte()?;
+        }
+
+        let intact = match check
+        {
+            Check::Checksum => self.receive_byte()? == checksum(block),
+            Check::Crc =>
+            {
+                let crc = ((self.receive_byte()? as u16) << 8) | self.receive_byte()? as u16;
+                crc == crc16(block)
+            }
+        };
+
+        match intact && number == !inverse
+        {
+            true => Ok(Some(number)),
+            false => Ok(None)
+        }
+    }
+
+    /* throw away what's left of a damaged block, until the sender goes quiet */
+    fn purge(&mut self) -> Result<(), Fault>
+    {
+        loop
+        {
+            match self.receive_byte()
+            {
+                Ok(_) => (),
+                Err(Fault::Timeout) => return Ok(()),
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    /* tell the other end the transfer is off */
+    fn cancel(&mut self) -> Result<(), Fault>
+    {
+        self.send_byte(CAN)?;
+        self.send_byte(CAN)
+    }
+
+    fn send_byte(&mut self, byte: u8) -> Result<(), Fault>
+    {
+        self.htif.send_raw_byte(byte)
+    }
+
+    fn receive_byte(&mut self) -> Result<u8, Fault>
+    {
+        self.htif.receive_raw_byte(Some(self.polls))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::fesvr::Fesvr;
+
+    #[test]
+    fn crc()
+    {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(checksum(&[0xff, 0x02]), 0x01);
+    }
+
+    /* build a block as a sender would, with a CRC */
+    fn block(number: u8, fill: u8) -> Vec<u8>
+    {
+        let data = [fill; BLOCK_LEN];
+        let crc = crc16(&data);
+        let mut block = vec![SOH, number, !number];
+        block.extend_from_slice(&data);
+        block.extend_from_slice(&[(crc >> 8) as u8, crc as u8]);
+        block
+    }
+
+    #[test]
+    fn receive()
+    {
+        let mut htif = HTIF::over(Fesvr::<1024>::new());
+
+        /* the first block is sent twice, as if our ACK were lost */
+        let mut input = block(1, b'a');
+        input.extend(block(1, b'a'));
+        input.extend(block(2, b'b'));
+        input.push(EOT);
+        htif.transport().type_input(&input);
+
+        let mut buf = [0; 2 * BLOCK_LEN];
+        assert_eq!(Xmodem::new(&mut htif).with_timeout(10).receive(&mut buf), Ok(2 * BLOCK_LEN));
+        assert!(buf[..BLOCK_LEN].iter().all(|byte| *byte == b'a'));
+        assert!(buf[BLOCK_LEN..].iter().all(|byte| *byte == b'b'));
+        assert_eq!(&*htif.transport().output(), &[CRC, ACK, ACK, ACK, ACK]);
+    }
+
+    #[test]
+    fn send()
+    {
+        let mut htif = HTIF::over(Fesvr::<1024>::new());
+        htif.transport().type_input(&[CRC, ACK, ACK]);
+        assert_eq!(Xmodem::new(&mut htif).with_timeout(10).send(&[b'a'; 100]), Ok(()));
+
+        let mut expected = block(1, b'a');
+        expected[3 + 100..3 + BLOCK_LEN].fill(SUB);
+        let crc = crc16(&expected[3..3 + BLOCK_LEN]);
+        expected[3 + BLOCK_LEN] = (crc >> 8) as u8;
+        expected[4 + BLOCK_LEN] = crc as u8;
+        expected.push(EOT);
+        assert_eq!(&*htif.transport().output(), &expected[..]);
+    }
+}